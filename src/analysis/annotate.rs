@@ -0,0 +1,228 @@
+use crate::board::history::Move;
+use crate::computer::search::{self, Limits, Score};
+use crate::input::pgn::{self, Pgn};
+use crate::{Board, Team};
+use std::fmt;
+use std::io;
+
+/// Centipawns a score counts for at most, so a missed mate costs about as much as a lost Queen.
+const CAP: i32 = 1000;
+
+/// How much worse than the engine's move a move may be before it is marked, in centipawns.
+const INACCURACY: i32 = 50;
+const MISTAKE: i32 = 100;
+const BLUNDER: i32 = 300;
+
+/// How well one side played a game, next to the engine.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Loss {
+    /// Centipawns lost over all moves.
+    pub total: i64,
+    pub moves: usize,
+    pub inaccuracies: usize,
+    pub mistakes: usize,
+    pub blunders: usize,
+}
+
+impl Loss {
+    /// Average centipawn loss per move.
+    pub fn average(&self) -> f64 {
+        match self.moves {
+            0 => 0.0,
+            moves => self.total as f64 / moves as f64,
+        }
+    }
+
+    /// Counts a move losing `loss` centipawns, and returns how it is marked, if at all.
+    fn add(&mut self, loss: i32) -> Option<&'static str> {
+        self.total += loss as i64;
+        self.moves += 1;
+        if loss >= BLUNDER {
+            self.blunders += 1;
+            Some("??")
+        } else if loss >= MISTAKE {
+            self.mistakes += 1;
+            Some("?")
+        } else if loss >= INACCURACY {
+            self.inaccuracies += 1;
+            Some("?!")
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "average centipawn loss {:.0}, {} inaccuracies, {} mistakes, {} blunders",
+            self.average(),
+            self.inaccuracies,
+            self.mistakes,
+            self.blunders
+        )
+    }
+}
+
+/// A game with the engine's opinion on every move.
+pub struct Annotated {
+    pub pgn: String,
+    /// Loss of White and Black.
+    pub loss: [Loss; 2],
+}
+
+/// What the engine makes of a position: its score for the team to move, in centipawns, and
+/// the line it would play. None when the game is over.
+fn evaluate(board: &Board, limits: &Limits) -> (i32, Option<(Score, Vec<Move>)>) {
+    let analysis = search::analyze(board, limits, 1);
+    match analysis.lines.into_iter().next() {
+        Some(line) => {
+            let cp = match line.score {
+                Score::Centipawns(cp) => cp.clamp(-CAP, CAP),
+                Score::Mate(moves) if moves > 0 => CAP,
+                Score::Mate(_) => -CAP,
+            };
+            (cp, Some((line.score, line.moves)))
+        }
+        None if board.in_check(board.turn_order) => (-CAP, None),
+        None => (0, None),
+    }
+}
+
+/// A score for the team to move as `[%eval]` gives it, from White's side.
+fn eval(score: Score, turn: Team) -> String {
+    let score = match (turn, score) {
+        (Team::White, score) => score,
+        (Team::Black, Score::Centipawns(cp)) => Score::Centipawns(-cp),
+        (Team::Black, Score::Mate(moves)) => Score::Mate(-moves),
+    };
+    match score {
+        Score::Centipawns(cp) => format!("{:.2}", cp as f64 / 100.0),
+        Score::Mate(moves) => format!("#{}", moves),
+    }
+}
+
+/// Replays the game, and writes it back with the evaluation after every move in `[%eval]`
+/// comments. Moves losing ground are marked `?!`, `?` or `??` and followed by the line the
+/// engine prefers.
+pub fn annotate(game: &Pgn, limits: &Limits) -> io::Result<Annotated> {
+    let (start, end) = pgn::replay(game)?;
+    let played = pgn::played(&start, &end)?;
+    let mut positions: Vec<&Board> = played.iter().map(|(before, _)| before).collect();
+    positions.push(&end);
+    let evaluations: Vec<_> = positions
+        .iter()
+        .map(|board| evaluate(board, limits))
+        .collect();
+
+    let mut loss = [Loss::default(), Loss::default()];
+    let mut tokens: Vec<String> = Vec::new();
+    // Black's move needs its number after a comment or variation.
+    let mut interrupted = true;
+    for (n, (before, mov)) in played.iter().enumerate() {
+        let (cp, best) = &evaluations[n];
+        let (reply, after) = &evaluations[n + 1];
+        let side = match before.turn_order {
+            Team::White => 0,
+            Team::Black => 1,
+        };
+        let lost = (cp + reply).max(0);
+        let mark = loss[side].add(lost);
+
+        match before.turn_order {
+            Team::White => tokens.push(format!("{}.", before.fullmove)),
+            Team::Black if interrupted => tokens.push(format!("{}...", before.fullmove)),
+            Team::Black => (),
+        }
+        let san = mov.label.clone().unwrap_or_default();
+        tokens.push(format!("{}{}", san, mark.unwrap_or("")));
+        interrupted = false;
+        if let Some((score, _)) = after {
+            let turn = before.turn_order.not();
+            tokens.push(format!("{{ [%eval {}] }}", eval(*score, turn)));
+            interrupted = true;
+        }
+        if let (Some(_), Some((_, line))) = (mark, best) {
+            let same = |other: &Move| {
+                (other.from, other.to, other.promotion) == (mov.from, mov.to, mov.promotion)
+            };
+            if !line.first().is_some_and(same) {
+                tokens.push(format!("({})", pgn::line(before, line)?));
+                interrupted = true;
+            }
+        }
+    }
+    tokens.push(game.tag("Result").unwrap_or_else(|| "*".to_string()));
+
+    let mut text = String::new();
+    for tag in &game.meta {
+        text.push_str(tag);
+        text.push('\n');
+    }
+    text.push_str("[Annotator \"chess\"]\n\n");
+    text.push_str(&pgn::wrap(&tokens));
+    Ok(Annotated { pgn: text, loss })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> Limits {
+        Limits {
+            depth: Some(2),
+            ..Limits::default()
+        }
+    }
+
+    #[test]
+    fn test_blunder() {
+        let data = "[White \"a\"]\n[Black \"b\"]\n[Result \"1-0\"]\n\n\
+            1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n";
+        let game = &pgn::games(data)[0];
+        let annotated = annotate(game, &limits()).unwrap();
+        let pgn = &annotated.pgn;
+        assert!(pgn.starts_with("[White \"a\"]\n[Black \"b\"]\n[Result \"1-0\"]\n"));
+        assert!(pgn.contains("[Annotator \"chess\"]"));
+        let movetext = pgn.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(movetext.contains("1. e4 { [%eval "), "{}", pgn);
+        // Nf6 allows mate, and the engine would have played something else.
+        assert!(
+            movetext.contains("3... Nf6?? { [%eval #1] } (3... "),
+            "{}",
+            pgn
+        );
+        // No evaluation after mate.
+        assert!(movetext.ends_with(") 4. Qxf7# 1-0"), "{}", pgn);
+
+        let [white, black] = &annotated.loss;
+        assert_eq!(white.moves, 4);
+        assert_eq!(black.moves, 3);
+        assert_eq!(black.blunders, 1);
+        assert!(black.total >= BLUNDER as i64);
+        assert!(black.to_string().ends_with(", 1 blunders"));
+
+        // The annotated game reads back as the same game.
+        let again = &pgn::games(pgn)[0];
+        assert_eq!(pgn::replay(again).unwrap(), pgn::replay(game).unwrap());
+    }
+
+    #[test]
+    fn test_marks() {
+        let mut loss = Loss::default();
+        assert_eq!(loss.add(0), None);
+        assert_eq!(loss.add(60), Some("?!"));
+        assert_eq!(loss.add(100), Some("?"));
+        assert_eq!(loss.add(900), Some("??"));
+        assert_eq!(loss.average(), 265.0);
+        assert_eq!((loss.inaccuracies, loss.mistakes, loss.blunders), (1, 1, 1));
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval(Score::Centipawns(35), Team::White), "0.35");
+        assert_eq!(eval(Score::Centipawns(35), Team::Black), "-0.35");
+        assert_eq!(eval(Score::Mate(2), Team::Black), "#-2");
+    }
+}
@@ -0,0 +1,188 @@
+use crate::computer::search::{self, Analysis, Limits, Score};
+use crate::input::pgn;
+use crate::{Args, Board};
+use serde_json::{json, Value};
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+pub mod annotate;
+
+/// How long to look at each position, from `--depth` or `--time`.
+pub fn limits(args: &Args) -> io::Result<Limits> {
+    if args.depth.is_some() && args.time.is_some() {
+        return Err(io::Error::other(
+            "--depth and --time can't be used together",
+        ));
+    }
+    if args.depth == Some(0) {
+        return Err(io::Error::other("--depth needs at least one ply"));
+    }
+    Ok(Limits {
+        depth: args.depth,
+        movetime: args.time.map(Duration::from_millis),
+        elo: None,
+    })
+}
+
+/// Looks at the position given by `--fen`, or the initial one, as deep as `--depth` or for as
+/// long as `--time` says, and prints the `--multipv` best lines, as JSON with `--json`.
+pub fn run(args: &Args) -> io::Result<()> {
+    let board: Board = match &args.fen {
+        Some(fen) => fen.clone().try_into()?,
+        None => Board::new(),
+    };
+    if args.multipv == 0 {
+        return Err(io::Error::other("--multipv needs at least one line"));
+    }
+    let limits = limits(args)?;
+    let analysis = search::analyze(&board, &limits, args.multipv);
+    match args.json {
+        true => println!("{}", json(&board, &analysis)?),
+        false => print!("{}", text(&board, &analysis)?),
+    }
+    Ok(())
+}
+
+/// Prints the games of a PGN file annotated by the engine, and how each player did to stderr,
+/// so the games can be redirected to a file.
+pub fn annotate(args: &Args, path: &str) -> io::Result<()> {
+    let limits = limits(args)?;
+    for game in pgn::games(&fs::read_to_string(path)?) {
+        let annotated = annotate::annotate(&game, &limits)?;
+        println!("{}", annotated.pgn);
+        for (tag, loss) in ["White", "Black"].iter().zip(&annotated.loss) {
+            let name = game.tag(tag).unwrap_or_else(|| tag.to_string());
+            eprintln!("{} ({}): {}", tag, name, loss);
+        }
+    }
+    Ok(())
+}
+
+/// The analysis for people, one numbered line per move.
+fn text(board: &Board, analysis: &Analysis) -> io::Result<String> {
+    let mut text = format!(
+        "Depth {}, {} nodes in {:.2}s, {} nps\n",
+        analysis.depth,
+        analysis.nodes,
+        analysis.elapsed.as_secs_f64(),
+        analysis.nps()
+    );
+    if board.moves().is_empty() {
+        let end = match board.in_check(board.turn_order) {
+            true => "checkmate",
+            false => "stalemate",
+        };
+        text.push_str(&format!("No legal moves, {}\n", end));
+    }
+    for (n, line) in analysis.lines.iter().enumerate() {
+        let score = match line.score {
+            Score::Mate(moves) if moves > 0 => format!("mate in {}", moves),
+            Score::Mate(moves) => format!("mated in {}", -moves),
+            score => score.to_string(),
+        };
+        text.push_str(&format!(
+            "{:>2}. {:<12} {}\n",
+            n + 1,
+            score,
+            pgn::line(board, &line.moves)?
+        ));
+    }
+    Ok(text)
+}
+
+/// A score as UCI gives it: `{"cp": 35}` or `{"mate": -2}`.
+pub fn score(score: Score) -> Value {
+    match score {
+        Score::Centipawns(cp) => json!({ "cp": cp }),
+        Score::Mate(moves) => json!({ "mate": moves }),
+    }
+}
+
+/// The analysis for programs, with scores as UCI gives them.
+fn json(board: &Board, analysis: &Analysis) -> io::Result<Value> {
+    let lines = analysis
+        .lines
+        .iter()
+        .enumerate()
+        .map(|(n, line)| {
+            Ok(json!({
+                "multipv": n + 1,
+                "score": score(line.score),
+                "pv": line.moves.iter().map(|mov| mov.uci()).collect::<Vec<_>>(),
+                "san": pgn::line(board, &line.moves)?,
+            }))
+        })
+        .collect::<io::Result<Vec<Value>>>()?;
+    Ok(json!({
+        "fen": board.fen(),
+        "depth": analysis.depth,
+        "nodes": analysis.nodes,
+        "time_ms": analysis.elapsed.as_millis() as u64,
+        "nps": analysis.nps(),
+        "lines": lines,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyze(fen: &str, multipv: usize) -> (Board, Analysis) {
+        let board: Board = fen.to_string().try_into().unwrap();
+        let limits = Limits {
+            depth: Some(2),
+            ..Limits::default()
+        };
+        let analysis = search::analyze(&board, &limits, multipv);
+        (board, analysis)
+    }
+
+    #[test]
+    fn test_text() {
+        let (board, analysis) = analyze("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 2);
+        let text = text(&board, &analysis).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Depth 2, "));
+        assert!(lines[0].ends_with(" nps"));
+        assert_eq!(lines[1], " 1. mate in 1    1. Ra8#");
+        assert!(lines[2].starts_with(" 2. +2.00"), "{}", lines[2]);
+    }
+
+    #[test]
+    fn test_json() {
+        let (board, analysis) = analyze("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 2);
+        let json = json(&board, &analysis).unwrap();
+        assert_eq!(json["depth"], 2);
+        assert_eq!(json["lines"][0]["multipv"], 1);
+        assert_eq!(json["lines"][0]["score"], json!({ "mate": 1 }));
+        assert_eq!(json["lines"][0]["pv"], json!(["a1a8"]));
+        assert_eq!(json["lines"][0]["san"], "1. Ra8#");
+        assert_eq!(json["lines"][1]["score"]["cp"], 200);
+        assert!(json["nodes"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_no_moves() {
+        let (board, analysis) = analyze("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3);
+        let text = text(&board, &analysis).unwrap();
+        assert!(text.lines().nth(1).unwrap().starts_with("No legal moves"));
+        assert_eq!(json(&board, &analysis).unwrap()["lines"], json!([]));
+    }
+
+    #[test]
+    fn test_out_of_time() {
+        let board = Board::new();
+        let limits = Limits {
+            movetime: Some(Duration::ZERO),
+            ..Limits::default()
+        };
+        let analysis = search::analyze(&board, &limits, 2);
+        let text = text(&board, &analysis).unwrap();
+        assert!(text.starts_with("Depth 1, "), "{}", text);
+        assert!(!text.contains("No legal moves"));
+        assert_eq!(text.lines().count(), 3);
+    }
+}
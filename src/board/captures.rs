@@ -0,0 +1,76 @@
+use crate::{Piece, Team};
+use serde::{Deserialize, Serialize};
+
+/// Pieces taken off the board during the game, by the team that took them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Captures {
+    white: Vec<Piece>,
+    black: Vec<Piece>,
+}
+
+impl Captures {
+    pub fn push(&mut self, by: Team, piece: Piece) {
+        match by {
+            Team::White => self.white.push(piece),
+            Team::Black => self.black.push(piece),
+        }
+    }
+
+    /// What `team` has taken, cheapest first.
+    pub fn by(&self, team: Team) -> Vec<Piece> {
+        let mut pieces = match team {
+            Team::White => self.white.clone(),
+            Team::Black => self.black.clone(),
+        };
+        pieces.sort_by_key(|piece| piece.value());
+        pieces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::execute::execute;
+    use crate::{Board, Piece, Team};
+    use std::convert::TryInto;
+
+    fn play(board: &mut Board, moves: &[&str]) {
+        for san in moves {
+            execute(board, san.chars()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_captures() {
+        let mut board = Board::new();
+        play(&mut board, &["e4", "d5", "exd5", "Qxd5", "Nc3", "Qxg2"]);
+        assert_eq!(board.captures.by(Team::White), vec![Piece::Pawn]);
+        assert_eq!(
+            board.captures.by(Team::Black),
+            vec![Piece::Pawn, Piece::Pawn]
+        );
+    }
+
+    #[test]
+    fn test_en_passant_and_promotion() {
+        let mut board: Board = "1n2k3/P7/8/3pP3/8/8/8/4K3 w - d6 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        play(&mut board, &["exd6", "Kd7", "axb8=Q"]);
+        assert_eq!(
+            board.captures.by(Team::White),
+            vec![Piece::Pawn, Piece::Knight]
+        );
+        assert!(board.captures.by(Team::Black).is_empty());
+    }
+
+    #[test]
+    fn test_illegal_move_captures_nothing() {
+        let mut board: Board = "4k3/8/8/8/8/8/3q4/4K2R w K - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        assert!(execute(&mut board, "Rh8+".chars()).is_err());
+        assert!(board.captures.by(Team::White).is_empty());
+    }
+}
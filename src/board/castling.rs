@@ -1,15 +1,40 @@
-use crate::Team;
+use crate::{Board, Piece, Sq, Team, SIZE};
+use serde::{Deserialize, Serialize};
+use std::io::{Error, Result};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CastlingAvailability {
     pub long: bool,
     pub short: bool,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// Files the King and its Rooks start from, e, a and h but in Chess960.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Files {
+    pub king: usize,
+    pub long: usize,
+    pub short: usize,
+}
+
+impl Default for Files {
+    fn default() -> Self {
+        Files {
+            king: 4,
+            long: 0,
+            short: 7,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Castling {
     pub white: CastlingAvailability,
     pub black: CastlingAvailability,
+    #[serde(default)]
+    pub files: Files,
+    /// Chess960 rules, where castling is written as the King taking its own Rook, e.g. e1h1.
+    #[serde(default)]
+    pub chess960: bool,
 }
 
 impl From<&str> for Castling {
@@ -23,6 +48,8 @@ impl From<&str> for Castling {
                 long: false,
                 short: false,
             },
+            files: Files::default(),
+            chess960: false,
         };
         for ch in item.chars() {
             match ch {
@@ -43,32 +70,134 @@ impl Castling {
         Castling {
             white: CastlingAvailability::new(),
             black: CastlingAvailability::new(),
+            files: Files::default(),
+            chess960: false,
         }
     }
+
+    /// Reads the castling field of a FEN for the pieces on `board`, in X-FEN or Shredder-FEN.
+    /// K and Q stand for the outermost Rook on either side of the King, a file letter for the
+    /// Rook on that file. Any Rook but those on a and h, or a King off e, means Chess960.
+    pub fn parse(field: &str, board: &Board) -> Result<Self> {
+        let mut castling = Castling::from("-");
+        for ch in field.chars() {
+            let team = match ch {
+                '-' => break,
+                'A'..='Z' => Team::White,
+                _ => Team::Black,
+            };
+            let rank = match team {
+                Team::White => 0,
+                Team::Black => SIZE - 1,
+            };
+            let on_rank = |piece: Piece| {
+                (0..SIZE).filter(move |file| {
+                    board
+                        .find(Sq::new(rank, *file), Some(&team), Some(piece))
+                        .is_some()
+                })
+            };
+            let king = on_rank(Piece::King).next();
+            let rook = match (ch.to_ascii_lowercase(), king) {
+                ('k', Some(king)) => on_rank(Piece::Rook).rfind(|file| *file > king),
+                ('q', Some(king)) => on_rank(Piece::Rook).find(|file| *file < king),
+                ('k', None) => Some(7),
+                ('q', None) => Some(0),
+                (letter @ 'a'..='h', _) => {
+                    castling.chess960 = true;
+                    Some(letter as usize - 'a' as usize)
+                }
+                _ => {
+                    let msg = format!("invalid FEN castling availability {}", field);
+                    return Err(Error::other(msg));
+                }
+            };
+            // Rights without their Rook are kept, castling with them fails.
+            let king = king.unwrap_or(castling.files.king);
+            let rook = rook.unwrap_or(match ch.to_ascii_lowercase() {
+                'k' => 7,
+                _ => 0,
+            });
+            let availability = match team {
+                Team::White => &mut castling.white,
+                Team::Black => &mut castling.black,
+            };
+            castling.files.king = king;
+            match rook > king {
+                true => {
+                    availability.short = true;
+                    castling.files.short = rook;
+                }
+                false => {
+                    availability.long = true;
+                    castling.files.long = rook;
+                }
+            }
+        }
+        if castling.files != Files::default() {
+            castling.chess960 = true;
+        }
+        Ok(castling)
+    }
     pub fn revoke(&mut self, team: Team) {
         match team {
             Team::White => self.white.revoke(),
             Team::Black => self.black.revoke(),
         }
     }
+    pub fn get(&self, team: Team) -> &CastlingAvailability {
+        match team {
+            Team::White => &self.white,
+            Team::Black => &self.black,
+        }
+    }
+    /// Revokes any availability tied to a King or Rook on its initial square.
+    pub fn touch(&mut self, sq: Sq) {
+        let files = self.files;
+        let availability = match sq.digit {
+            0 => &mut self.white,
+            7 => &mut self.black,
+            _ => return,
+        };
+        if sq.letter == files.king {
+            availability.revoke();
+        } else if sq.letter == files.long {
+            availability.long = false;
+        } else if sq.letter == files.short {
+            availability.short = false;
+        }
+    }
     pub fn fen(&self) -> String {
+        self.fen_with(&|_, _| true)
+    }
+    /// The castling field of an X-FEN: a right is written with the file of its Rook when
+    /// `outermost(team, file)` says another Rook stands further out on that side.
+    pub fn fen_with(&self, outermost: &dyn Fn(Team, usize) -> bool) -> String {
         let mut res = String::new();
-        if !self.white.short && !self.white.long && !self.black.short && !self.black.long {
-            res.push('-');
-        } else {
-            if self.white.short {
-                res.push('K');
-            }
-            if self.white.long {
-                res.push('Q');
-            }
-            if self.black.short {
-                res.push('k');
+        let rights = [
+            (Team::White, self.white.short, self.files.short, 'K'),
+            (Team::White, self.white.long, self.files.long, 'Q'),
+            (Team::Black, self.black.short, self.files.short, 'k'),
+            (Team::Black, self.black.long, self.files.long, 'q'),
+        ];
+        for (team, available, file, letter) in rights {
+            if !available {
+                continue;
             }
-            if self.black.long {
-                res.push('q');
+            match self.chess960 && !outermost(team, file) {
+                true => {
+                    let letter = (b'a' + file as u8) as char;
+                    res.push(match team {
+                        Team::White => letter.to_ascii_uppercase(),
+                        Team::Black => letter,
+                    });
+                }
+                false => res.push(letter),
             }
         }
+        if res.is_empty() {
+            res.push('-');
+        }
         res
     }
 }
@@ -89,6 +218,7 @@ impl CastlingAvailability {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::convert::TryInto;
     #[test]
     fn test_initial_state() {
         let castling = Castling::new();
@@ -102,6 +232,31 @@ mod tests {
         assert_eq!(castling.fen(), "-");
     }
     #[test]
+    fn test_touch_rook() {
+        let mut castling = Castling::new();
+        castling.touch(Sq::new(0, 7));
+        castling.touch(Sq::new(7, 0));
+        assert_eq!(castling.fen(), "Qk");
+    }
+    #[test]
+    fn test_x_fen() {
+        // The long Rook is on c1, with another one further out on a1.
+        let board: crate::Board = "4k3/8/8/8/8/8/8/R1R3K1 w C - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        assert!(board.castling.chess960);
+        assert_eq!(board.castling.files.long, 2);
+        assert_eq!(board.fen(), "4k3/8/8/8/8/8/8/R1R3K1 w C - 0 1");
+        let board: crate::Board = "4k3/8/8/8/8/8/8/R5KR w KQ - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        assert_eq!(board.castling.files.king, 6);
+        assert_eq!(board.fen(), "4k3/8/8/8/8/8/8/R5KR w KQ - 0 1");
+        assert!(Castling::parse("KX", &board).is_err());
+    }
+    #[test]
     fn test_from_str() {
         let expected_castling = Castling::new();
         let actual_castling: Castling = "KQkq".into();
@@ -118,6 +273,8 @@ mod tests {
                 long: false,
                 short: false,
             },
+            files: Files::default(),
+            chess960: false,
         };
         let actual_castling: Castling = "-".into();
         assert_eq!(expected_castling, actual_castling);
@@ -0,0 +1,184 @@
+//! Chess960 starting positions, numbered 0 to 959 as in Scharnagl's scheme, where 518 is the
+//! classical setup.
+//! https://en.wikipedia.org/wiki/Fischer_random_chess_numbering_scheme
+use super::castling::Files;
+use crate::{Board, Entity, Piece, Sq, Team, SIZE};
+use rand::Rng;
+use std::io::{Error, Result};
+
+pub const POSITIONS: usize = 960;
+
+/// Where the two Knights go among the five files left after the Bishops and the Queen.
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// The back rank of the position numbered `index`, from the a-file to the h-file.
+fn back_rank(index: usize) -> [Piece; SIZE] {
+    let mut rank = [None; SIZE];
+    let mut n = index;
+    // A Bishop on a light square, b, d, f or h, and one on a dark square.
+    rank[2 * (n % 4) + 1] = Some(Piece::Bishop);
+    n /= 4;
+    rank[2 * (n % 4)] = Some(Piece::Bishop);
+    n /= 4;
+    let empty = |rank: &[Option<Piece>; SIZE]| -> Vec<usize> {
+        (0..SIZE).filter(|file| rank[*file].is_none()).collect()
+    };
+    rank[empty(&rank)[n % 6]] = Some(Piece::Queen);
+    n /= 6;
+    let (first, second) = KNIGHTS[n];
+    let files = empty(&rank);
+    rank[files[first]] = Some(Piece::Knight);
+    rank[files[second]] = Some(Piece::Knight);
+    // The King stands between the Rooks on the three files left.
+    for (file, piece) in empty(&rank)
+        .into_iter()
+        .zip([Piece::Rook, Piece::King, Piece::Rook])
+    {
+        rank[file] = Some(piece);
+    }
+    rank.map(|piece| piece.expect("every file is filled"))
+}
+
+impl Board {
+    /// The Chess960 starting position numbered `index`, both sides mirroring each other.
+    pub fn chess960(index: usize) -> Result<Self> {
+        if index >= POSITIONS {
+            let msg = format!("Chess960 positions go from 0 to {}", POSITIONS - 1);
+            return Err(Error::other(msg));
+        }
+        let pieces = back_rank(index);
+        let mut board = Board::new();
+        for (team, rank) in [(Team::White, 0), (Team::Black, SIZE - 1)] {
+            for (file, piece) in pieces.iter().enumerate() {
+                board.place(Sq::new(rank, file), Entity::new(*piece, team));
+            }
+        }
+        let rooks: Vec<usize> = (0..SIZE).filter(|f| pieces[*f] == Piece::Rook).collect();
+        board.castling.files = Files {
+            king: (0..SIZE).find(|f| pieces[*f] == Piece::King).unwrap_or(4),
+            long: rooks[0],
+            short: rooks[1],
+        };
+        board.castling.chess960 = true;
+        Ok(board)
+    }
+
+    /// A Chess960 starting position picked at random.
+    pub fn chess960_random() -> Self {
+        let index = rand::thread_rng().gen_range(0..POSITIONS);
+        Board::chess960(index).expect("index is in range")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_numbering() {
+        assert_eq!(
+            Board::chess960(518).unwrap().fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert_eq!(
+            Board::chess960(0).unwrap().fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert_eq!(
+            Board::chess960(959).unwrap().fen(),
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1"
+        );
+        assert!(Board::chess960(960).is_err());
+    }
+
+    #[test]
+    fn test_every_position() {
+        let mut seen = Vec::new();
+        for index in 0..POSITIONS {
+            let rank = back_rank(index);
+            let files = |piece| (0..SIZE).filter(move |f| rank[*f] == piece);
+            let bishops: Vec<usize> = files(Piece::Bishop).collect();
+            assert_ne!(bishops[0] % 2, bishops[1] % 2, "{}", index);
+            let rooks: Vec<usize> = files(Piece::Rook).collect();
+            let king = files(Piece::King).next().unwrap();
+            assert!(rooks[0] < king && king < rooks[1], "{}", index);
+            assert!(!seen.contains(&rank), "{}", index);
+            seen.push(rank);
+        }
+    }
+
+    #[test]
+    fn test_fen_round_trip() {
+        let board = Board::chess960(0).unwrap();
+        let parsed: Board = board.fen().try_into().unwrap();
+        assert_eq!(parsed.castling, board.castling);
+        // Shredder-FEN names the Rooks by their files.
+        let shredder: Board = "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        assert_eq!(shredder.castling, board.castling);
+    }
+
+    fn perft(board: &Board, depth: usize) -> usize {
+        let moves = board.moves();
+        if depth == 1 {
+            return moves.len();
+        }
+        moves
+            .iter()
+            .map(|mov| {
+                let mut next = board.clone();
+                next.apply(mov).unwrap();
+                perft(&next, depth - 1)
+            })
+            .sum()
+    }
+
+    /// Move counts from the Chess960 perft suite, where castling through and next to the Rooks
+    /// is what usually goes wrong.
+    #[test]
+    fn test_perft() {
+        for (fen, counts) in [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                [21, 528, 12189],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                [21, 807, 18002],
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                [20, 479, 10471],
+            ),
+            (
+                "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+                [28, 1120, 31058],
+            ),
+        ] {
+            let board: Board = fen.to_string().try_into().unwrap();
+            for (depth, count) in counts.iter().enumerate() {
+                assert_eq!(
+                    perft(&board, depth + 1),
+                    *count,
+                    "{} at depth {}",
+                    fen,
+                    depth + 1
+                );
+            }
+        }
+    }
+}
@@ -1,14 +1,26 @@
 use crate::{Piece, Sq, Team};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Move {
     pub piece: Piece,
     pub from: Sq,
     pub to: Sq,
+    pub promotion: Option<Piece>,
     pub label: Option<String>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+impl Move {
+    /// The move in UCI long algebraic notation, e.g. "e2e4", "e1g1" or "e7e8q".
+    pub fn uci(&self) -> String {
+        let promotion = self
+            .promotion
+            .map_or(String::new(), |piece| piece.to_str().to_lowercase());
+        format!("{}{}{}", self.from, self.to, promotion)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct History {
     white_moves: Vec<Move>,
     black_moves: Vec<Move>,
@@ -41,17 +53,25 @@ impl History {
         vec.last()
     }
 
-    pub fn push(&mut self, team: Team, piece: Piece, from: Sq, to: Sq, label: Option<String>) {
+    pub fn push(
+        &mut self,
+        team: Team,
+        piece: Piece,
+        from: Sq,
+        to: Sq,
+        promotion: Option<Piece>,
+        label: Option<String>,
+    ) {
         let mov = Move {
             piece,
             from,
             to,
+            promotion,
             label,
         };
         self.resolve_type_mut(team).push(mov);
     }
 
-    #[allow(dead_code)]
     pub fn pop(&mut self, team: Team) -> Option<Move> {
         self.resolve_type_mut(team).pop()
     }
@@ -88,6 +108,7 @@ mod tests {
             piece: Piece::Pawn,
             from: Sq::notation("a2")?,
             to: Sq::notation("a4")?,
+            promotion: None,
             label: Some("a4".to_string()),
         };
         assert_eq!(last_move, Some(&expected_mov));
@@ -1,26 +1,33 @@
+use crate::execute::special;
 use crate::place::entity::{Entity, SqEntity};
 use crate::place::sq::Sq;
 use crate::SIZE;
 use crate::{History, Piece, Team};
+use history::Move;
+use serde::{Deserialize, Serialize};
 use state::*;
-use std::io::{Error, ErrorKind, Result};
+use std::io::{Error, Result};
 
 use std::convert::TryFrom;
 
+pub mod captures;
 pub mod castling;
+pub mod chess960;
 pub mod history;
 pub mod king_status;
 pub mod piece;
 pub mod state;
 pub mod team;
+pub mod zobrist;
 
 pub enum SqStatus {
     None,
+    #[allow(dead_code)]
     Blocked,
     Some(Sq),
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Board {
     pub state: BoardState,
     pub turn_order: Team,
@@ -30,6 +37,7 @@ pub struct Board {
     pub castling: castling::Castling,
     pub fullmove: usize,
     pub halfmove: usize,
+    pub captures: captures::Captures,
 }
 
 /// Using FEN
@@ -40,7 +48,7 @@ impl TryFrom<String> for Board {
     fn try_from(item: String) -> Result<Self> {
         let mut board = Board::new();
         board.board = [[None; SIZE]; SIZE];
-        let item: Vec<&str> = item.split_whitespace().into_iter().collect();
+        let item: Vec<&str> = item.split_whitespace().collect();
 
         let expected_len = 6;
         if item.len() != expected_len {
@@ -49,7 +57,7 @@ impl TryFrom<String> for Board {
                 expected_len,
                 item.len()
             );
-            return Err(Error::new(ErrorKind::Other, msg));
+            return Err(Error::other(msg));
         }
 
         // Piece placement.
@@ -57,7 +65,6 @@ impl TryFrom<String> for Board {
 
         for (i, rank) in ranks.enumerate() {
             let mut file: usize = 0;
-            println!("[board/mod]: FEN rank: {}", rank);
             for ch in rank.chars() {
                 match ch {
                     'a'..='z' | 'A'..='Z' => {
@@ -65,7 +72,6 @@ impl TryFrom<String> for Board {
                         let entity: Entity = Entity::from(ch);
                         let target: Sq = Sq::new(7 - i, file);
                         board.place(target, entity);
-                        println!("[board/mod]: FEN Wants to place {:?} at {}", entity, target);
                         file += 1;
                     }
                     '1'..='8' => match ch.to_digit(10) {
@@ -82,20 +88,21 @@ impl TryFrom<String> for Board {
         // Active color.
         board.turn_order = item[1].into();
 
-        // Castling availability.
-        board.castling = item[2].into();
+        // Castling availability, with the files of the Rooks in Chess960.
+        board.castling = castling::Castling::parse(item[2], &board)?;
 
         // En passant target square.
-        board.en_passant_target_square = match Sq::notation(item[3]) {
-            Ok(sq) => Some(sq),
-            Err(_) => None,
-        };
+        board.en_passant_target_square = Sq::notation(item[3]).ok();
 
         // Halfmove clock.
-        // item[4]
+        board.halfmove = item[4]
+            .parse()
+            .map_err(|_| Error::other(format!("invalid FEN halfmove clock {}", item[4])))?;
 
         // Fullmove number.
-        // item[5]
+        board.fullmove = item[5]
+            .parse()
+            .map_err(|_| Error::other(format!("invalid FEN fullmove number {}", item[5])))?;
 
         Ok(board)
     }
@@ -107,7 +114,7 @@ impl Board {
         let b = Team::Black;
         let w = Team::White;
 
-        for (team, rank) in &[(Team::White, 0usize), (Team::Black, (SIZE - 1) as usize)] {
+        for (team, rank) in &[(Team::White, 0usize), (Team::Black, (SIZE - 1))] {
             board[*rank][0] = Some(Entity::new(Piece::Rook, *team));
             board[*rank][1] = Some(Entity::new(Piece::Knight, *team));
             board[*rank][2] = Some(Entity::new(Piece::Bishop, *team));
@@ -118,14 +125,13 @@ impl Board {
             board[*rank][7] = Some(Entity::new(Piece::Rook, *team));
         }
 
-        for n in 0..SIZE {
-            board[1][n] = Some(Entity::new(Piece::Pawn, w));
-            board[6][n] = Some(Entity::new(Piece::Pawn, b));
-        }
+        board[1] = [Some(Entity::new(Piece::Pawn, w)); SIZE];
+        board[6] = [Some(Entity::new(Piece::Pawn, b)); SIZE];
         Board {
             state: BoardState::new(),
             halfmove: 0,
             fullmove: 1,
+            captures: captures::Captures::default(),
             en_passant_target_square: None,
             castling: castling::Castling::new(),
             turn_order: Team::White,
@@ -154,12 +160,14 @@ impl Board {
         let label = other.translate(from, to)?;
         if other.in_check(turn) {
             let msg = format!("illegal move for {}, places yourself in check.", turn);
-            return Err(Error::new(ErrorKind::Other, msg));
+            return Err(Error::other(msg));
         }
 
         match self.find(from, Some(&turn), None) {
             None => (), // Panic?
-            Some(entity) => self.history.push(turn, entity.kind, from, to, Some(label)),
+            Some(entity) => self
+                .history
+                .push(turn, entity.kind, from, to, None, Some(label)),
         }
 
         // Move was alright, register it.
@@ -169,6 +177,8 @@ impl Board {
         self.castling = other.castling;
         self.en_passant_target_square = other.en_passant_target_square;
         self.halfmove = other.halfmove;
+        self.fullmove = other.fullmove;
+        self.captures = other.captures;
 
         #[cfg(test)]
         println!("[board/mod]: {} -> {}", from, to);
@@ -192,7 +202,7 @@ impl Board {
         for sq_entity in entities {
             let sq = sq_entity.sq;
             let piece = sq_entity.entity.kind;
-            let translations = piece.get_translations()(&self, sq, team.not(), piece);
+            let translations = piece.get_translations()(self, sq, team.not(), piece);
             for t in translations {
                 if let Some(_ent) = self.find(t, Some(&team), Some(Piece::King)) {
                     #[cfg(test)]
@@ -273,10 +283,25 @@ impl Board {
                 res.push('/');
             }
         }
+        // X-FEN names a Rook by its file when another one stands further out.
+        let outermost = |team: Team, file: usize| {
+            let rank = match team {
+                Team::White => 0,
+                Team::Black => SIZE - 1,
+            };
+            let beyond = match file > self.castling.files.king {
+                true => file + 1..SIZE,
+                false => 0..file,
+            };
+            !beyond.into_iter().any(|file| {
+                self.find(Sq::new(rank, file), Some(&team), Some(Piece::Rook))
+                    .is_some()
+            })
+        };
         res.push_str(&format!(
             " {} {} ",
             self.turn().abrev().to_lowercase(),
-            self.castling.fen()
+            self.castling.fen_with(&outermost)
         ));
 
         match self.en_passant_target_square {
@@ -317,36 +342,35 @@ impl Board {
             Some(ent) => {
                 if ent.team != self.turn() {
                     let msg = format!("illegal move, (piece: {}, turn: {})", ent.team, self.turn());
-                    return Err(Error::new(ErrorKind::Other, msg));
+                    return Err(Error::other(msg));
                 }
                 ent
             }
             None => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    "illegal move, nothing at from",
-                ));
+                return Err(Error::other("illegal move, nothing at from"));
             }
         };
 
         label.push_str(from_entity.kind.to_str());
 
+        // Moving a King or Rook from, or capturing on, their initial squares forfeits castling.
+        self.castling.touch(from);
+        self.castling.touch(to);
+
         match self.get(to) {
             Some(to_entity) => {
                 if from_entity.team == to_entity.team {
-                    return Err(Error::new(
-                        ErrorKind::Other,
-                        "illegal move, target is friendly",
-                    ));
+                    return Err(Error::other("illegal move, target is friendly"));
                 }
                 if to_entity.kind == Piece::King {
-                    return Err(Error::new(ErrorKind::Other, "illegal move, target is King"));
+                    return Err(Error::other("illegal move, target is King"));
                 }
                 if from_entity.kind == Piece::Pawn {
                     label.push(from.get_file_char());
                 }
-                label.push_str("x");
+                label.push('x');
                 self.halfmove = 0;
+                self.captures.push(from_entity.team, to_entity.kind);
                 self.board[to.digit][to.letter] = self.board[from.digit][from.letter];
                 self.board[from.digit][from.letter] = None;
             }
@@ -370,12 +394,13 @@ impl Board {
                             if from_entity.kind == Piece::Pawn {
                                 label.push(from.get_file_char());
                             }
-                            label.push_str("x");
+                            label.push('x');
+                            self.captures.push(from_entity.team, Piece::Pawn);
                             self.board[clean_up_sq.digit][clean_up_sq.letter] = None
                         }
                         None => {
                             let msg = format!("illegal en passant, no Pawn at {}", clean_up_sq);
-                            return Err(Error::new(ErrorKind::Other, msg));
+                            return Err(Error::other(msg));
                         }
                     }
                 }
@@ -414,14 +439,215 @@ impl Board {
                 println!("[board/mod]: en passant disallowed");
                 self.en_passant_target_square = None;
             }
+        } else {
+            self.en_passant_target_square = None;
         }
 
         Ok(label)
     }
 
+    /// The legal move written in SAN, with or without check marks, or in UCI notation.
+    pub fn find_move(&self, text: &str) -> Result<Move> {
+        let text = text.trim();
+        let san = text.trim_end_matches(['+', '#', '!', '?']);
+        self.legal_moves()
+            .into_iter()
+            .find(|mov| {
+                mov.uci() == text
+                    || mov
+                        .label
+                        .as_deref()
+                        .map(|label| label.trim_end_matches(['+', '#']))
+                        == Some(san)
+            })
+            .ok_or_else(|| Error::other(format!("{} is not a legal move", text)))
+    }
+
+    /// The legal move going where `mov` goes, labeled in SAN, or an error if there is none.
+    pub fn legal(&self, mov: &Move) -> Result<Move> {
+        self.legal_moves()
+            .into_iter()
+            .find(|legal| {
+                (legal.from, legal.to, legal.promotion) == (mov.from, mov.to, mov.promotion)
+            })
+            .ok_or_else(|| Error::other(format!("{} is not a legal move", mov.uci())))
+    }
+
+    /// Every legal move for the team to move, labeled in SAN.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let team = self.turn_order;
+        let mut moves = self.moves();
+        let translations: Vec<(Piece, Sq, Sq)> = moves
+            .iter()
+            .map(|mov| (mov.piece, mov.from, mov.to))
+            .collect();
+        for mov in moves.iter_mut() {
+            let mut label = match self.castles(mov) {
+                Some(true) => "O-O".to_string(),
+                Some(false) => "O-O-O".to_string(),
+                None => self.san_prefix(&translations, mov.piece, mov.from, mov.to),
+            };
+            if let Some(piece) = mov.promotion {
+                label = format!("{}={}", label, piece.to_str());
+            }
+            // Suffix every label with the check or mate it delivers.
+            let mut other = self.clone();
+            if other.apply(mov).is_ok() && other.in_check(team.not()) {
+                label.push(match other.check_mate(team.not()) {
+                    true => '#',
+                    false => '+',
+                });
+            }
+            mov.label = Some(label);
+        }
+        moves
+    }
+
+    /// Every legal move for the team to move, without labels. Cheaper than `legal_moves`, for
+    /// when the moves are only played and not shown.
+    pub fn moves(&self) -> Vec<Move> {
+        let team = self.turn_order;
+        let mut moves = Vec::new();
+        for sq_entity in self.find_by_team(team) {
+            let from = sq_entity.sq;
+            let piece = sq_entity.entity.kind;
+            for to in piece.get_translations()(self, from, team, piece) {
+                if !self.can_translate(from, to) {
+                    continue;
+                }
+                let promotions =
+                    match piece == Piece::Pawn && (to.digit == 0 || to.digit == SIZE - 1) {
+                        true => vec![
+                            Some(Piece::Queen),
+                            Some(Piece::Rook),
+                            Some(Piece::Bishop),
+                            Some(Piece::Knight),
+                        ],
+                        false => vec![None],
+                    };
+                for promotion in promotions {
+                    moves.push(Move {
+                        piece,
+                        from,
+                        to,
+                        promotion,
+                        label: None,
+                    });
+                }
+            }
+        }
+
+        let rank = match team {
+            Team::White => 0,
+            Team::Black => SIZE - 1,
+        };
+        let files = self.castling.files;
+        for (notation, rook, file) in [("O-O", files.short, 6), ("O-O-O", files.long, 2)] {
+            if special::castle(&mut self.clone(), notation).is_ok() {
+                let to = match self.castling.chess960 {
+                    true => rook,
+                    false => file,
+                };
+                moves.push(Move {
+                    piece: Piece::King,
+                    from: Sq::new(rank, files.king),
+                    to: Sq::new(rank, to),
+                    promotion: None,
+                    label: None,
+                });
+            }
+        }
+        moves
+    }
+
+    /// Whether `mov` castles, short or long. Castling moves the King two files, or in Chess960
+    /// onto its own Rook.
+    pub fn castles(&self, mov: &Move) -> Option<bool> {
+        if mov.piece != Piece::King {
+            return None;
+        }
+        let castles = match self.castling.chess960 {
+            true => self
+                .find(mov.to, Some(&self.turn_order), Some(Piece::Rook))
+                .is_some(),
+            false => isize::abs(mov.from.letter as isize - mov.to.letter as isize) == 2,
+        };
+        match castles {
+            true => Some(mov.to.letter > mov.from.letter),
+            false => None,
+        }
+    }
+
+    /// SAN without check indicators, disambiguated against the other legal translations.
+    fn san_prefix(
+        &self,
+        translations: &[(Piece, Sq, Sq)],
+        piece: Piece,
+        from: Sq,
+        to: Sq,
+    ) -> String {
+        let en_passant = piece == Piece::Pawn && from.letter != to.letter;
+        let capture = self.get(to).is_some() || en_passant;
+
+        let mut label = String::from(piece.to_str());
+        if piece == Piece::Pawn {
+            if capture {
+                label.push(from.get_file_char());
+            }
+        } else {
+            let rivals: Vec<Sq> = translations
+                .iter()
+                .filter(|(p, f, t)| *p == piece && *t == to && *f != from)
+                .map(|(_, f, _)| *f)
+                .collect();
+            if !rivals.is_empty() {
+                if rivals.iter().all(|sq| sq.letter != from.letter) {
+                    label.push(from.get_file_char());
+                } else if rivals.iter().all(|sq| sq.digit != from.digit) {
+                    label.push_str(&(from.digit + 1).to_string());
+                } else {
+                    label.push_str(&from.to_string());
+                }
+            }
+        }
+        if capture {
+            label.push('x');
+        }
+        label.push_str(&to.to_string());
+        label
+    }
+
+    /// Plays a move produced by `legal_moves`, recording its label in the History.
+    pub fn apply(&mut self, mov: &Move) -> Result<()> {
+        let team = self.turn_order;
+        if let Some(short) = self.castles(mov) {
+            let notation = match short {
+                true => "O-O",
+                false => "O-O-O",
+            };
+            special::castle(self, notation)?;
+        } else {
+            self.checked_translate(mov.from, mov.to)?;
+            if let Some(piece) = mov.promotion {
+                self.place(mov.to, Entity::new(piece, team));
+            }
+        }
+
+        if let Some(last) = self.history.pop(team) {
+            let label = match (&mov.label, mov.promotion) {
+                (Some(label), _) => Some(label.clone()),
+                (None, Some(piece)) => last.label.map(|l| format!("{}={}", l, piece.to_str())),
+                (None, None) => last.label,
+            };
+            self.history
+                .push(team, last.piece, last.from, last.to, mov.promotion, label);
+        }
+        Ok(())
+    }
+
     pub fn legal_target(&self, from: Sq, to: Sq, team: Team, piece: Piece) -> Option<Sq> {
         if let Some(_entity) = self.find(from, Some(&team), Some(piece)) {
-            let translations = piece.get_translations()(&self, from, team, piece);
+            let translations = piece.get_translations()(self, from, team, piece);
             if translations.contains(&to) && self.can_translate(from, to) {
                 return Some(from);
             }
@@ -519,6 +745,26 @@ mod tests {
         );
     }
     #[test]
+    fn test_find_move() {
+        let board: Board = "r3k3/1P6/8/8/8/8/8/4K2R w K - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            board.find_move("e1g1").unwrap().label.as_deref(),
+            Some("O-O")
+        );
+        assert_eq!(board.find_move("O-O").unwrap().uci(), "e1g1");
+        assert_eq!(board.find_move("bxa8=N").unwrap().uci(), "b7a8n");
+        assert_eq!(
+            board.find_move("b7a8q").unwrap().label.as_deref(),
+            Some("bxa8=Q+")
+        );
+        assert_eq!(board.find_move("Rh8+").unwrap().uci(), "h1h8");
+        assert!(board.find_move("e1e3").is_err());
+        assert!(board.find_move("Qd4").is_err());
+    }
+    #[test]
     fn test_halfclock() {
         let mut board = Board::new();
         assert_eq!(board.halfmove, 0);
@@ -556,7 +802,7 @@ mod tests {
             .unwrap();
         let expected_board = Board::new();
         if board != expected_board {
-            display::present(&board);
+            display::present_with(&board, &Default::default());
             assert_eq!(
                 board.castling, expected_board.castling,
                 "FEN: castling differed"
@@ -631,8 +877,8 @@ mod tests {
     #[test]
     fn test_init_check_mate() {
         let board = Board::new();
-        assert_eq!(board.check_mate(Team::Black), false);
-        assert_eq!(board.check_mate(Team::White), false)
+        assert!(!board.check_mate(Team::Black));
+        assert!(!board.check_mate(Team::White))
     }
     #[test]
     fn test_stalemate() {
@@ -647,25 +893,18 @@ mod tests {
             "Nxa3", "g4", "Rc6", "Ke5", "Re6+", "Kf4", "Re1", "Kf3", "Nb5", "Kf4", "Nd4", "Kg3",
             "Re2", "Kh3", "Re5", "Kh4"
         );
-        assert_eq!(
-            board.stalemate(Team::White),
-            false,
+        assert!(
+            !board.stalemate(Team::White),
             "White should not be in stalemate"
         );
-        assert_eq!(
-            board.stalemate(Team::Black),
-            false,
+        assert!(
+            !board.stalemate(Team::Black),
             "Black should not be in stalemate"
         );
         run!(board, "Re3");
-        assert_eq!(
-            board.stalemate(Team::White),
-            true,
-            "White should be in stalemate"
-        );
-        assert_eq!(
-            board.stalemate(Team::Black),
-            false,
+        assert!(board.stalemate(Team::White), "White should be in stalemate");
+        assert!(
+            !board.stalemate(Team::Black),
             "Black should not be in stalemate"
         );
     }
@@ -677,4 +916,62 @@ mod tests {
         assert_eq!(white_count.len(), 16, "White should own 16 pieces");
         assert_eq!(black_count.len(), 16, "Black should own 16 pieces");
     }
+    #[test]
+    fn test_initial_legal_moves() {
+        let board = Board::new();
+        let moves = board.legal_moves();
+        assert_eq!(moves.len(), 20, "White has 20 moves initially");
+        for mov in moves {
+            let mut other = board.clone();
+            let label = mov.label.unwrap();
+            run!(other, label);
+        }
+    }
+    #[test]
+    fn test_legal_moves_labels() {
+        let board: Board = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        let labels: Vec<String> = board
+            .legal_moves()
+            .into_iter()
+            .filter_map(|mov| mov.label)
+            .collect();
+        assert_eq!(labels.len(), 48);
+        for expected in &[
+            "O-O", "O-O-O", "Qxf6", "Nxf7", "dxe6", "Bxa6", "gxh3", "Qxh3",
+        ] {
+            assert!(labels.contains(&expected.to_string()), "{:?}", labels);
+        }
+    }
+    #[test]
+    fn test_apply_promotion() {
+        let mut board = Board::new();
+        board.clear();
+        board.place(
+            Sq::notation("a7").unwrap(),
+            Entity::new(Piece::Pawn, Team::White),
+        );
+        board.place(
+            Sq::notation("h1").unwrap(),
+            Entity::new(Piece::King, Team::White),
+        );
+        board.place(
+            Sq::notation("h8").unwrap(),
+            Entity::new(Piece::King, Team::Black),
+        );
+        let mov = board
+            .legal_moves()
+            .into_iter()
+            .find(|mov| mov.promotion == Some(Piece::Queen))
+            .unwrap();
+        assert_eq!(mov.label, Some("a8=Q+".to_string()));
+        board.apply(&mov).unwrap();
+        assert_eq!(
+            board.get(Sq::notation("a8").unwrap()),
+            Some(Entity::new(Piece::Queen, Team::White))
+        );
+        assert_eq!(board.history.last(Team::White), Some(&mov));
+    }
 }
@@ -1,9 +1,12 @@
 use crate::execute::{bishop, king, knight, pawn, queen, rook};
 use crate::{Board, OptSq, Sq, Team};
+use serde::{Deserialize, Serialize};
 use std::convert::From;
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+type Locate = dyn Fn(&Board, Sq, OptSq, Team, Piece) -> Option<Sq>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Piece {
     Pawn,
     Knight,
@@ -68,7 +71,7 @@ impl Piece {
             _ => None,
         }
     }
-    pub fn to_str(&self) -> &str {
+    pub fn to_str(self) -> &'static str {
         match self {
             Piece::Rook => "R",
             Piece::Bishop => "B",
@@ -90,7 +93,7 @@ impl Piece {
             Piece::Pawn => &pawn::get_translations,
         }
     }
-    pub fn get_locate(&self) -> &'static (dyn Fn(&Board, Sq, OptSq, Team, Piece) -> Option<Sq>) {
+    pub fn get_locate(&self) -> &'static Locate {
         match self {
             Piece::Rook => &rook::locate,
             Piece::Bishop => &bishop::locate,
@@ -101,7 +104,6 @@ impl Piece {
         }
     }
 
-    #[allow(dead_code)]
     pub fn value(&self) -> usize {
         match self {
             Piece::Rook => 5,
@@ -143,7 +145,7 @@ mod tests {
     }
     #[test]
     fn test_parse_invalid() {
-        assert_eq!(Piece::from_str("Z").is_err(), true);
+        assert!(Piece::from_str("Z").is_err());
     }
     #[test]
     fn test_char_illegal() {
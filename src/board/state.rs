@@ -1,25 +1,34 @@
-use crate::Team;
+use crate::{Board, Piece, Team};
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct BoardState {
     winner: Option<Team>,
     result: GameState,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum GameState {
     Checkmate,
+    Resignation,
+    /// The winner's opponent ran out of time.
+    Timeout,
+    /// The winner's opponent made an illegal move, or none at all.
+    Forfeit,
     Active,
     Draw(DrawState),
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DrawState {
     ThreefoldRepetion,
     FiftyMoveRule,
     Stalemate,
     Agreement,
     InsufficientMatingMaterial,
+    /// A flag fell, but the other side could never have mated.
+    TimeoutVsInsufficientMaterial,
 }
 
 impl BoardState {
@@ -29,4 +38,192 @@ impl BoardState {
             result: GameState::Active,
         }
     }
+
+    pub fn won(winner: Team, result: GameState) -> Self {
+        BoardState {
+            winner: Some(winner),
+            result,
+        }
+    }
+
+    pub fn drawn(reason: DrawState) -> Self {
+        BoardState {
+            winner: None,
+            result: GameState::Draw(reason),
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.result != GameState::Active
+    }
+
+    #[allow(dead_code)]
+    pub fn winner(&self) -> Option<Team> {
+        self.winner
+    }
+
+    /// The result as written in PGN.
+    pub fn score(&self) -> &'static str {
+        match (&self.result, self.winner) {
+            (GameState::Active, _) => "*",
+            (_, Some(Team::White)) => "1-0",
+            (_, Some(Team::Black)) => "0-1",
+            (_, None) => "1/2-1/2",
+        }
+    }
+}
+
+impl fmt::Display for BoardState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match &self.result {
+            GameState::Active => return write!(f, "Game in progress"),
+            GameState::Checkmate => "checkmate",
+            GameState::Resignation => "resignation",
+            GameState::Timeout => "timeout",
+            GameState::Forfeit => "forfeit",
+            GameState::Draw(DrawState::ThreefoldRepetion) => "threefold repetition",
+            GameState::Draw(DrawState::FiftyMoveRule) => "the fifty-move rule",
+            GameState::Draw(DrawState::Stalemate) => "stalemate",
+            GameState::Draw(DrawState::Agreement) => "agreement",
+            GameState::Draw(DrawState::InsufficientMatingMaterial) => "insufficient material",
+            GameState::Draw(DrawState::TimeoutVsInsufficientMaterial) => {
+                "timeout vs insufficient material"
+            }
+        };
+        match self.winner {
+            Some(team) => write!(f, "{} wins by {}, {}", team, reason, self.score()),
+            None => write!(f, "Draw by {}, {}", reason, self.score()),
+        }
+    }
+}
+
+impl Board {
+    /// Ends the game when the team to move has no legal moves or a draw rule applies.
+    /// `previous` are the positions played before this one, for threefold repetition.
+    pub fn adjudicate(&mut self, previous: &[Board]) {
+        let team = self.turn_order;
+        self.state = if self.legal_moves().is_empty() {
+            match self.in_check(team) {
+                true => BoardState::won(team.not(), GameState::Checkmate),
+                false => BoardState::drawn(DrawState::Stalemate),
+            }
+        } else if self.insufficient_material() {
+            BoardState::drawn(DrawState::InsufficientMatingMaterial)
+        } else if self.halfmove >= 100 {
+            BoardState::drawn(DrawState::FiftyMoveRule)
+        } else if self.repetitions(previous) >= 3 {
+            BoardState::drawn(DrawState::ThreefoldRepetion)
+        } else {
+            BoardState::new()
+        };
+    }
+
+    /// How often the current position has occurred, counting this time.
+    pub fn repetitions(&self, previous: &[Board]) -> usize {
+        let key = self.zobrist();
+        1 + previous
+            .iter()
+            .filter(|board| board.zobrist() == key)
+            .count()
+    }
+
+    /// Neither side can mate whatever is played: bare kings, a single minor piece, or only
+    /// bishops that all stand on squares of one color.
+    pub fn insufficient_material(&self) -> bool {
+        let pieces: Vec<_> = [Team::White, Team::Black]
+            .iter()
+            .flat_map(|team| self.find_by_team(*team))
+            .filter(|sq_entity| sq_entity.entity.kind != Piece::King)
+            .collect();
+        match pieces.as_slice() {
+            [] => true,
+            [single] => matches!(single.entity.kind, Piece::Bishop | Piece::Knight),
+            [first, ..] => pieces.iter().all(|sq_entity| {
+                sq_entity.entity.kind == Piece::Bishop
+                    && sq_entity.sq.dark_square() == first.sq.dark_square()
+            }),
+        }
+    }
+
+    /// Whether `team` has enough material to ever deliver mate, e.g. to win on time.
+    pub fn can_mate(&self, team: Team) -> bool {
+        let pieces: Vec<Piece> = self
+            .find_by_team(team)
+            .iter()
+            .map(|sq_entity| sq_entity.entity.kind)
+            .filter(|piece| *piece != Piece::King)
+            .collect();
+        pieces.len() >= 2
+            || pieces
+                .iter()
+                .any(|piece| matches!(piece, Piece::Pawn | Piece::Rook | Piece::Queen))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute::execute;
+    use std::convert::TryInto;
+
+    fn board(fen: &str) -> Board {
+        fen.to_string().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_checkmate() {
+        let mut board = Board::new();
+        for san in ["f3", "e5", "g4", "Qh4#"] {
+            execute(&mut board, san.chars()).unwrap();
+        }
+        board.adjudicate(&[]);
+        assert!(board.state.is_over());
+        assert_eq!(board.state.to_string(), "Black wins by checkmate, 0-1");
+    }
+
+    #[test]
+    fn test_stalemate() {
+        let mut board = board("k7/8/1Q6/8/8/8/8/7K b - - 0 1");
+        board.adjudicate(&[]);
+        assert_eq!(board.state, BoardState::drawn(DrawState::Stalemate));
+        assert_eq!(board.state.score(), "1/2-1/2");
+    }
+
+    #[test]
+    fn test_fifty_move_rule() {
+        let mut board = board("k7/8/8/8/8/8/8/R6K b - - 100 80");
+        board.adjudicate(&[]);
+        assert_eq!(board.state, BoardState::drawn(DrawState::FiftyMoveRule));
+    }
+
+    #[test]
+    fn test_threefold_repetition() {
+        let mut board = Board::new();
+        let mut previous = Vec::new();
+        for san in ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1", "Ng8"] {
+            board.adjudicate(&previous);
+            assert!(!board.state.is_over(), "over before {}", san);
+            previous.push(board.clone());
+            execute(&mut board, san.chars()).unwrap();
+        }
+        board.adjudicate(&previous);
+        assert_eq!(board.state, BoardState::drawn(DrawState::ThreefoldRepetion));
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        assert!(board("k7/8/8/8/8/8/8/7K w - - 0 1").insufficient_material());
+        assert!(board("k7/8/8/8/8/8/8/6NK w - - 0 1").insufficient_material());
+        assert!(board("kb6/8/8/8/8/8/8/2B4K w - - 0 1").insufficient_material());
+        assert!(!board("k1b5/8/8/8/8/8/8/2B4K w - - 0 1").insufficient_material());
+        assert!(!board("k7/8/8/8/8/8/8/5NNK w - - 0 1").insufficient_material());
+        assert!(!board("k7/p7/8/8/8/8/8/7K w - - 0 1").insufficient_material());
+    }
+
+    #[test]
+    fn test_can_mate() {
+        let board = board("kn6/8/8/8/8/8/8/5NNK w - - 0 1");
+        assert!(board.can_mate(Team::White));
+        assert!(!board.can_mate(Team::Black));
+    }
 }
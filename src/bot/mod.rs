@@ -0,0 +1,241 @@
+//! A line based JSON protocol for programs playing through the game loop, lighter than UCI.
+//!
+//! Every event is one JSON object on a line of stdout, told apart by its `event`:
+//!
+//! - `{"event": "position", "fen": ..., "turn": ..., "legal_moves": [{"san", "uci"}], ...}`
+//!   after every move, with the fields of `Session::describe`.
+//! - `{"event": "message", "text": ...}` answers a command like `fen` or `pgn`.
+//! - `{"event": "error", "message": ...}` for a rejected command or move.
+//! - `{"event": "result", "result": "1-0", "status": ..., "pgn": ...}` when the game is over.
+//!
+//! Commands are read one per line of stdin, named by `command`: `{"command": "move", "move":
+//! "e4"}` in SAN or UCI, `undo`, `redo`, `resign`, `draw`, `fen`, `pgn`, `{"command": "save",
+//! "path": ...}`, `{"command": "load", "path": ...}`, `new` and `quit`. After a result, `new`
+//! starts a rematch with colors swapped.
+
+use crate::computer::{Action, Context, Playable};
+use crate::game_loop::screen::Screen;
+use crate::game_loop::session::Session;
+use crate::input;
+use crate::input::command::Command;
+use crate::Board;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{self, Write};
+
+/// A command sent by a bot.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Request {
+    Move {
+        #[serde(rename = "move")]
+        mov: String,
+    },
+    Undo,
+    Redo,
+    Resign,
+    Draw,
+    Fen,
+    Pgn,
+    Save {
+        path: String,
+    },
+    Load {
+        path: String,
+    },
+    New,
+    Quit,
+}
+
+impl Request {
+    pub fn parse(line: &str) -> io::Result<Request> {
+        serde_json::from_str(line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+    }
+
+    /// Reads the move in the position on `board`, or hands anything else to the game loop.
+    pub fn act(self, board: &Board) -> io::Result<Action> {
+        let command = match self {
+            Request::Move { mov } => return board.find_move(&mov).map(Action::Move),
+            Request::Undo => Command::Undo,
+            Request::Redo => Command::Redo,
+            Request::Resign => Command::Resign,
+            Request::Draw => Command::Draw,
+            Request::Fen => Command::Fen,
+            Request::Pgn => Command::Pgn,
+            Request::Save { path } => Command::Save(path),
+            Request::Load { path } => Command::Load(path),
+            Request::New => Command::New,
+            Request::Quit => Command::Quit,
+        };
+        Ok(Action::Command(command))
+    }
+}
+
+/// Reads the next request from stdin, where a closed stdin asks to quit.
+fn read() -> io::Result<Request> {
+    match input::read() {
+        Ok(line) => Request::parse(&line),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(Request::Quit),
+        Err(e) => Err(e),
+    }
+}
+
+/// A bot sending its moves and commands as JSON over stdin.
+pub struct JsonPlayer;
+
+impl Playable for JsonPlayer {
+    fn action(&mut self, context: &Context) -> io::Result<Action> {
+        read()?.act(context.board)
+    }
+}
+
+/// Writes the game as JSON events, one per line.
+pub struct JsonScreen<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonScreen<W> {
+    pub fn new(out: W) -> Self {
+        JsonScreen { out }
+    }
+
+    fn emit(&mut self, event: Value) -> io::Result<()> {
+        writeln!(self.out, "{}", event)?;
+        self.out.flush()
+    }
+}
+
+impl<W: Write> Screen for JsonScreen<W> {
+    fn present(&mut self, session: &Session) -> io::Result<()> {
+        let mut position = session.describe()?;
+        position["event"] = json!("position");
+        self.emit(position)
+    }
+
+    fn show(&mut self, text: &str) -> io::Result<()> {
+        self.emit(json!({ "event": "message", "text": text }))
+    }
+
+    fn error(&mut self, err: io::Error) -> io::Result<()> {
+        self.emit(json!({ "event": "error", "message": err.to_string() }))
+    }
+
+    fn result(&mut self, session: &Session) -> io::Result<()> {
+        let state = &session.board.state;
+        self.emit(json!({
+            "event": "result",
+            "result": state.score(),
+            "status": state.to_string(),
+            "pgn": session.pgn()?,
+        }))
+    }
+
+    fn ask(&mut self, question: &str) -> io::Result<String> {
+        self.emit(json!({ "event": "question", "text": question }))?;
+        input::read()
+    }
+
+    /// Bots have the PGN in the result already.
+    fn save_as(&mut self) -> io::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Waits for `new` or `quit`, reporting anything else as an error.
+    fn rematch(&mut self) -> io::Result<bool> {
+        loop {
+            match read() {
+                Ok(Request::New) => return Ok(true),
+                Ok(Request::Quit) => return Ok(false),
+                Ok(request) => {
+                    let msg = format!("{:?} needs a game, send new or quit", request);
+                    self.error(io::Error::other(msg))?;
+                }
+                Err(e) => self.error(e)?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Team;
+
+    #[test]
+    fn test_parse_requests() {
+        assert_eq!(
+            Request::parse(r#"{"command": "move", "move": "e2e4"}"#).unwrap(),
+            Request::Move {
+                mov: "e2e4".to_string()
+            }
+        );
+        assert_eq!(
+            Request::parse(r#"{"command":"undo"}"#).unwrap(),
+            Request::Undo
+        );
+        assert_eq!(
+            Request::parse(r#"{"command": "save", "path": "game.pgn"}"#).unwrap(),
+            Request::Save {
+                path: "game.pgn".to_string()
+            }
+        );
+        for line in [
+            "e4",
+            "{}",
+            r#"{"command": "dance"}"#,
+            r#"{"command": "move"}"#,
+        ] {
+            let err = Request::parse(line).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_act() {
+        let mut board = Board::new();
+        let request = Request::parse(r#"{"command": "move", "move": "Nf3"}"#).unwrap();
+        let mov = match request.act(&board).unwrap() {
+            Action::Move(mov) => mov,
+            action => panic!("{:?}", action),
+        };
+        assert_eq!(mov.uci(), "g1f3");
+        board.apply(&mov).unwrap();
+        assert_eq!(board.turn_order, Team::Black);
+        let illegal = Request::Move {
+            mov: "e2e4".to_string(),
+        };
+        assert!(illegal.act(&board).is_err());
+        assert_eq!(
+            Request::Resign.act(&board).unwrap(),
+            Action::Command(Command::Resign)
+        );
+    }
+
+    #[test]
+    fn test_events() {
+        let mut session = Session::new(Board::new(), "player", "computer", vec![Team::White], None);
+        let mut screen = JsonScreen::new(Vec::new());
+        screen.present(&session).unwrap();
+        screen
+            .error(io::Error::other("e5 is not a legal move"))
+            .unwrap();
+        screen.show("done").unwrap();
+        session.command(Command::Resign).unwrap();
+        screen.result(&session).unwrap();
+
+        let events: Vec<Value> = String::from_utf8(screen.out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events[0]["event"], "position");
+        assert_eq!(events[0]["legal_moves"].as_array().unwrap().len(), 20);
+        assert_eq!(events[0]["last_move"], Value::Null);
+        assert_eq!(events[1]["message"], "e5 is not a legal move");
+        assert_eq!(events[2], json!({ "event": "message", "text": "done" }));
+        assert_eq!(events[3]["event"], "result");
+        assert_eq!(events[3]["result"], "0-1");
+        assert_eq!(events[3]["status"], "Black wins by resignation, 0-1");
+    }
+}
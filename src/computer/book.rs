@@ -0,0 +1,302 @@
+use crate::board::history::Move;
+use crate::computer::{polyglot, Opening};
+use crate::{Board, Team};
+use rand::seq::SliceRandom;
+use std::cmp::Reverse;
+use std::str::FromStr;
+
+/// Where the computer looks up its opening moves.
+pub enum Book {
+    Openings(Vec<Opening>),
+    Polyglot(polyglot::Book),
+}
+
+/// How a move is chosen among the candidates of a book.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BookPolicy {
+    /// Always the heaviest move.
+    Best,
+    /// Any move, as often as its weight suggests.
+    Weighted,
+    /// Only lines of the named opening, e.g. "Ruy López", heaviest first.
+    Repertoire(String),
+}
+
+impl FromStr for BookPolicy {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<BookPolicy, Self::Err> {
+        match input {
+            "best" => Ok(BookPolicy::Best),
+            "weighted" => Ok(BookPolicy::Weighted),
+            _ => match input.strip_prefix("repertoire:") {
+                Some(name) if !name.is_empty() => Ok(BookPolicy::Repertoire(name.to_string())),
+                _ => Err(format!(
+                    "unknown book policy '{}', expected best, weighted or repertoire:<name>",
+                    input
+                )),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BookOptions {
+    pub policy: BookPolicy,
+    /// Leave the book after this many moves per side.
+    pub depth: Option<usize>,
+}
+
+impl Default for BookOptions {
+    fn default() -> Self {
+        BookOptions {
+            policy: BookPolicy::Weighted,
+            depth: None,
+        }
+    }
+}
+
+/// A legal move the book suggests, and how strongly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub mov: Move,
+    pub weight: u32,
+    /// Names of the openings the move leads into.
+    pub lines: Vec<String>,
+}
+
+impl Book {
+    /// Every book move for the position.
+    pub fn candidates(&self, board: &Board) -> Vec<Candidate> {
+        match self {
+            Book::Openings(openings) => opening_candidates(openings, board),
+            Book::Polyglot(book) => book
+                .probe(board)
+                .into_iter()
+                .map(|book_move| Candidate {
+                    mov: book_move.mov,
+                    weight: book_move.weight as u32,
+                    lines: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// The SAN of the book move to play, if the book still has one.
+    pub fn select(&self, board: &Board, options: &BookOptions) -> Option<String> {
+        if let Some(depth) = options.depth {
+            if board.history.len(board.turn_order) >= depth {
+                return None;
+            }
+        }
+
+        // Polyglot books keep moves that were only ever lost with, but they aren't played.
+        let mut candidates = self.candidates(board);
+        candidates.retain(|c| c.weight > 0);
+        // Polyglot books carry no names, the book itself is the repertoire.
+        if let BookPolicy::Repertoire(name) = &options.policy {
+            if candidates.iter().any(|c| !c.lines.is_empty()) {
+                let name = name.to_lowercase();
+                candidates.retain(|c| c.lines.iter().any(|l| l.to_lowercase().contains(&name)));
+            }
+        }
+
+        let selected = match options.policy {
+            BookPolicy::Weighted => candidates
+                .choose_weighted(&mut rand::thread_rng(), |c| c.weight)
+                .ok()?,
+            _ => candidates.iter().min_by_key(|c| Reverse(c.weight))?,
+        };
+        selected.mov.label.clone()
+    }
+}
+
+fn strip_check(label: &str) -> &str {
+    label.trim_end_matches(['+', '#'])
+}
+
+/// The number of variations an opening branches into, unless the book says otherwise.
+fn popularity(opening: &Opening) -> u32 {
+    if let Some(weight) = opening.weight {
+        return weight;
+    }
+    match &opening.children {
+        Some(children) => children.iter().map(popularity).sum::<u32>().max(1),
+        None => 1,
+    }
+}
+
+fn lines(opening: &Opening, names: &mut Vec<String>) {
+    names.push(opening.name.clone());
+    if let Some(children) = &opening.children {
+        for child in children.iter() {
+            lines(child, names);
+        }
+    }
+}
+
+fn opening_candidates(openings: &[Opening], board: &Board) -> Vec<Candidate> {
+    // One variation of an opening can have several children, like a graph, or a tree structure.
+    // The openings are all lines of classical chess.
+    if board.castling.chess960 {
+        return Vec::new();
+    }
+    let mut suitable_openings: Vec<Opening> = openings.to_vec();
+
+    let mut ptn = 0;
+    let mut cur_team = Team::White;
+
+    // Follow the History down the tree, keeping the openings that match every move so far.
+    while let Some(mov) = board.history.get(cur_team, ptn) {
+        let label = match &mov.label {
+            Some(label) => strip_check(label),
+            None => return Vec::new(),
+        };
+        let mut tmp_suitable_openings = Vec::new();
+        for opening in suitable_openings.iter() {
+            if strip_check(&opening.reply) == label {
+                if let Some(children) = &opening.children {
+                    tmp_suitable_openings.extend(children.iter().cloned());
+                }
+            }
+        }
+        suitable_openings = tmp_suitable_openings;
+        if suitable_openings.is_empty() {
+            return Vec::new();
+        }
+
+        // Since the ptn is the same for White and Black, we only iterate every other.
+        if cur_team == Team::Black {
+            ptn += 1;
+        }
+        cur_team = cur_team.not();
+    }
+
+    // Only keep replies that are legal, transpositions into the same move are merged.
+    let legal_moves = board.legal_moves();
+    let mut candidates: Vec<Candidate> = Vec::new();
+    for opening in suitable_openings.iter() {
+        let mov = legal_moves.iter().find(|mov| match &mov.label {
+            Some(label) => strip_check(label) == strip_check(&opening.reply),
+            None => false,
+        });
+        let mov = match mov {
+            Some(mov) => mov,
+            None => continue,
+        };
+        let mut names = Vec::new();
+        lines(opening, &mut names);
+        match candidates.iter_mut().find(|c| c.mov == *mov) {
+            Some(candidate) => {
+                candidate.weight += popularity(opening);
+                candidate.lines.append(&mut names);
+            }
+            None => candidates.push(Candidate {
+                mov: mov.clone(),
+                weight: popularity(opening),
+                lines: names,
+            }),
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::polyglot;
+    use crate::computer::{read_openings_file, OPENINGS_FILE};
+    use crate::execute::*;
+    use crate::input::pgn;
+    use crate::run;
+
+    fn openings() -> Book {
+        Book::Openings(read_openings_file(OPENINGS_FILE).unwrap())
+    }
+
+    fn weights(candidates: Vec<Candidate>) -> Vec<(String, u32)> {
+        candidates
+            .into_iter()
+            .map(|c| (c.mov.label.unwrap(), c.weight))
+            .collect()
+    }
+
+    #[test]
+    fn test_candidates_after_e4() {
+        let mut board = Board::new();
+        run!(board, "e4");
+        let candidates = openings().candidates(&board);
+        assert_eq!(
+            weights(candidates),
+            vec![("d5".to_string(), 1), ("e5".to_string(), 8)]
+        );
+    }
+    #[test]
+    fn test_out_of_book() {
+        let mut board = Board::new();
+        run!(board, "a3");
+        assert!(openings().candidates(&board).is_empty());
+        let options = BookOptions::default();
+        assert_eq!(openings().select(&board, &options), None);
+    }
+    #[test]
+    fn test_best_policy() {
+        let mut board = Board::new();
+        run!(board, "e4");
+        let options = BookOptions {
+            policy: BookPolicy::Best,
+            depth: None,
+        };
+        assert_eq!(openings().select(&board, &options), Some("e5".to_string()));
+    }
+    #[test]
+    fn test_lost_moves_are_not_played() {
+        let games = pgn::games("[Result \"0-1\"]\n\n1. d4 d5 0-1\n");
+        let book = Book::Polyglot(polyglot::Book::from_games(&games));
+        let board = Board::new();
+        assert_eq!(weights(book.candidates(&board)), [("d4".to_string(), 0)]);
+        for policy in [BookPolicy::Best, BookPolicy::Weighted] {
+            let options = BookOptions {
+                policy,
+                depth: None,
+            };
+            assert_eq!(book.select(&board, &options), None);
+        }
+    }
+    #[test]
+    fn test_repertoire_policy() {
+        let mut board = Board::new();
+        run!(board, "e4");
+        let options = BookOptions {
+            policy: "repertoire:scandinavian".parse().unwrap(),
+            depth: None,
+        };
+        assert_eq!(openings().select(&board, &options), Some("d5".to_string()));
+        run!(board, "d5", "d4");
+        assert_eq!(openings().select(&board, &options), None);
+    }
+    #[test]
+    fn test_depth_limit() {
+        let mut board = Board::new();
+        let options = BookOptions {
+            policy: BookPolicy::Best,
+            depth: Some(1),
+        };
+        assert_eq!(openings().select(&board, &options), Some("e4".to_string()));
+        run!(board, "e4");
+        assert_eq!(openings().select(&board, &options), Some("e5".to_string()));
+        run!(board, "e5");
+        assert_eq!(openings().select(&board, &options), None);
+    }
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!("best".parse(), Ok(BookPolicy::Best));
+        assert_eq!("weighted".parse(), Ok(BookPolicy::Weighted));
+        assert_eq!(
+            "repertoire:Vienna Game".parse(),
+            Ok(BookPolicy::Repertoire("Vienna Game".to_string()))
+        );
+        assert!("repertoire:".parse::<BookPolicy>().is_err());
+        assert!("zebra".parse::<BookPolicy>().is_err());
+    }
+}
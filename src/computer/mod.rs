@@ -1,21 +1,36 @@
+pub mod book;
 pub mod player;
+pub mod polyglot;
+pub mod search;
+pub mod uci;
 
-use crate::execute;
+use crate::board::history::Move;
+use crate::game_loop::clock::Clock;
+use crate::input::command::Command;
+#[cfg(feature = "syzygy")]
+use crate::tablebase::syzygy::{Syzygy, Wdl};
+use crate::tablebase::{Outcome, Tablebase};
 use crate::{Board, Team};
+use book::{Book, BookOptions};
 use rand::seq::SliceRandom;
+use search::Limits;
 use serde::Deserialize;
 use std::fs;
 use std::io;
 use std::path::Path;
+use std::time::Duration;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Opening {
     pub name: String,
     pub reply: String,
-    #[allow(clippy::box_vec)]
+    /// Overrides the popularity of the line, which otherwise is the number of variations in it.
+    pub weight: Option<u32>,
+    #[allow(clippy::box_collection)]
     pub children: Option<Box<Vec<Opening>>>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, Clone)]
 pub struct CDCOpening {
     /// Unique identifier for the Opening
@@ -38,129 +53,271 @@ pub fn read_openings_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<Opening>> {
     Ok(res)
 }
 
+/// What a player does with its turn.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// The move to play, which the game loop checks and applies.
+    Move(Move),
+    /// Something typed at the prompt that is not a move, for the game loop to carry out. This is
+    /// also how a player resigns or offers a draw.
+    Command(Command),
+}
+
+/// The game as a player sees it when it is asked to move.
+#[derive(Clone, Copy)]
+pub struct Context<'a> {
+    /// The position to move in, with the moves that led to it in its history.
+    pub board: &'a Board,
+    /// The position the game started from.
+    pub start: &'a Board,
+    pub clock: Option<&'a Clock>,
+    /// Whether the opponent offered a draw, which playing a move declines.
+    pub draw_offered: bool,
+}
+
+impl Context<'_> {
+    /// Time `team` has left, counting the turn in progress. None when the game has no clock.
+    pub fn remaining(&self, team: Team) -> Option<Duration> {
+        self.clock.map(|clock| {
+            let running = !self.board.state.is_over() && team == self.board.turn_order;
+            let spent = match running {
+                true => clock.elapsed(),
+                false => Duration::ZERO,
+            };
+            clock.remaining(team).saturating_sub(spent)
+        })
+    }
+}
+
+/// Anything that can play one side of a game: a person at some kind of screen, or an engine.
+///
+/// Players only get to look at the game, the game loop plays their moves once it has checked
+/// them.
 pub trait Playable {
-    // This is called when you are expected to reply to a turn.
-    fn action(&self, board: &mut Board) -> io::Result<()>;
+    /// Called when the player is expected to reply to a turn. Players other than people should
+    /// answer before their time in the context runs out, one that fails to move forfeits.
+    fn action(&mut self, context: &Context) -> io::Result<Action>;
+
+    /// Called before the first move of a game, which may start from any position.
+    fn new_game(&mut self, _context: &Context) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once the game is over, with its result in the position's state.
+    fn game_over(&mut self, _context: &Context) -> io::Result<()> {
+        Ok(())
+    }
 }
 
+pub const OPENINGS_FILE: &str = "./data/openings.json";
+
+/// Centipawns behind at which the computer takes a draw it is offered.
+const ACCEPTS_DRAW: i32 = -100;
+
 pub struct Computer {
-    openings: Vec<Opening>,
+    book: Book,
+    book_options: BookOptions,
+    tablebase: Option<Tablebase>,
+    #[cfg(feature = "syzygy")]
+    syzygy: Option<Syzygy>,
+    /// How it thinks once out of the book, or None to play at random.
+    search: Option<Limits>,
 }
 
 impl Computer {
+    #[allow(dead_code)]
     pub fn new() -> Self {
+        Computer::with_book(
+            Book::Openings(
+                read_openings_file(OPENINGS_FILE).expect("Failed to read openings file"),
+            ),
+            BookOptions::default(),
+        )
+    }
+    pub fn with_book(book: Book, book_options: BookOptions) -> Self {
         Computer {
-            openings: read_openings_file("./data/openings.json")
-                .expect("Failed to read openings file"),
+            book,
+            book_options,
+            tablebase: None,
+            #[cfg(feature = "syzygy")]
+            syzygy: None,
+            search: Some(Limits::default()),
         }
     }
+    pub fn with_search(mut self, limits: Limits) -> Self {
+        self.search = Some(limits);
+        self
+    }
+    /// Plays book moves and nothing else, random ones once out of the book.
+    pub fn book_only(mut self) -> Self {
+        self.search = None;
+        self
+    }
+    /// Plays perfectly once the position is in one of the tables.
+    pub fn with_tablebase(mut self, tablebase: Tablebase) -> Self {
+        self.tablebase = Some(tablebase);
+        self
+    }
+    #[cfg(feature = "syzygy")]
+    pub fn with_syzygy(mut self, syzygy: Syzygy) -> Self {
+        self.syzygy = Some(syzygy);
+        self
+    }
+}
+
+/// Whether a position scoring `score` for the computer looks bad enough to take a draw.
+fn accepts_draw(score: search::Score) -> bool {
+    match score {
+        search::Score::Centipawns(cp) => cp <= ACCEPTS_DRAW,
+        search::Score::Mate(moves) => moves < 0,
+    }
 }
 
 impl Playable for Computer {
-    fn action(&self, mut board: &mut Board) -> io::Result<()> {
-        match self.next_book_move(board) {
-            Some(string) => match execute::execute(&mut board, string.chars()) {
-                Ok(_) => Ok(()),
-                Err(e) => Err(e),
-            },
-            None => {
-                #[cfg(test)]
-                println!("[computer/mod]: No book move available, thinking on my own!");
-                let moves = board.find_by_team(board.turn_order);
-                let sq_entity = moves.last().unwrap();
-                let sq = sq_entity.sq;
-                let piece = sq_entity.entity.kind;
-                let translations = piece.get_translations()(&board, sq, board.turn_order, piece);
-                if !translations.is_empty() {
-                    let last = translations.last().unwrap();
-                    match board.checked_translate(sq, *last) {
-                        Ok(()) => (),
-                        Err(e) => return Err(e),
-                    };
+    fn action(&mut self, context: &Context) -> io::Result<Action> {
+        let board = context.board;
+        // In the tables, a draw is taken unless the position is won.
+        #[cfg(feature = "syzygy")]
+        if let Some(syzygy) = &self.syzygy {
+            if let (Some((wdl, _)), Some(mov)) = (syzygy.probe(board), syzygy.best_move(board)) {
+                if context.draw_offered && !matches!(wdl, Wdl::Win | Wdl::CursedWin) {
+                    return Ok(Action::Command(Command::Draw));
+                }
+                return Ok(Action::Move(mov));
+            }
+        }
+        if let Some(tablebase) = &self.tablebase {
+            if let (Some(outcome), Some(mov)) = (tablebase.probe(board), tablebase.best_move(board))
+            {
+                if context.draw_offered && !matches!(outcome, Outcome::Win(_)) {
+                    return Ok(Action::Command(Command::Draw));
                 }
-                Ok(())
+                return Ok(Action::Move(mov));
             }
         }
+        if let Some(string) = self.book.select(board, &self.book_options) {
+            return board.find_move(&string).map(Action::Move);
+        }
+        #[cfg(test)]
+        println!("[computer/mod]: No book move available, thinking on my own!");
+        let mov = match &self.search {
+            // A draw offer is weighed with the same search that finds the move to play instead.
+            Some(limits) => {
+                let remaining = context.remaining(board.turn_order);
+                match search::best_line(board, limits, remaining) {
+                    Some((_, score)) if context.draw_offered && accepts_draw(score) => {
+                        return Ok(Action::Command(Command::Draw));
+                    }
+                    line => line.map(|(mov, _)| mov),
+                }
+            }
+            None => board.moves().choose(&mut rand::thread_rng()).cloned(),
+        };
+        mov.map(Action::Move)
+            .ok_or_else(|| io::Error::other("no legal moves"))
     }
 }
 
-impl Computer {
-    fn next_book_move(&self, board: &Board) -> Option<String> {
-        // One variation of an opening can have several children, like a graph, or a tree structure.
-        // TODO: make it able to return several openings.
-        #[cfg(test)]
-        println!("[computer/mod]: Checking for Known opening(s)");
+/// Plays any legal move, all as likely as the others.
+pub struct Random;
 
-        let mut suitable_openings: Vec<Opening> = Vec::new();
-
-        // initially add all openings as suitable.
-        for opening in self.openings.iter() {
-            suitable_openings.push(opening.clone());
+impl Playable for Random {
+    fn action(&mut self, context: &Context) -> io::Result<Action> {
+        let moves = context.board.moves();
+        match moves.choose(&mut rand::thread_rng()) {
+            Some(mov) => Ok(Action::Move(mov.clone())),
+            None => Err(io::Error::other("no legal moves")),
         }
+    }
+}
 
-        // Edge case if no history exist.
-
-        let mut ptn = 0;
-        let mut cur_team = Team::White;
-
-        // Move pointer.
-        while !suitable_openings.is_empty() {
-            // Check if ptn from any opening matches History.
-            #[cfg(test)]
-            println!(
-                "[computer/mod]: Getting History for {} on idx {}",
-                cur_team, ptn
-            );
-
-            match board.history.get(cur_team, ptn) {
-                Some(mov) => {
-                    match &mov.label {
-                        Some(label) => {
-                            #[cfg(test)]
-                            println!("[computer/mod]: Checking if previous move was a book move");
-                            // We don't want to append to the same vec we are removing items from,
-                            // This will cause indexing issues, so we make a temporary vec.
-                            let mut tmp_suitable_openings = Vec::new();
-
-                            // Remove all items that do not match the given book move.
-                            for opening in suitable_openings.clone().iter() {
-                                if opening.reply == *label {
-                                    #[cfg(test)]
-                                    println!("[computer/mod]: {} was a book move", label);
-                                    if let Some(mut boxed_openings) = opening.children.clone() {
-                                        tmp_suitable_openings.append(&mut (*boxed_openings));
-                                        #[cfg(test)]
-                                        println!("[computer/mod]: {:?}", tmp_suitable_openings);
-                                    }
-                                }
-                            }
-                            suitable_openings = tmp_suitable_openings;
-
-                            // Since the ptn is the same for White and Black, we only iterate every other.
-                            if cur_team == Team::Black {
-                                ptn += 1;
-                            }
-                            cur_team = cur_team.not();
-                        }
-                        None => return None,
-                    }
-                }
-                None => {
-                    #[cfg(test)]
-                    println!("[computer/mod]: Up to date, look for next move to perform.");
-                    #[cfg(test)]
-                    println!(
-                        "[computer/mod]: Selected reply: {}",
-                        suitable_openings[0].reply
-                    );
-
-                    let selected_opening = suitable_openings.choose(&mut rand::thread_rng())?;
-
-                    return Some(selected_opening.reply.clone());
-                }
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use std::str::FromStr;
+    use std::time::Instant;
 
-        None
+    fn answer(fen: &str) -> Action {
+        let board: Board = fen.to_string().try_into().unwrap();
+        let context = Context {
+            board: &board,
+            start: &board,
+            clock: None,
+            draw_offered: true,
+        };
+        let book = Book::Openings(read_openings_file(OPENINGS_FILE).unwrap());
+        let mut computer = Computer::with_book(book, BookOptions::default());
+        computer.action(&context).unwrap()
+    }
+
+    #[test]
+    fn test_draw_offers() {
+        // A Queen down it takes the draw, a Queen up it plays on.
+        assert_eq!(
+            answer("k7/8/8/8/8/8/8/KQ6 b - - 0 1"),
+            Action::Command(Command::Draw)
+        );
+        assert!(matches!(
+            answer("kq6/8/8/8/8/8/8/K7 b - - 0 1"),
+            Action::Move(_)
+        ));
+    }
+
+    #[test]
+    fn test_draw_offers_in_the_tables() {
+        let mut tablebase = Tablebase::default();
+        tablebase.generate(&"KQK".parse().unwrap());
+        let book = Book::Openings(read_openings_file(OPENINGS_FILE).unwrap());
+        let mut computer =
+            Computer::with_book(book, BookOptions::default()).with_tablebase(tablebase);
+        let mut answer = |fen: &str| {
+            let board: Board = fen.to_string().try_into().unwrap();
+            let context = Context {
+                board: &board,
+                start: &board,
+                clock: None,
+                draw_offered: true,
+            };
+            computer.action(&context).unwrap()
+        };
+        // Lost or drawn by the table it takes the draw, won it plays on.
+        assert_eq!(
+            answer("k7/8/8/8/8/8/8/KQ6 b - - 0 1"),
+            Action::Command(Command::Draw)
+        );
+        assert_eq!(
+            answer("k7/8/8/8/8/8/1q6/K7 w - - 0 1"),
+            Action::Command(Command::Draw)
+        );
+        assert!(matches!(
+            answer("k7/8/8/8/8/8/8/KQ6 w - - 0 1"),
+            Action::Move(_)
+        ));
+    }
+    #[test]
+    fn test_draw_offer_on_the_clock() {
+        // Deciding about the draw takes no longer than moving would, well within the clock.
+        let board: Board = "4k3/pppq1ppp/8/8/8/8/PPPQ1PPP/4K3 w - - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        let mut clock = Clock::from_str("0.02").unwrap();
+        clock.start();
+        let context = Context {
+            board: &board,
+            start: &board,
+            clock: Some(&clock),
+            draw_offered: true,
+        };
+        let book = Book::Openings(read_openings_file(OPENINGS_FILE).unwrap());
+        let limits = Limits {
+            depth: Some(32),
+            ..Limits::default()
+        };
+        let mut computer = Computer::with_book(book, BookOptions::default()).with_search(limits);
+        let started = Instant::now();
+        assert!(matches!(computer.action(&context).unwrap(), Action::Move(_)));
+        assert!(started.elapsed() < Duration::from_secs(2));
     }
 }
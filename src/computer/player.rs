@@ -1,7 +1,6 @@
-use crate::computer::Playable;
-use crate::execute;
+use crate::computer::{Action, Context, Playable};
 use crate::input;
-use crate::Board;
+use crate::input::command::Command;
 use std::io;
 
 pub struct Player {}
@@ -13,11 +12,18 @@ impl Player {
 }
 
 impl Playable for Player {
-    fn action(&self, mut board: &mut Board) -> io::Result<()> {
-        let input = input::read()?;
-        match execute::execute(&mut board, input.chars()) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
+    fn action(&mut self, context: &Context) -> io::Result<Action> {
+        let input = match input::read() {
+            Ok(input) => input,
+            // Closing stdin leaves the game like `quit` does.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(Action::Command(Command::Quit))
+            }
+            Err(e) => return Err(e),
+        };
+        if let Some(command) = Command::parse(&input) {
+            return command.map(Action::Command);
         }
+        context.board.find_move(&input).map(Action::Move)
     }
 }
@@ -0,0 +1,291 @@
+//! Polyglot opening books.
+//! http://hgm.nubati.net/book_format.html
+use crate::board::castling::Files;
+use crate::board::history::Move;
+use crate::execute;
+use crate::input::pgn::Pgn;
+use crate::{input, Board, Piece, Sq, Team};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+const ENTRY_SIZE: usize = 16;
+
+/// Only the first plies of every game are worth remembering.
+const MAX_PLY: usize = 40;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Entry {
+    pub key: u64,
+    pub mov: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BookMove {
+    pub mov: Move,
+    pub weight: u16,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Book {
+    /// Sorted by key, as required by the format.
+    entries: Vec<Entry>,
+}
+
+impl Book {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Book::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        if !data.len().is_multiple_of(ENTRY_SIZE) {
+            let msg = format!(
+                "Polyglot book is {} bytes, expected a multiple of {}",
+                data.len(),
+                ENTRY_SIZE
+            );
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let mut entries: Vec<Entry> = data
+            .chunks(ENTRY_SIZE)
+            .map(|chunk| {
+                let mut key = [0u8; 8];
+                key.copy_from_slice(&chunk[0..8]);
+                let mut learn = [0u8; 4];
+                learn.copy_from_slice(&chunk[12..16]);
+                Entry {
+                    key: u64::from_be_bytes(key),
+                    mov: u16::from_be_bytes([chunk[8], chunk[9]]),
+                    weight: u16::from_be_bytes([chunk[10], chunk[11]]),
+                    learn: u32::from_be_bytes(learn),
+                }
+            })
+            .collect();
+        // Books written by other tools should already be sorted, but it is cheap to make sure.
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)));
+        Ok(Book { entries })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.entries.len() * ENTRY_SIZE);
+        for entry in self.entries.iter() {
+            data.extend_from_slice(&entry.key.to_be_bytes());
+            data.extend_from_slice(&entry.mov.to_be_bytes());
+            data.extend_from_slice(&entry.weight.to_be_bytes());
+            data.extend_from_slice(&entry.learn.to_be_bytes());
+        }
+        data
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Every book move for the position, with the weight the book gives it.
+    pub fn probe(&self, board: &Board) -> Vec<BookMove> {
+        let key = board.zobrist();
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let entries: Vec<&Entry> = self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .collect();
+        if entries.is_empty() {
+            return Vec::new();
+        }
+
+        let legal_moves = board.legal_moves();
+        entries
+            .into_iter()
+            .filter_map(|entry| {
+                let mov = decode(&legal_moves, board.castling.files, entry.mov)?;
+                Some(BookMove {
+                    mov,
+                    weight: entry.weight,
+                })
+            })
+            .collect()
+    }
+
+    /// Builds a book from the opening moves of the given games.
+    /// Like Polyglot, a move scores 2 for each win, 1 for each draw or unknown result. Moves
+    /// that were only ever lost with stay in the book with weight 0.
+    pub fn from_games(games: &[Pgn]) -> Self {
+        let mut scores: HashMap<(u64, u16), u32> = HashMap::new();
+
+        for game in games {
+            let result = game.tag("Result");
+            let mut board = Board::new();
+            let plies = game
+                .turns
+                .iter()
+                .flat_map(|turn| vec![turn.white.as_ref(), turn.black.as_ref()])
+                .flatten()
+                .take(MAX_PLY);
+
+            for san in plies {
+                let team = board.turn_order;
+                let key = board.zobrist();
+                if execute::execute(&mut board, san.chars()).is_err() {
+                    break;
+                }
+                let mov = match board.history.last(team) {
+                    Some(mov) => encode(mov, board.castling.files),
+                    None => break,
+                };
+                let score = match (result.as_deref(), team) {
+                    (Some("1-0"), Team::White) | (Some("0-1"), Team::Black) => 2,
+                    (Some("1-0"), Team::Black) | (Some("0-1"), Team::White) => 0,
+                    _ => 1,
+                };
+                *scores.entry((key, mov)).or_insert(0) += score;
+            }
+        }
+
+        // Weights have to fit in 16 bits, so scale them down when they would not.
+        let max = scores.values().copied().max().unwrap_or(0);
+        let divisor = max / u16::MAX as u32 + 1;
+        let mut entries: Vec<Entry> = scores
+            .into_iter()
+            .map(|((key, mov), score)| Entry {
+                key,
+                mov,
+                weight: (score / divisor) as u16,
+                learn: 0,
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            a.key
+                .cmp(&b.key)
+                .then(b.weight.cmp(&a.weight))
+                .then(a.mov.cmp(&b.mov))
+        });
+        Book { entries }
+    }
+}
+
+/// Castling is encoded as the King capturing its own Rook, e.g. "e1h1".
+fn encode(mov: &Move, files: Files) -> u16 {
+    let mut to = mov.to;
+    let castle = mov.label.as_deref().filter(|label| label.starts_with("O-O"));
+    if let (Piece::King, Some(castle)) = (mov.piece, castle) {
+        to = match castle.starts_with("O-O-O") {
+            true => Sq::new(to.digit, files.long),
+            false => Sq::new(to.digit, files.short),
+        };
+    }
+    let promotion = match mov.promotion {
+        Some(Piece::Knight) => 1,
+        Some(Piece::Bishop) => 2,
+        Some(Piece::Rook) => 3,
+        Some(Piece::Queen) => 4,
+        _ => 0,
+    };
+    (promotion << 12 | mov.from.digit << 9 | mov.from.letter << 6 | to.digit << 3 | to.letter)
+        as u16
+}
+
+fn decode(legal_moves: &[Move], files: Files, raw: u16) -> Option<Move> {
+    legal_moves.iter().find(|mov| encode(mov, files) == raw).cloned()
+}
+
+/// Writes a Polyglot book built from a PGN collection.
+pub fn build(pgn: String, out: String) -> Result<()> {
+    let data = fs::read_to_string(pgn)?;
+    let games = input::pgn::games(&data);
+    let book = Book::from_games(&games);
+    book.write(&out)?;
+    println!(
+        "{} game(s) parsed, {} book entries written to {}",
+        games.len(),
+        book.len(),
+        out
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute::*;
+    use crate::input::pgn;
+    use crate::run;
+    use std::convert::TryInto;
+
+    fn book() -> Book {
+        let data = "1. e4 e5 2. Nf3 Nc6 *\n\n\
+                    [Result \"1/2-1/2\"]\n\n1. e4 c5 2. Nf3 1/2-1/2\n\n\
+                    [Result \"0-1\"]\n\n1. d4 d5 0-1\n";
+        Book::from_games(&pgn::games(data))
+    }
+
+    #[test]
+    fn test_probe_initial() {
+        let book = book();
+        let moves = book.probe(&Board::new());
+        let labels: Vec<(String, u16)> = moves
+            .into_iter()
+            .map(|book_move| (book_move.mov.label.unwrap(), book_move.weight))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![("e4".to_string(), 2), ("d4".to_string(), 0)],
+            "1. d4 only lost, so it is kept with weight 0"
+        );
+    }
+
+    #[test]
+    fn test_probe_reply() {
+        let book = book();
+        let mut board = Board::new();
+        run!(board, "e4");
+        let moves = book.probe(&board);
+        assert_eq!(moves.len(), 2);
+        assert!(moves.iter().all(|book_move| book_move.weight == 1));
+        run!(board, "e5");
+        let moves = book.probe(&board);
+        assert_eq!(moves[0].mov.label, Some("Nf3".to_string()));
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let book = book();
+        assert_eq!(Book::from_bytes(&book.to_bytes())?, book);
+        assert!(Book::from_bytes(&[0u8; 15]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_castling() {
+        let mut board = Board::new();
+        run!(board, "e4", "e5", "Nf3", "Nc6", "Bc4", "Bc5", "O-O");
+        let mov = board.history.last(Team::White).unwrap();
+        // e1h1
+        assert_eq!(encode(mov, board.castling.files), 4 << 6 | 7);
+    }
+
+    #[test]
+    fn test_encode_chess960_castling() {
+        // King on f1, Rooks on a1 and h1.
+        let board: Board = "4k3/8/8/8/8/8/8/R4K1R w HA - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        let short = board.find_move("O-O").unwrap();
+        // f1h1
+        assert_eq!(encode(&short, board.castling.files), 5 << 6 | 7);
+        let long = board.find_move("O-O-O").unwrap();
+        // f1a1
+        assert_eq!(encode(&long, board.castling.files), 5 << 6);
+        assert_eq!(
+            decode(&board.legal_moves(), board.castling.files, 5 << 6 | 7),
+            Some(short)
+        );
+    }
+}
@@ -0,0 +1,430 @@
+use crate::board::history::Move;
+use crate::{Board, Team};
+use rand::seq::SliceRandom;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Plies looked ahead when nothing else is asked for.
+pub const DEFAULT_DEPTH: usize = 3;
+/// Plies looked ahead at most when thinking for a while.
+const MAX_DEPTH: usize = 32;
+/// Share of the time left on the clock spent on a single move.
+const CLOCK_SHARE: u32 = 30;
+
+const INFINITY: i32 = 1_000_000;
+/// Score of being mated now, less the plies it takes to get there.
+const MATE: i32 = 100_000;
+
+/// How far and for how long the computer thinks, and how well it plays.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Limits {
+    /// Plies to look ahead.
+    pub depth: Option<usize>,
+    /// Time to think about each move.
+    pub movetime: Option<Duration>,
+    /// Plays roughly like a player of this rating, by looking ahead less and picking any of the
+    /// moves that look about as good as the best.
+    pub elo: Option<u32>,
+}
+
+impl Limits {
+    /// Ratings `elo` can be set to.
+    pub const ELO_RANGE: std::ops::RangeInclusive<u32> = 600..=2400;
+
+    /// The depth to stop at, when the time to think doesn't run out first.
+    fn depth(&self) -> usize {
+        let cap = match self.elo {
+            Some(elo) if elo < 1000 => 1,
+            Some(elo) if elo < 1500 => 2,
+            Some(elo) if elo < 2000 => 3,
+            _ => MAX_DEPTH,
+        };
+        let depth = match (self.depth, self.movetime) {
+            (Some(depth), _) => depth,
+            (None, Some(_)) => MAX_DEPTH,
+            (None, None) => DEFAULT_DEPTH,
+        };
+        depth.min(cap)
+    }
+
+    /// How many centipawns worse than the best a move may look and still be played.
+    fn margin(&self) -> i32 {
+        match self.elo {
+            Some(elo) => (*Self::ELO_RANGE.end() as i32 - elo as i32).max(0) / 4,
+            None => 0,
+        }
+    }
+
+    /// When to stop thinking, from the time given per move or else from the time left.
+    fn deadline(&self, remaining: Option<Duration>) -> Option<Instant> {
+        let time = match (self.movetime, remaining) {
+            (Some(movetime), _) => movetime,
+            (None, Some(remaining)) => remaining / CLOCK_SHARE,
+            (None, None) => return None,
+        };
+        Some(Instant::now() + time)
+    }
+}
+
+/// How good a position is for the team to move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Score {
+    Centipawns(i32),
+    /// Mates in this many moves, or is mated in as many when negative.
+    Mate(i32),
+}
+
+impl Score {
+    fn new(value: i32) -> Score {
+        if value.abs() < MATE - MAX_DEPTH as i32 {
+            return Score::Centipawns(value);
+        }
+        let moves = (MATE - value.abs() + 1) / 2;
+        match value > 0 {
+            true => Score::Mate(moves),
+            false => Score::Mate(-moves),
+        }
+    }
+}
+
+/// Pawns for the team to move, e.g. "+0.35", or the moves to mate, e.g. "#3" or "#-2".
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Score::Centipawns(cp) => write!(f, "{:+.2}", *cp as f64 / 100.0),
+            Score::Mate(moves) => write!(f, "#{}", moves),
+        }
+    }
+}
+
+/// A line the search expects to be played, and how good it is for the team to move.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub score: Score,
+    pub moves: Vec<Move>,
+}
+
+/// What `analyze` found, looking as deep as it got.
+#[derive(Debug)]
+pub struct Analysis {
+    pub depth: usize,
+    /// Positions looked at.
+    pub nodes: u64,
+    pub elapsed: Duration,
+    /// The best lines, best first.
+    pub lines: Vec<Line>,
+}
+
+impl Analysis {
+    /// Nodes per second.
+    pub fn nps(&self) -> u64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => (self.nodes as f64 / secs) as u64,
+            _ => 0,
+        }
+    }
+}
+
+/// The move that looks best within `limits`, and the score of the best line for the team to
+/// move, or None when there are no legal moves. `remaining` is the time the team to move has
+/// left, if the game is on a clock.
+pub fn best_line(
+    board: &Board,
+    limits: &Limits,
+    remaining: Option<Duration>,
+) -> Option<(Move, Score)> {
+    let mut search = Search::new(limits.deadline(remaining));
+    let (_, lines) = search.deepen(board, limits.depth(), 1, limits.margin());
+    let score = Score::new(lines.first()?.0);
+    let candidates: Vec<Move> = lines
+        .into_iter()
+        .filter_map(|(_, moves)| moves.into_iter().next())
+        .collect();
+    let mov = candidates.choose(&mut rand::thread_rng()).cloned()?;
+    Some((mov, score))
+}
+
+/// The `multipv` best lines within `limits`.
+pub fn analyze(board: &Board, limits: &Limits, multipv: usize) -> Analysis {
+    let started = Instant::now();
+    let mut search = Search::new(limits.deadline(None));
+    let (depth, lines) = search.deepen(board, limits.depth(), multipv.max(1), 0);
+    let lines = match depth {
+        0 => Vec::new(),
+        _ => lines
+            .into_iter()
+            .take(multipv)
+            .map(|(score, moves)| Line {
+                score: Score::new(score),
+                moves,
+            })
+            .collect(),
+    };
+    Analysis {
+        depth,
+        nodes: search.nodes,
+        elapsed: started.elapsed(),
+        lines,
+    }
+}
+
+/// Counts the positions looked at, and gives up once the deadline passes.
+struct Search {
+    deadline: Option<Instant>,
+    nodes: u64,
+}
+
+impl Search {
+    fn new(deadline: Option<Instant>) -> Self {
+        Search { deadline, nodes: 0 }
+    }
+
+    /// Looks one ply deeper at a time up to `depth`, for as long as there is time, though the
+    /// first ply is always finished. Returns the depth of the last complete look ahead, and the
+    /// scored lines it found as `root` does.
+    fn deepen(
+        &mut self,
+        board: &Board,
+        depth: usize,
+        keep: usize,
+        margin: i32,
+    ) -> (usize, Vec<(i32, Vec<Move>)>) {
+        let mut moves = ordered(board, board.moves());
+        let mut lines: Vec<(i32, Vec<Move>)> =
+            moves.iter().map(|mov| (0, vec![mov.clone()])).collect();
+        let mut reached = 0;
+        let deadline = self.deadline.take();
+        for depth in 1..=depth {
+            let found = self.root(board, &moves, depth, keep, margin);
+            self.deadline = deadline;
+            match found {
+                Some(found) => lines = found,
+                // What the last complete look ahead found stands.
+                None => break,
+            }
+            reached = depth;
+            // The best moves so far are looked at first next time.
+            let best: Vec<Move> = lines.iter().map(|(_, line)| line[0].clone()).collect();
+            moves.retain(|mov| !best.contains(mov));
+            moves.splice(0..0, best);
+        }
+        (reached, lines)
+    }
+
+    /// Searches every move `depth` plies deep, and returns the `keep` best and any others
+    /// scoring within `margin` of the best, best first, with the lines expected to follow.
+    /// None when the deadline passed before it was done.
+    fn root(
+        &mut self,
+        board: &Board,
+        moves: &[Move],
+        depth: usize,
+        keep: usize,
+        margin: i32,
+    ) -> Option<Vec<(i32, Vec<Move>)>> {
+        let mut scored: Vec<(i32, Vec<Move>)> = Vec::new();
+        for mov in moves {
+            let mut child = board.clone();
+            if child.apply(mov).is_err() {
+                continue;
+            }
+            // Anything scoring no better than `floor` won't be kept, how much worse is moot.
+            let best = scored.first().map_or(-INFINITY, |(score, _)| *score);
+            let kept = match scored.get(keep - 1) {
+                Some((score, _)) => *score,
+                None => -INFINITY,
+            };
+            let floor = (kept.min(best - margin) - 1).max(-INFINITY);
+            let mut line = Vec::new();
+            let score = -self.negamax(&child, depth - 1, 1, -INFINITY, -floor, &mut line)?;
+            line.insert(0, mov.clone());
+            let at = scored.partition_point(|(other, _)| *other >= score);
+            scored.insert(at, (score, line));
+        }
+        let best = scored.first().map_or(-INFINITY, |(score, _)| *score);
+        Some(
+            scored
+                .into_iter()
+                .enumerate()
+                .take_while(|(n, (score, _))| *n < keep || *score >= best - margin)
+                .map(|(_, line)| line)
+                .collect(),
+        )
+    }
+
+    /// The score of `board` for the team to move, looking `depth` plies ahead, and in `line`
+    /// the moves expected to follow. None once the deadline has passed.
+    fn negamax(
+        &mut self,
+        board: &Board,
+        depth: usize,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+        line: &mut Vec<Move>,
+    ) -> Option<i32> {
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return None;
+        }
+        self.nodes += 1;
+        line.clear();
+        let moves = board.moves();
+        if moves.is_empty() {
+            return Some(match board.in_check(board.turn_order) {
+                true => -MATE + ply,
+                false => 0,
+            });
+        }
+        if depth == 0 {
+            return Some(evaluate(board));
+        }
+        let mut rest = Vec::new();
+        for mov in ordered(board, moves) {
+            let mut child = board.clone();
+            if child.apply(&mov).is_err() {
+                continue;
+            }
+            let score = -self.negamax(&child, depth - 1, ply + 1, -beta, -alpha, &mut rest)?;
+            if score > alpha {
+                alpha = score;
+                line.clear();
+                line.push(mov);
+                line.append(&mut rest);
+            }
+            if score >= beta {
+                return Some(score);
+            }
+        }
+        Some(alpha)
+    }
+}
+
+/// Material in centipawns, from the side of the team to move.
+fn evaluate(board: &Board) -> i32 {
+    let score = board.evaluation() as i32 * 100;
+    match board.turn_order {
+        Team::White => score,
+        Team::Black => -score,
+    }
+}
+
+/// Captures of the most valuable pieces first, as they most often settle the score.
+fn ordered(board: &Board, mut moves: Vec<Move>) -> Vec<Move> {
+    moves.sort_by_key(|mov| {
+        let victim = board.get(mov.to).map_or(0, |entity| entity.kind.value());
+        std::cmp::Reverse(victim)
+    });
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn board(fen: &str) -> Board {
+        fen.to_string().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_mate_in_one() {
+        let board = board("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let limits = Limits {
+            depth: Some(2),
+            ..Limits::default()
+        };
+        let (mov, _) = best_line(&board, &limits, None).unwrap();
+        assert_eq!(mov.uci(), "a1a8");
+    }
+
+    #[test]
+    fn test_best_line() {
+        let board = board("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let (mov, score) = best_line(&board, &Limits::default(), None).unwrap();
+        assert_eq!(mov.uci(), "a1a8");
+        assert_eq!(score, Score::Mate(1));
+    }
+
+    #[test]
+    fn test_takes_hanging_queen() {
+        let board = board("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1");
+        let (mov, _) = best_line(&board, &Limits::default(), None).unwrap();
+        assert_eq!(mov.uci(), "d2d5");
+    }
+
+    #[test]
+    fn test_no_moves() {
+        let board = board("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(best_line(&board, &Limits::default(), None), None);
+    }
+
+    #[test]
+    fn test_movetime() {
+        let limits = Limits {
+            movetime: Some(Duration::from_millis(50)),
+            ..Limits::default()
+        };
+        let started = Instant::now();
+        assert!(best_line(&Board::new(), &limits, None).is_some());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_analyze() {
+        let position = board("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let limits = Limits {
+            depth: Some(3),
+            ..Limits::default()
+        };
+        let analysis = analyze(&position, &limits, 3);
+        assert_eq!(analysis.depth, 3);
+        assert!(analysis.nodes > 0);
+        assert_eq!(analysis.lines.len(), 3);
+        assert_eq!(analysis.lines[0].score, Score::Mate(1));
+        assert_eq!(analysis.lines[0].moves[0].uci(), "a1a8");
+        assert!(matches!(analysis.lines[1].score, Score::Centipawns(_)));
+
+        let mated = board("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert!(analyze(&mated, &limits, 2).lines.is_empty());
+
+        // Out of time from the start, the first ply is still looked at.
+        let limits = Limits {
+            movetime: Some(Duration::ZERO),
+            ..Limits::default()
+        };
+        let analysis = analyze(&position, &limits, 1);
+        assert_eq!(analysis.depth, 1);
+        assert_eq!(analysis.lines[0].score, Score::Mate(1));
+    }
+
+    #[test]
+    fn test_mate_scores() {
+        assert_eq!(Score::new(MATE - 1), Score::Mate(1));
+        assert_eq!(Score::new(MATE - 3), Score::Mate(2));
+        assert_eq!(Score::new(-MATE + 2), Score::Mate(-1));
+        assert_eq!(Score::new(120), Score::Centipawns(120));
+        assert_eq!(Score::Centipawns(35).to_string(), "+0.35");
+        assert_eq!(Score::Centipawns(-120).to_string(), "-1.20");
+        assert_eq!(Score::Mate(-2).to_string(), "#-2");
+    }
+
+    #[test]
+    fn test_elo_weakens_play() {
+        let weak = Limits {
+            elo: Some(600),
+            ..Limits::default()
+        };
+        assert_eq!(weak.depth(), 1);
+        assert_eq!(weak.margin(), 450);
+        let strong = Limits {
+            elo: Some(2400),
+            depth: Some(4),
+            ..Limits::default()
+        };
+        assert_eq!(strong.depth(), 4);
+        assert_eq!(strong.margin(), 0);
+    }
+}
@@ -0,0 +1,327 @@
+//! Another engine on this machine as a player, spoken to over the Universal Chess Interface.
+//!
+//! The engine runs as a child process for as long as the player lives. Before every move it is
+//! sent the whole game as `position startpos moves ...` or `position fen ... moves ...`, and
+//! `go` with the clocks, or a fixed time when the game has none. Its `bestmove` is handed to
+//! the game loop like any other player's move. An engine that doesn't answer in time, its clock
+//! plus `SLACK`, is given up on with an error.
+
+use crate::computer::{Action, Context, Playable};
+use crate::input::pgn;
+use crate::{Board, Team};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time the engine gets per move in games without a clock.
+const MOVETIME: Duration = Duration::from_millis(1000);
+/// How long the engine gets to leave after `quit`, before it is killed.
+const QUIT_GRACE: Duration = Duration::from_millis(500);
+/// How long the engine gets to answer `uci` and `isready`.
+const HANDSHAKE: Duration = Duration::from_secs(10);
+/// Time the engine gets past its clock, or `MOVETIME`, to answer `go`.
+const SLACK: Duration = Duration::from_millis(1000);
+
+pub struct Uci {
+    child: Child,
+    input: ChildStdin,
+    /// The lines the engine writes, read on a thread of their own so waiting can time out.
+    output: Receiver<String>,
+    /// As the engine introduced itself with `id name`.
+    pub name: String,
+}
+
+impl Uci {
+    /// Starts the engine at `path` and waits until it is ready.
+    pub fn spawn(path: &str) -> io::Result<Uci> {
+        Uci::start(Command::new(path), path)
+    }
+
+    fn start(mut command: Command, path: &str) -> io::Result<Uci> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| io::Error::other(format!("can't run {}: {}", path, e)))?;
+        let input = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (lines, output) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if lines.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut engine = Uci {
+            child,
+            input,
+            output,
+            name: path.to_string(),
+        };
+        engine.send("uci")?;
+        let deadline = Instant::now() + HANDSHAKE;
+        loop {
+            let line = engine.read(deadline)?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            }
+            if line.trim() == "uciok" {
+                break;
+            }
+        }
+        engine.ready()?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.input, "{}", command)?;
+        self.input.flush()
+    }
+
+    /// The next line the engine writes, if it comes before `deadline`.
+    fn read(&mut self, deadline: Instant) -> io::Result<String> {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        match self.output.recv_timeout(wait) {
+            Ok(line) => Ok(line),
+            Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("{} did not answer in time", self.name),
+            )),
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} stopped", self.name),
+            )),
+        }
+    }
+
+    /// Waits until the engine has done everything it was told.
+    fn ready(&mut self) -> io::Result<()> {
+        self.send("isready")?;
+        let deadline = Instant::now() + HANDSHAKE;
+        while self.read(deadline)?.trim() != "readyok" {}
+        Ok(())
+    }
+}
+
+/// The game as a UCI `position` command.
+fn position(context: &Context) -> io::Result<String> {
+    let start = match *context.start == Board::new() {
+        true => "startpos".to_string(),
+        false => format!("fen {}", context.start.fen()),
+    };
+    let moves: Vec<String> = pgn::played(context.start, context.board)?
+        .iter()
+        .map(|(_, mov)| mov.uci())
+        .collect();
+    Ok(match moves.is_empty() {
+        true => format!("position {}", start),
+        false => format!("position {} moves {}", start, moves.join(" ")),
+    })
+}
+
+/// The UCI `go` command, with the time each side has left in milliseconds.
+fn go(context: &Context) -> String {
+    let clock = match context.clock {
+        Some(clock) => clock,
+        None => return format!("go movetime {}", MOVETIME.as_millis()),
+    };
+    let left = |team: Team| context.remaining(team).unwrap_or_default().as_millis();
+    let increment = clock.increment().as_millis();
+    format!(
+        "go wtime {} btime {} winc {} binc {}",
+        left(Team::White),
+        left(Team::Black),
+        increment,
+        increment
+    )
+}
+
+impl Playable for Uci {
+    fn action(&mut self, context: &Context) -> io::Result<Action> {
+        self.send(&position(context)?)?;
+        self.send(&go(context))?;
+        let time = context
+            .remaining(context.board.turn_order)
+            .unwrap_or(MOVETIME);
+        let deadline = Instant::now() + time + SLACK;
+        let reply = loop {
+            let line = match self.read(deadline) {
+                Ok(line) => line,
+                Err(e) => {
+                    let _ = self.send("stop");
+                    return Err(e);
+                }
+            };
+            if let Some(reply) = line.strip_prefix("bestmove") {
+                break reply.split_whitespace().next().unwrap_or("").to_string();
+            }
+        };
+        match reply.as_str() {
+            "" | "(none)" | "0000" => Err(io::Error::other(format!("{} has no move", self.name))),
+            uci => context.board.find_move(uci).map(Action::Move).map_err(|_| {
+                io::Error::other(format!("{} played {}, which is not legal", self.name, uci))
+            }),
+        }
+    }
+
+    fn new_game(&mut self, context: &Context) -> io::Result<()> {
+        // Set either way, the engine may have played Chess960 the game before.
+        self.send(&format!(
+            "setoption name UCI_Chess960 value {}",
+            context.start.castling.chess960
+        ))?;
+        self.send("ucinewgame")?;
+        self.ready()
+    }
+}
+
+impl Drop for Uci {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let asked = Instant::now();
+        while asked.elapsed() < QUIT_GRACE {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::game_loop::clock::Clock;
+    use crate::game_loop::session::Session;
+    use std::convert::TryInto;
+    use std::fs;
+    use std::str::FromStr;
+
+    /// Knows a few moves of the Ruy Lopez, and notes down everything it is told in the file
+    /// named by `UCI_LOG`.
+    const ENGINE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/uci/engine.sh");
+
+    fn engine() -> Uci {
+        Uci::spawn(ENGINE).unwrap()
+    }
+
+    #[test]
+    fn test_handshake() {
+        assert_eq!(engine().name, "Stand-in");
+        let err = Uci::spawn("no/such/engine").err().unwrap();
+        assert!(err.to_string().starts_with("can't run no/such/engine"));
+    }
+
+    #[test]
+    fn test_plays_along() {
+        let mut engine = engine();
+        let mut session = Session::new(Board::new(), "uci", "player", vec![Team::Black], None);
+        engine.new_game(&session.context()).unwrap();
+        for reply in ["e5", "Nc6"] {
+            let mov = match engine.action(&session.context()).unwrap() {
+                Action::Move(mov) => mov,
+                action => panic!("{:?}", action),
+            };
+            session.play(&mov).unwrap();
+            session
+                .play(&session.board.find_move(reply).unwrap())
+                .unwrap();
+        }
+        match engine.action(&session.context()).unwrap() {
+            Action::Move(mov) => assert_eq!(mov.uci(), "f1b5"),
+            action => panic!("{:?}", action),
+        }
+        // Out of its depth, the engine gives up.
+        session
+            .play(&session.board.find_move("Bb5").unwrap())
+            .unwrap();
+        session
+            .play(&session.board.find_move("a6").unwrap())
+            .unwrap();
+        let err = engine.action(&session.context()).unwrap_err();
+        assert_eq!(err.to_string(), "Stand-in has no move");
+    }
+
+    #[test]
+    fn test_gives_up_on_a_silent_engine() {
+        let mut engine = engine();
+        let clock = Clock::from_str("0.005").ok();
+        let mut session = Session::new(Board::new(), "player", "uci", vec![Team::White], clock);
+        session
+            .play(&session.board.find_move("a3").unwrap())
+            .unwrap();
+        let asked = Instant::now();
+        let err = engine.action(&session.context()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(err.to_string(), "Stand-in did not answer in time");
+        assert!(asked.elapsed() < Duration::from_millis(300) + SLACK * 2);
+    }
+
+    #[test]
+    fn test_commands() {
+        let clock = Clock::from_str("5+3").ok();
+        let start: Board = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        let mut session = Session::new(start, "uci", "player", vec![Team::Black], clock);
+        assert_eq!(
+            position(&session.context()).unwrap(),
+            "position fen 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"
+        );
+        assert_eq!(
+            go(&session.context()),
+            "go wtime 300000 btime 300000 winc 3000 binc 3000"
+        );
+        session
+            .play(&session.board.find_move("e4").unwrap())
+            .unwrap();
+        assert_eq!(
+            position(&session.context()).unwrap(),
+            "position fen 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 moves e2e4"
+        );
+
+        let session = Session::new(Board::new(), "uci", "player", vec![], None);
+        assert_eq!(position(&session.context()).unwrap(), "position startpos");
+        assert_eq!(go(&session.context()), "go movetime 1000");
+    }
+
+    #[test]
+    fn test_engine_is_told_the_game() {
+        let log = std::env::temp_dir().join("chess_uci_test.log");
+        let _ = fs::remove_file(&log);
+        let mut command = Command::new(ENGINE);
+        command.env("UCI_LOG", &log);
+        let mut engine = Uci::start(command, ENGINE).unwrap();
+        let fischer = Session::new(Board::chess960(0).unwrap(), "uci", "player", vec![], None);
+        engine.new_game(&fischer.context()).unwrap();
+        let session = Session::new(Board::new(), "uci", "player", vec![], None);
+        engine.new_game(&session.context()).unwrap();
+        engine.action(&session.context()).unwrap();
+        drop(engine);
+        let told = fs::read_to_string(&log).unwrap();
+        fs::remove_file(&log).unwrap();
+        assert_eq!(
+            told.lines().collect::<Vec<_>>(),
+            [
+                "uci",
+                "isready",
+                "setoption name UCI_Chess960 value true",
+                "ucinewgame",
+                "isready",
+                "setoption name UCI_Chess960 value false",
+                "ucinewgame",
+                "isready",
+                "position startpos",
+                "go movetime 1000",
+                "quit"
+            ]
+        );
+    }
+}
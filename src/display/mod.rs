@@ -2,62 +2,171 @@ extern crate colored;
 use crate::board::Board;
 use crate::colored::Colorize;
 use crate::place::sq::Sq;
-use crate::Team;
+use crate::SIZE;
+use crate::{Entity, Piece, Team};
 use std::io::Error;
 
-pub fn present(board: &Board) {
-    print!("{}[2J", 27 as char);
-    for (x, row) in board.board.iter().rev().enumerate() {
-        for (y, column) in row.iter().enumerate() {
-            if y == 0 {
-                print!(" {} ", row.len() - x);
-            }
-            let label: colored::ColoredString = match column {
-                Some(ent) => color_team(&ent.team, ent.kind.into()).to_string().black(),
+pub mod theme;
+
+use theme::{Highlight, Theme};
+
+/// How, and from which side, the board is shown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct View {
+    pub perspective: Team,
+    pub theme: Theme,
+    /// Square of the piece whose moves are shown.
+    pub selected: Option<Sq>,
+    /// Square under the keyboard cursor of the full-screen interface.
+    pub cursor: Option<Sq>,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        View {
+            perspective: Team::White,
+            theme: Theme::default(),
+            selected: None,
+            cursor: None,
+        }
+    }
+}
+
+/// Prints the board as `view` says. Only a colored theme clears the screen first, plain text
+/// may well be going to a file.
+pub fn present_with(board: &Board, view: &View) {
+    if view.theme.palette.is_some() {
+        print!("{}[2J", 27 as char);
+    }
+    print!("{}", render(board, view));
+}
 
-                None => " ".black(),
+/// The board as seen from the side of the view's perspective, next to a column with the side to
+/// move, the latest moves and the evaluation. The column reads the same from either side.
+pub fn render(board: &Board, view: &View) -> String {
+    let column = side_column(board, &view.theme);
+    let highlights = highlights(board, view);
+    let mut out = String::new();
+    for (x, side) in column.iter().enumerate() {
+        let rank = match view.perspective {
+            Team::White => SIZE - 1 - x,
+            Team::Black => x,
+        };
+        out.push_str(&format!(" {} ", rank + 1));
+        for y in 0..SIZE {
+            let file = match view.perspective {
+                Team::White => y,
+                Team::Black => SIZE - 1 - y,
             };
-            let mut label = format!(" {} ", label);
-            if Sq::new(x, y).dark_square() {
-                label = label.on_green().to_string();
-            } else {
-                label = label.on_purple().to_string();
-            }
-            print!("{}", label);
+            let sq = Sq::new(rank, file);
+            let highlight = highlights[rank][file];
+            out.push_str(
+                &view
+                    .theme
+                    .square(board.get(sq), sq.dark_square(), highlight),
+            );
         }
-        if x == 0 {
-            print_player_to_move(&board.turn_order);
-        } else {
-            let len = board.history.len(Team::White);
-            if len > 0 && len >= x {
-                let [w, b] = board.history.tuple(x - 1);
-                let w = match w {
-                    Some(x) => x.label.clone().unwrap(),
-                    None => String::new(),
-                };
-                let b = match b {
-                    Some(x) => x.label.clone().unwrap(),
-                    None => String::new(),
-                };
-                print!("   {}. {} {}", x, pad_string(w, 7), pad_string(b, 7));
-            }
+        out.push_str(side);
+        out.push('\n');
+    }
+    let files = match view.perspective {
+        Team::White => "    A  B  C  D  E  F  G  H ",
+        Team::Black => "    H  G  F  E  D  C  B  A ",
+    };
+    out.push_str(files);
+    out.push('\n');
+    out
+}
+
+/// The last move, a King in check, the moves of the selected piece and the cursor, later ones
+/// taking precedence.
+fn highlights(board: &Board, view: &View) -> [[Option<Highlight>; SIZE]; SIZE] {
+    let mut highlights = [[None; SIZE]; SIZE];
+    if let Some(last) = board.history.last(board.turn_order.not()) {
+        for sq in [last.from, last.to] {
+            highlights[sq.digit][sq.letter] = Some(Highlight::LastMove);
         }
-        if x == 7 {
-            print!("  Eval: {}   ", board.evaluation());
+    }
+    if board.in_check(board.turn_order) {
+        let king = board
+            .find_by_team(board.turn_order)
+            .into_iter()
+            .find(|sq_entity| sq_entity.entity.kind == Piece::King);
+        if let Some(king) = king {
+            highlights[king.sq.digit][king.sq.letter] = Some(Highlight::Check);
+        }
+    }
+    if let Some(from) = view.selected {
+        for mov in board.legal_moves().iter().filter(|mov| mov.from == from) {
+            let en_passant = mov.piece == Piece::Pawn && mov.from.letter != mov.to.letter;
+            highlights[mov.to.digit][mov.to.letter] =
+                match board.get(mov.to).is_some() || en_passant {
+                    true => Some(Highlight::Capture),
+                    false => Some(Highlight::Quiet),
+                };
         }
-        println!();
     }
-    println!("    A  B  C  D  E  F  G  H ");
+    if let Some(sq) = view.cursor {
+        highlights[sq.digit][sq.letter] = Some(Highlight::Cursor);
+    }
+    highlights
 }
 
-fn print_player_to_move(team: &Team) {
-    print!(
-        "{}",
-        color_team_background(
-            team,
-            &color_team(team, &format!("    {} to Move    ", team))
-        )
-    );
+/// One line per rank: the side to move on top, the captured pieces at the bottom and as many of
+/// the latest moves as fit in between.
+fn side_column(board: &Board, theme: &Theme) -> Vec<String> {
+    let mut column = vec![String::new(); SIZE];
+    column[0] = match theme.palette {
+        Some(_) => player_to_move(&board.turn_order).to_string(),
+        None => format!("    {} to Move    ", board.turn_order),
+    };
+    column[SIZE - 2] = captured(board, theme, Team::White);
+    column[SIZE - 1] = captured(board, theme, Team::Black);
+
+    let pairs = board
+        .history
+        .len(Team::White)
+        .max(board.history.len(Team::Black));
+    let first = pairs.saturating_sub(SIZE - 3);
+    for (row, idx) in (first..pairs).enumerate() {
+        let [w, b] = board
+            .history
+            .tuple(idx)
+            .map(|mov| mov.and_then(|mov| mov.label.clone()).unwrap_or_default());
+        column[row + 1] = format!("   {}. {} {}", idx + 1, pad_string(w, 7), pad_string(b, 7));
+    }
+    column
+}
+
+/// The pieces `team` has taken, and its material lead if it is ahead.
+fn captured(board: &Board, theme: &Theme, team: Team) -> String {
+    let pieces: Vec<String> = board
+        .captures
+        .by(team)
+        .into_iter()
+        .map(|piece| theme.glyph(Entity::new(piece, team.not())))
+        .collect();
+    let lead = match team {
+        Team::White => board.evaluation(),
+        Team::Black => -board.evaluation(),
+    };
+    let mut line = format!("   {} took: {}", team, pieces.join(" "));
+    if lead > 0 {
+        line.push_str(&format!("  +{}", lead));
+    }
+    line
+}
+
+fn player_to_move(team: &Team) -> colored::ColoredString {
+    color_team_background(
+        team,
+        &color_team(team, &format!("    {} to Move    ", team)),
+    )
+}
+
+pub fn print_result(result: &str) {
+    let msg = format!("        {}        ", result);
+    println!("{}", msg.black().on_yellow());
 }
 
 pub fn print_error(err: Error) {
@@ -97,7 +206,132 @@ fn color_team_background_rev(team: &Team, label: &str) -> colored::ColoredString
 
 fn pad_string(mut string: String, len: usize) -> String {
     while string.len() < len {
-        string.push_str(" ");
+        string.push(' ');
     }
     string
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute::execute;
+
+    /// The rendered lines without colors.
+    fn plain(board: &Board, perspective: Team) -> Vec<String> {
+        let view = View {
+            perspective,
+            ..View::default()
+        };
+        let mut lines = Vec::new();
+        for line in render(board, &view).lines() {
+            let mut plain = String::new();
+            let mut escape = false;
+            for ch in line.chars() {
+                match ch {
+                    '\u{1b}' => escape = true,
+                    'm' if escape => escape = false,
+                    _ if escape => (),
+                    _ => plain.push(ch),
+                }
+            }
+            lines.push(plain);
+        }
+        lines
+    }
+
+    #[test]
+    fn test_render_from_white() {
+        let lines = plain(&Board::new(), Team::White);
+        assert!(lines[0].starts_with(" 8  R  N  B  Q  K  B  N  R "));
+        assert!(lines[0].ends_with("White to Move    "));
+        assert!(lines[7].starts_with(" 1  R  N  B  Q  K  B  N  R "));
+        assert_eq!(lines[8], "    A  B  C  D  E  F  G  H ");
+    }
+
+    #[test]
+    fn test_render_from_black() {
+        let mut board = Board::new();
+        execute(&mut board, "e4".chars()).unwrap();
+        let white = plain(&board, Team::White);
+        let black = plain(&board, Team::Black);
+        assert!(black[0].starts_with(" 1  R  N  B  K  Q  B  N  R "));
+        assert!(black[4].starts_with(" 5           "));
+        assert!(black[3].starts_with(" 4           P "));
+        assert!(black[7].starts_with(" 8  R  N  B  K  Q  B  N  R "));
+        assert_eq!(black[8], "    H  G  F  E  D  C  B  A ");
+        // The column next to the board does not move.
+        for (w, b) in white.iter().zip(black.iter()).take(SIZE) {
+            assert_eq!(w[27..], b[27..]);
+        }
+        assert!(black[0].ends_with("Black to Move    "));
+        assert!(black[1].starts_with(" 2  P  P  P     P  P  P  P    1. e4"));
+    }
+
+    #[test]
+    fn test_render_latest_moves() {
+        let mut board = Board::new();
+        for san in [
+            "Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1", "Ng8",
+            "Nf3", "Nf6", "e4",
+        ] {
+            execute(&mut board, san.chars()).unwrap();
+        }
+        let lines = plain(&board, Team::White);
+        assert!(lines[1].contains("   4. Ng1"));
+        assert!(lines[5].contains("   8. e4"));
+        assert!(lines[6].ends_with("   White took: "));
+        assert!(lines[7].ends_with("   Black took: "));
+    }
+
+    #[test]
+    fn test_render_ascii() {
+        let view = View {
+            theme: Theme::ascii(),
+            ..View::default()
+        };
+        let out = render(&Board::new(), &view);
+        assert!(!out.contains('\u{1b}'));
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], " 8  r  n  b  q  k  b  n  r     White to Move    ");
+        assert!(lines[3].starts_with(" 5  .  .  .  .  .  .  .  . "));
+    }
+
+    #[test]
+    fn test_highlights() {
+        let mut board = Board::new();
+        for san in ["e4", "d5", "Bb5+"] {
+            execute(&mut board, san.chars()).unwrap();
+        }
+        let view = View {
+            theme: Theme::ascii(),
+            selected: Sq::notation("d5").ok(),
+            ..View::default()
+        };
+        let lines: Vec<String> = render(&board, &view).lines().map(String::from).collect();
+        // The Bishop came from f1 to b5 and checks the King on e8, which the Pawn can't help.
+        assert!(lines[0].starts_with(" 8  r  n  b  q !k! b  n  r "));
+        assert!(lines[3].starts_with(" 5  . [B] .  p  .  .  .  . "));
+        assert!(lines[7].starts_with(" 1  R  N  B  Q  K [.] N  R "));
+
+        // Out of check, the Pawn can push or take.
+        execute(&mut board, "c6".chars()).unwrap();
+        execute(&mut board, "Nc3".chars()).unwrap();
+        let lines: Vec<String> = render(&board, &view).lines().map(String::from).collect();
+        assert!(lines[4].starts_with(" 4  .  .  .  * >P< .  .  . "));
+    }
+
+    #[test]
+    fn test_captured_panel() {
+        let mut board = Board::new();
+        for san in ["e4", "d5", "exd5", "Qxd5", "Nc3", "Qxg2", "Bxg2"] {
+            execute(&mut board, san.chars()).unwrap();
+        }
+        let view = View {
+            theme: Theme::ascii(),
+            ..View::default()
+        };
+        let lines: Vec<String> = render(&board, &view).lines().map(String::from).collect();
+        assert!(lines[6].ends_with("   White took: p q  +9"));
+        assert!(lines[7].ends_with("   Black took: P P"));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::colored::{Color, Colorize};
+use crate::{Entity, Piece, Team};
+use std::env;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+
+/// How pieces are drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Glyphs {
+    /// SAN letters, e.g. "N" for a Knight.
+    Letters,
+    /// Chess figurines, hollow for White and solid for Black.
+    Unicode,
+}
+
+/// Colors of the squares and the pieces on them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub light: Color,
+    pub dark: Color,
+    pub white: Color,
+    pub black: Color,
+    /// Background of the squares the last move went from and to.
+    pub last_move: Color,
+    /// Background of a King in check.
+    pub check: Color,
+    /// Background of the squares a selected piece can move to.
+    pub target: Color,
+}
+
+const LAST_MOVE: Color = Color::TrueColor {
+    r: 205,
+    g: 210,
+    b: 106,
+};
+const CHECK: Color = Color::BrightRed;
+const TARGET: Color = Color::BrightCyan;
+
+/// Why a square stands out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    LastMove,
+    Check,
+    /// The selected piece can move here.
+    Quiet,
+    /// The selected piece can capture here.
+    Capture,
+    /// Where the keyboard cursor is.
+    Cursor,
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    /// A named palette, or custom colors as "light,dark" or "light,dark,white,black".
+    fn from_str(input: &str) -> Result<Palette, Self::Err> {
+        let palette = match input {
+            "classic" => Palette {
+                light: Color::Magenta,
+                dark: Color::Green,
+                white: Color::White,
+                black: Color::Black,
+                last_move: LAST_MOVE,
+                check: CHECK,
+                target: TARGET,
+            },
+            "wood" => Palette {
+                light: Color::TrueColor {
+                    r: 240,
+                    g: 217,
+                    b: 181,
+                },
+                dark: Color::TrueColor {
+                    r: 181,
+                    g: 136,
+                    b: 99,
+                },
+                white: Color::BrightWhite,
+                black: Color::Black,
+                last_move: LAST_MOVE,
+                check: CHECK,
+                target: TARGET,
+            },
+            // Black on white and light grey, the teams tell apart by glyph.
+            "high-contrast" => Palette {
+                light: Color::BrightWhite,
+                dark: Color::White,
+                white: Color::Black,
+                black: Color::Black,
+                last_move: Color::BrightYellow,
+                check: Color::BrightRed,
+                target: Color::BrightCyan,
+            },
+            // Blue and orange stay distinct for the common kinds of color blindness.
+            "colorblind" => Palette {
+                light: Color::TrueColor {
+                    r: 230,
+                    g: 159,
+                    b: 0,
+                },
+                dark: Color::TrueColor {
+                    r: 0,
+                    g: 114,
+                    b: 178,
+                },
+                white: Color::BrightWhite,
+                black: Color::Black,
+                last_move: Color::TrueColor {
+                    r: 240,
+                    g: 228,
+                    b: 66,
+                },
+                check: Color::TrueColor {
+                    r: 213,
+                    g: 94,
+                    b: 0,
+                },
+                target: Color::TrueColor {
+                    r: 86,
+                    g: 180,
+                    b: 233,
+                },
+            },
+            custom => {
+                let colors = custom
+                    .split(',')
+                    .map(|name| Color::from_str(name.trim()))
+                    .collect::<Result<Vec<Color>, ()>>()
+                    .map_err(|_| {
+                        format!(
+                            "unknown palette '{}', expected classic, wood, high-contrast, \
+                             colorblind or colors as light,dark[,white,black]",
+                            custom
+                        )
+                    })?;
+                match colors.as_slice() {
+                    [light, dark] => Palette {
+                        light: *light,
+                        dark: *dark,
+                        white: Color::White,
+                        black: Color::Black,
+                        last_move: LAST_MOVE,
+                        check: CHECK,
+                        target: TARGET,
+                    },
+                    [light, dark, white, black] => Palette {
+                        light: *light,
+                        dark: *dark,
+                        white: *white,
+                        black: *black,
+                        last_move: LAST_MOVE,
+                        check: CHECK,
+                        target: TARGET,
+                    },
+                    _ => return Err(format!("palette '{}' needs 2 or 4 colors", custom)),
+                }
+            }
+        };
+        Ok(palette)
+    }
+}
+
+/// How the board is drawn. Without a palette nothing but plain text is printed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub glyphs: Glyphs,
+    pub palette: Option<Palette>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            glyphs: Glyphs::Letters,
+            palette: Palette::from_str("classic").ok(),
+        }
+    }
+}
+
+impl Theme {
+    /// Plain ASCII, for pipes, files and `NO_COLOR`.
+    pub fn ascii() -> Self {
+        Theme {
+            glyphs: Glyphs::Letters,
+            palette: None,
+        }
+    }
+
+    /// The theme named by `--theme` (letters, unicode or ascii) in the `--palette` colors.
+    /// Without a name, plain ASCII is picked when stdout is not a terminal or `NO_COLOR` is set,
+    /// and `NO_COLOR` always drops the colors.
+    pub fn select(name: Option<&str>, palette: &str) -> io::Result<Self> {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let glyphs = match name {
+            None if no_color || !io::stdout().is_terminal() => return Ok(Theme::ascii()),
+            None | Some("letters") => Glyphs::Letters,
+            Some("unicode") => Glyphs::Unicode,
+            Some("ascii") => return Ok(Theme::ascii()),
+            Some(other) => {
+                let msg = format!(
+                    "unknown theme '{}', expected letters, unicode or ascii",
+                    other
+                );
+                return Err(io::Error::other(msg));
+            }
+        };
+        let palette = Palette::from_str(palette).map_err(io::Error::other)?;
+        let palette = match no_color {
+            true => None,
+            false => Some(palette),
+        };
+        Ok(Theme { glyphs, palette })
+    }
+
+    /// A single character for the piece. Letters are lower case for Black when colors can't
+    /// tell the teams apart.
+    pub fn glyph(&self, entity: Entity) -> String {
+        match self.glyphs {
+            Glyphs::Unicode => figurine(entity).to_string(),
+            Glyphs::Letters => {
+                let letter: &str = entity.kind.into();
+                let same_colors = self
+                    .palette
+                    .as_ref()
+                    .is_none_or(|palette| palette.white == palette.black);
+                match (entity.team, same_colors) {
+                    (Team::Black, true) => letter.to_lowercase(),
+                    _ => letter.to_string(),
+                }
+            }
+        }
+    }
+
+    /// A square three characters wide, holding the piece or nothing. Moves of a selected piece
+    /// are marked in every theme, captures differently from quiet moves, so they don't depend on
+    /// telling colors apart. Without colors, the last move and a check are marked too.
+    pub fn square(
+        &self,
+        entity: Option<Entity>,
+        dark: bool,
+        highlight: Option<Highlight>,
+    ) -> String {
+        let glyph = match entity {
+            Some(entity) => self.glyph(entity),
+            None => match (&self.palette, highlight) {
+                (_, Some(Highlight::Quiet)) => "*".to_string(),
+                (_, Some(Highlight::Capture)) => "x".to_string(),
+                (None, _) => ".".to_string(),
+                (Some(_), _) => " ".to_string(),
+            },
+        };
+        let [left, right] = match (&self.palette, highlight) {
+            (_, Some(Highlight::Capture)) => ['>', '<'],
+            (_, Some(Highlight::Cursor)) => ['(', ')'],
+            (None, Some(Highlight::LastMove)) => ['[', ']'],
+            (None, Some(Highlight::Check)) => ['!', '!'],
+            _ => [' ', ' '],
+        };
+        match &self.palette {
+            None => format!("{}{}{}", left, glyph, right),
+            Some(palette) => {
+                let background = match highlight {
+                    Some(Highlight::LastMove) => palette.last_move,
+                    Some(Highlight::Check) => palette.check,
+                    Some(Highlight::Quiet) | Some(Highlight::Capture) => palette.target,
+                    Some(Highlight::Cursor) | None if dark => palette.dark,
+                    Some(Highlight::Cursor) | None => palette.light,
+                };
+                let glyph = match entity {
+                    Some(entity) => match entity.team {
+                        Team::White => glyph.color(palette.white),
+                        Team::Black => glyph.color(palette.black),
+                    },
+                    None => glyph.color(palette.black),
+                };
+                format!("{}{}{}", left, glyph, right)
+                    .on_color(background)
+                    .to_string()
+            }
+        }
+    }
+}
+
+fn figurine(entity: Entity) -> char {
+    match (entity.team, entity.kind) {
+        (Team::White, Piece::King) => '♔',
+        (Team::White, Piece::Queen) => '♕',
+        (Team::White, Piece::Rook) => '♖',
+        (Team::White, Piece::Bishop) => '♗',
+        (Team::White, Piece::Knight) => '♘',
+        (Team::White, Piece::Pawn) => '♙',
+        (Team::Black, Piece::King) => '♚',
+        (Team::Black, Piece::Queen) => '♛',
+        (Team::Black, Piece::Rook) => '♜',
+        (Team::Black, Piece::Bishop) => '♝',
+        (Team::Black, Piece::Knight) => '♞',
+        (Team::Black, Piece::Pawn) => '♟',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palettes() {
+        for name in ["classic", "wood", "high-contrast", "colorblind"] {
+            assert!(Palette::from_str(name).is_ok(), "{}", name);
+        }
+        let custom = Palette::from_str("white, blue").unwrap();
+        assert_eq!(custom.dark, Color::Blue);
+        assert_eq!(
+            Palette::from_str("white,blue,yellow,red").unwrap().black,
+            Color::Red
+        );
+        assert!(Palette::from_str("white").is_err());
+        assert!(Palette::from_str("rainbow").is_err());
+    }
+
+    #[test]
+    fn test_glyphs() {
+        let knight = Entity::new(Piece::Knight, Team::Black);
+        assert_eq!(Theme::ascii().glyph(knight), "n");
+        assert_eq!(Theme::default().glyph(knight), "N");
+        let unicode = Theme {
+            glyphs: Glyphs::Unicode,
+            palette: None,
+        };
+        assert_eq!(unicode.glyph(knight), "♞");
+        let high_contrast = Theme {
+            glyphs: Glyphs::Letters,
+            palette: Palette::from_str("high-contrast").ok(),
+        };
+        assert_eq!(high_contrast.glyph(knight), "n");
+    }
+
+    #[test]
+    fn test_ascii_has_no_escape_codes() {
+        let theme = Theme::ascii();
+        let queen = Some(Entity::new(Piece::Queen, Team::White));
+        assert_eq!(theme.square(None, true, None), " . ");
+        assert_eq!(theme.square(queen, false, None), " Q ");
+        assert_eq!(theme.square(queen, false, Some(Highlight::LastMove)), "[Q]");
+        assert_eq!(theme.square(queen, false, Some(Highlight::Capture)), ">Q<");
+        assert_eq!(theme.square(None, false, Some(Highlight::Quiet)), " * ");
+        assert_eq!(theme.square(None, false, Some(Highlight::Capture)), ">x<");
+    }
+
+    #[test]
+    fn test_select() {
+        assert_eq!(
+            Theme::select(Some("ascii"), "classic").unwrap(),
+            Theme::ascii()
+        );
+        assert!(Theme::select(Some("fancy"), "classic").is_err());
+        assert!(Theme::select(Some("letters"), "rainbow").is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use crate::input;
-use crate::{Board, KingStatus, OptSq, Piece, Sq, Team};
-use std::io::{Error, ErrorKind, Result};
+use crate::{Board, KingStatus, OptSq, Piece, Sq};
+use std::io::{Error, Result};
 use std::str::Chars;
 
 pub mod bishop;
@@ -20,7 +20,7 @@ macro_rules! run {
                 match execute(&mut $board, $x.chars()) {
                     Ok(_) => (),
                     Err(e) => {
-                        crate::display::present(&$board);
+                        $crate::display::present_with(&$board, &Default::default());
                         panic!("{} failed with error: '{}'", $x, e);
                     },
                 };
@@ -31,10 +31,7 @@ macro_rules! run {
 
 fn locator(board: &Board, to: Sq, from: OptSq, piece: char) -> Option<Sq> {
     match Piece::from_char(piece) {
-        Some(p) => match p.get_locate()(&board, to, from, board.turn_order, p) {
-            Some(t) => Some(t),
-            None => None,
-        },
+        Some(p) => p.get_locate()(board, to, from, board.turn_order, p),
         None => None,
     }
 }
@@ -42,10 +39,11 @@ fn locator(board: &Board, to: Sq, from: OptSq, piece: char) -> Option<Sq> {
 pub enum EResult {
     Ok,
     Stalemate,
+    #[allow(dead_code)]
     Checkmate,
 }
 
-pub fn execute(mut board: &mut Board, input: Chars) -> Result<EResult> {
+pub fn execute(board: &mut Board, input: Chars) -> Result<EResult> {
     let str_input: &str = input.as_str();
     let mut input = input.rev();
     let king_status: KingStatus = str_input.into();
@@ -53,11 +51,11 @@ pub fn execute(mut board: &mut Board, input: Chars) -> Result<EResult> {
     // FIXME: use rev chars.
     if str_input.contains('=') {
         // piece promotion.
-        special::promote(&mut board, &mut input)?;
+        special::promote(board, &mut input)?;
         return Ok(EResult::Ok);
     } else if str_input.starts_with("O-O") {
         // castling
-        special::castle(&mut board, str_input)?;
+        special::castle(board, str_input)?;
         return Ok(EResult::Ok);
     }
 
@@ -91,13 +89,13 @@ pub fn execute(mut board: &mut Board, input: Chars) -> Result<EResult> {
 
                 hold = match locator(board, target, from, next) {
                     Some(sq) => Some(sq),
-                    None => pawn::locate(&board, target, from, board.turn_order, Piece::Pawn),
+                    None => pawn::locate(board, target, from, board.turn_order, Piece::Pawn),
                 };
             }
         },
         None => {
             // pawn move
-            if let Some(sq) = pawn::locate(&board, target, from, board.turn_order, Piece::Pawn) {
+            if let Some(sq) = pawn::locate(board, target, from, board.turn_order, Piece::Pawn) {
                 hold = Some(sq)
             }
         }
@@ -108,7 +106,7 @@ pub fn execute(mut board: &mut Board, input: Chars) -> Result<EResult> {
             Ok(()) => (),
             Err(e) => return Err(e),
         },
-        None => return Err(Error::new(ErrorKind::Other, "nothing to move")),
+        None => return Err(Error::other("nothing to move")),
     };
 
     let did_i_check_them = board.in_check(board.turn_order);
@@ -119,8 +117,7 @@ pub fn execute(mut board: &mut Board, input: Chars) -> Result<EResult> {
         KingStatus::Safe => {
             // Check for stalemate
             if did_i_check_them {
-                return Err(Error::new(
-                    ErrorKind::Other,
+                return Err(Error::other(
                     "Your move went through, but you checked when not expected to",
                 ));
             } else if board.stalemate(board.turn_order) {
@@ -129,8 +126,7 @@ pub fn execute(mut board: &mut Board, input: Chars) -> Result<EResult> {
         }
         KingStatus::Mate => {
             if !did_i_check_them || !board.check_mate(board.turn_order) {
-                return Err(Error::new(
-                    ErrorKind::Other,
+                return Err(Error::other(
                     "Your move went through, but your move did not checkmate",
                 ));
             }
@@ -138,8 +134,7 @@ pub fn execute(mut board: &mut Board, input: Chars) -> Result<EResult> {
         _ => {
             if !did_i_check_them {
                 // Print some debug moves.
-                return Err(Error::new(
-                    ErrorKind::Other,
+                return Err(Error::other(
                     "Your move went through, but your move did not check",
                 ));
             }
@@ -152,7 +147,7 @@ pub fn execute(mut board: &mut Board, input: Chars) -> Result<EResult> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Entity;
+    use crate::{Entity, Team};
     #[test]
     fn test_macro() {
         let mut board = Board::new();
@@ -162,15 +157,13 @@ mod tests {
     fn test_in_check() {
         let mut board = Board::new();
         run!(board, "e4", "f5", "Qh5+");
-        assert_eq!(
+        assert!(
             board.in_check(Team::Black),
-            true,
             "Black should be in check after Qh5+"
         );
         run!(board, "g6", "Qxg6+", "hxg6", "f4", "Rh3", "d4", "Re3+");
-        assert_eq!(
+        assert!(
             board.in_check(Team::White),
-            true,
             "White should be in check after Re3+"
         );
     }
@@ -178,9 +171,8 @@ mod tests {
     fn test_in_check2() {
         let mut board = Board::new();
         run!(board, "d4", "Nf6", "Nc3", "d5", "Nf3", "e6", "e3", "c5", "Bb5+");
-        assert_eq!(
+        assert!(
             board.in_check(Team::Black),
-            true,
             "Black should be in check after Bb5+"
         );
         match execute(&mut board, "a6".chars()) {
@@ -191,10 +183,10 @@ mod tests {
     #[test]
     fn test_in_fake_mate() {
         let mut board = Board::new();
-        match execute(&mut board, "e4#".chars()) {
-            Ok(_) => panic!(format!("e4 is not mate you dummy")),
-            Err(_) => (),
-        }
+        assert!(
+            execute(&mut board, "e4#".chars()).is_err(),
+            "e4 is not mate you dummy"
+        );
     }
     #[test]
     fn test_in_check3() {
@@ -207,7 +199,7 @@ mod tests {
             "Qe6+"
         );
         let res = board.in_check(Team::Black);
-        assert_eq!(res, true, "Black should be in check after Qe6+");
+        assert!(res, "Black should be in check after Qe6+");
         match execute(&mut board, "Kh8".chars()) {
             Ok(_r) => (),
             Err(e) => panic!("{:?}", e),
@@ -276,7 +268,7 @@ mod tests {
             "{:?} pawn at {} should be able to capture Black pawn at {} ({:?})",
             team, sq, black_sq, translations
         );
-        assert!(translations.contains(&Sq::new(1, 1)), msg);
+        assert!(translations.contains(&Sq::new(1, 1)), "{}", msg);
     }
 
     #[test]
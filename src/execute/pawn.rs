@@ -60,9 +60,9 @@ pub fn get_translations<S: SqLike>(board: &Board, from: Sq, team: Team, piece: P
 pub fn locate(board: &Board, to: Sq, from: OptSq, team: Team, piece: Piece) -> Option<Sq> {
     // Locate suitable pawn
     for x in 0..8 {
-        let from = Sq::new(x, from.letter.or(Some(to.letter)).unwrap());
+        let from = Sq::new(x, from.letter.unwrap_or(to.letter));
         if let Some(_entity) = board.find(from, Some(&board.turn_order), Some(piece)) {
-            if get_translations(&board, from, team, piece).contains(&to) {
+            if get_translations(board, from, team, piece).contains(&to) {
                 return Some(from);
             }
         }
@@ -74,6 +74,7 @@ pub fn locate(board: &Board, to: Sq, from: OptSq, team: Team, piece: Piece) -> O
 mod tests {
     use crate::execute::*;
     use crate::run;
+    use crate::Team;
     #[test]
     fn test_many_generic_pawn_moves() {
         let mut board = Board::new();
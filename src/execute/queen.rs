@@ -9,7 +9,7 @@ pub fn get_translations<S: SqLike>(board: &Board, from: Sq, team: Team, piece: P
 
 pub fn locate(board: &Board, to: Sq, from: OptSq, team: Team, piece: Piece) -> Option<Sq> {
     for locate in &[bishop::locate, rook::locate] {
-        if let Some(sq) = locate(&board, to, from, team, piece) {
+        if let Some(sq) = locate(board, to, from, team, piece) {
             return Some(sq);
         };
     }
@@ -1,87 +1,107 @@
 use crate::input::chars_to_sq;
 use crate::{Board, Entity, OptSq, Piece, Sq, Team};
-use std::io::{Error, ErrorKind, Result};
+use std::io::{Error, Result};
 use std::iter::Rev;
 use std::str::Chars;
 
-pub fn castle(mut board: &mut Board, str: &str) -> Result<()> {
+/// Castles short for "O-O" and long for "O-O-O". The King goes to the g or c-file and the Rook
+/// next to it, from wherever they start in Chess960.
+pub fn castle(board: &mut Board, str: &str) -> Result<()> {
     let team = board.turn_order;
     let rank = match team {
         Team::White => 0,
         Team::Black => 7,
     };
     let short: bool = !str.starts_with("O-O-O");
+    let files = board.castling.files;
     let [king_file, rook_from_file, rook_to_file] = match short {
-        true => [6, 7, 5],
-        false => [2, 0, 3],
+        true => [6, files.short, 5],
+        false => [2, files.long, 3],
     };
 
-    // TODO: Validate that the pieces haven't moved previously.
-    match board.find(
-        Sq::new(rank, rook_from_file),
-        Some(&board.turn_order),
-        Some(Piece::Rook),
-    ) {
-        Some(_rook_sq) => {
-            let king_from = Sq::new(rank, 4);
-            let king_to = Sq::new(rank, king_file);
-            let rook_to = Sq::new(rank, rook_to_file);
-            match board.find(king_from, Some(&team), Some(Piece::King)) {
-                Some(_king_sq) => {
-                    // Check that the to position is not occupied.
-                    let mut other = board.clone();
-                    match other.get(king_to) {
-                        Some(ent) => {
-                            let msg = format!(
-                                "Castling blocked, King square {} occupied by {:?}",
-                                king_to, ent
-                            );
-                            return Err(Error::new(ErrorKind::Other, msg));
-                        }
-                        None => match other.get(rook_to) {
-                            Some(ent) => {
-                                let msg = format!(
-                                    "Castling blocked, Rook square {} occupied by {:?}",
-                                    rook_to, ent
-                                );
-                                return Err(Error::new(ErrorKind::Other, msg));
-                            }
-                            None => {
-                                let ent = Entity::new(Piece::King, team);
-                                // Pretend that the King and Rook spot are both occupied by a King, to see that we cannot castle into check.
-                                other.place(king_to, ent);
-                                other.place(rook_to, ent);
-                                if other.in_check(team) {
-                                    return Err(Error::new(
-                                        ErrorKind::Other,
-                                        "Cannot castle into check",
-                                    ));
-                                }
-                            }
-                        },
-                    }
-
-                    board.translate(king_from, king_to)?;
-                    board.turn_order = team;
-                    board.translate(Sq::new(rank, rook_from_file), rook_to)?;
-                    board.history.push(
-                        team,
-                        Piece::King,
-                        king_from,
-                        king_to,
-                        Some(str.to_string()),
-                    );
-
-                    // Revoke the team from castling again.
-                    board.castling.revoke(team);
-
-                    Ok(())
-                }
-                None => Err(Error::new(ErrorKind::Other, "no King to castle with")),
-            }
+    let availability = board.castling.get(team);
+    if short && !availability.short || !short && !availability.long {
+        let msg = format!("{} can no longer castle to that side", team);
+        return Err(Error::other(msg));
+    }
+    let king_from = Sq::new(rank, files.king);
+    let king_to = Sq::new(rank, king_file);
+    let rook_from = Sq::new(rank, rook_from_file);
+    let rook_to = Sq::new(rank, rook_to_file);
+    if board
+        .find(rook_from, Some(&team), Some(Piece::Rook))
+        .is_none()
+    {
+        return Err(Error::other("no Rook to castle with"));
+    }
+    if board
+        .find(king_from, Some(&team), Some(Piece::King))
+        .is_none()
+    {
+        return Err(Error::other("no King to castle with"));
+    }
+
+    // Every square the King or Rook crosses or lands on is empty, but for the two of them.
+    let squares = [king_from, king_to, rook_from, rook_to];
+    let low = squares.iter().map(|sq| sq.letter).min().unwrap_or(0);
+    let high = squares.iter().map(|sq| sq.letter).max().unwrap_or(0);
+    for file in low..=high {
+        let sq = Sq::new(rank, file);
+        if sq == king_from || sq == rook_from {
+            continue;
+        }
+        if let Some(ent) = board.get(sq) {
+            let msg = format!("Castling blocked, {} occupied by {:?}", sq, ent);
+            return Err(Error::other(msg));
         }
-        None => Err(Error::new(ErrorKind::Other, "no Rook to castle with")),
     }
+
+    // Pretend that every square the King passes is occupied by a King, to see that we cannot
+    // castle out of or through check.
+    let passed = files.king.min(king_file)..=files.king.max(king_file);
+    for file in passed {
+        let mut other = board.clone();
+        other.place(Sq::new(rank, file), Entity::new(Piece::King, team));
+        if other.in_check(team) {
+            return Err(Error::other("Cannot castle into check"));
+        }
+    }
+
+    // Castling is a single move, so the clocks should only tick once.
+    let mut other = board.clone();
+    other.board[rank][files.king] = None;
+    other.board[rank][rook_from_file] = None;
+    other.place(king_to, Entity::new(Piece::King, team));
+    other.place(rook_to, Entity::new(Piece::Rook, team));
+    // In Chess960 the Rook can stand between the King and a checking piece.
+    if other.in_check(team) {
+        return Err(Error::other("Cannot castle into check"));
+    }
+    other.turn_order = team.not();
+    other.en_passant_target_square = None;
+    other.halfmove += 1;
+    if team == Team::Black {
+        other.fullmove += 1;
+    }
+    // Chess960 writes castling as the King taking its Rook, since it may not move otherwise.
+    let to = match other.castling.chess960 {
+        true => rook_from,
+        false => king_to,
+    };
+    other.history.push(
+        team,
+        Piece::King,
+        king_from,
+        to,
+        None,
+        Some(str.to_string()),
+    );
+
+    // Revoke the team from castling again.
+    other.castling.revoke(team);
+    *board = other;
+
+    Ok(())
 }
 
 /*
@@ -90,28 +110,22 @@ cxd8=Q+
 cxd8=Q
 d8=Q
 */
-pub fn promote(board: &mut Board, mut chars: &mut Rev<Chars>) -> Result<()> {
+pub fn promote(board: &mut Board, chars: &mut Rev<Chars>) -> Result<()> {
     let piece = match Piece::from_char(chars.next().unwrap()) {
         Some(p) => p,
         None => match Piece::from_char(chars.next().unwrap()) {
             Some(p) => p,
-            None => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    "promotions with check, invalid format",
-                ))
-            }
+            None => return Err(Error::other("promotions with check, invalid format")),
         },
     };
 
     if chars.next().unwrap() != '=' {
-        return Err(Error::new(
-            ErrorKind::Other,
+        return Err(Error::other(
             "invalid promotion chars, second to last char must be '='",
         ));
     }
 
-    let target = chars_to_sq(&mut chars)?;
+    let target = chars_to_sq(chars)?;
     let mut opt_sq = OptSq::new();
     if let Some(ch) = chars.next() {
         if ch == 'x' {
@@ -124,7 +138,7 @@ pub fn promote(board: &mut Board, mut chars: &mut Rev<Chars>) -> Result<()> {
     } else if target.digit == 0 {
         opt_sq.digit = Some(1);
     } else {
-        return Err(Error::new(ErrorKind::Other, "not in position to promote"));
+        return Err(Error::other("not in position to promote"));
     }
 
     let from = target.union(opt_sq);
@@ -136,8 +150,25 @@ pub fn promote(board: &mut Board, mut chars: &mut Rev<Chars>) -> Result<()> {
         from, target, piece
     );
 
-    board.translate(from, target)?;
-    board.place(target, Entity::new(piece, board.turn_order.not()));
+    let team = board.turn_order;
+    if board.find(from, Some(&team), Some(Piece::Pawn)).is_none() {
+        let msg = format!("no Pawn at {} to promote", from);
+        return Err(Error::other(msg));
+    }
+
+    let mut other = board.clone();
+    let mut label = other.translate(from, target)?;
+    if other.in_check(team) {
+        let msg = format!("illegal move for {}, places yourself in check.", team);
+        return Err(Error::other(msg));
+    }
+    other.place(target, Entity::new(piece, team));
+    label.push('=');
+    label.push_str(piece.to_str());
+    other
+        .history
+        .push(team, Piece::Pawn, from, target, Some(piece), Some(label));
+    *board = other;
 
     Ok(())
 }
@@ -148,7 +179,8 @@ mod tests {
     use crate::execute::*;
     use crate::input::*;
     use crate::run;
-    use crate::{Board, Entity};
+    use crate::{Board, Entity, Team};
+    use std::convert::TryInto;
     use std::io::Result;
     #[test]
     fn test_short_castle() {
@@ -164,6 +196,7 @@ mod tests {
             piece: Piece::King,
             from: Sq::notation("e1")?,
             to: Sq::notation("g1")?,
+            promotion: None,
             label: Some("O-O".to_string()),
         };
         assert_eq!(
@@ -200,6 +233,33 @@ mod tests {
         );
     }
     #[test]
+    fn test_chess960_castle() {
+        // King on g1, Rooks on b1 and h1.
+        let board: Board = "4k3/8/8/8/8/8/8/1R4KR w HB - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        let short = board.find_move("O-O").unwrap();
+        assert_eq!(short.uci(), "g1h1");
+        let long = board.find_move("g1b1").unwrap();
+        assert_eq!(long.label.as_deref(), Some("O-O-O"));
+
+        let mut castled = board.clone();
+        castled.apply(&long).unwrap();
+        assert_eq!(castled.fen(), "4k3/8/8/8/8/8/8/2KR3R b - - 1 1");
+        let mut castled = board.clone();
+        castled.apply(&short).unwrap();
+        assert_eq!(castled.fen(), "4k3/8/8/8/8/8/8/1R3RK1 b - - 1 1");
+
+        // Long castling passes e1, which the Rook on e8 attacks.
+        let checked: Board = "4r1k1/8/8/8/8/8/8/1R4KR w HB - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        assert!(checked.find_move("O-O-O").is_err());
+        assert!(checked.find_move("O-O").is_ok());
+    }
+    #[test]
     fn test_promotion() {
         let mut board = Board::new();
         run!(board, "a4", "b5", "axb5", "a6", "bxa6", "Bb7", "axb7", "Qc8", "bxc8=Q#");
@@ -207,9 +267,8 @@ mod tests {
             board.get(chars_to_sq(&mut "c8".chars().rev()).unwrap()),
             Some(Entity::new(Piece::Queen, Team::White))
         );
-        assert_eq!(
+        assert!(
             board.check_mate(Team::Black),
-            true,
             "black should be in check mate after bxc8=Q#"
         )
     }
@@ -0,0 +1,132 @@
+use crate::Team;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Chess clock for a "minutes+increment" time control, e.g. "5+3".
+///
+/// Input is read blocking, so a flag can only be seen to fall once the move is in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Clock {
+    remaining: [Duration; 2],
+    increment: Duration,
+    /// When the running side's turn began. A resumed clock waits for the next `start`.
+    #[serde(skip)]
+    started: Option<Instant>,
+}
+
+impl FromStr for Clock {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Clock, Self::Err> {
+        let (minutes, increment) = input.split_once('+').unwrap_or((input, "0"));
+        let invalid = || format!("invalid time control '{}', expected e.g. 5+3", input);
+        let minutes: f64 = minutes.trim().parse().map_err(|_| invalid())?;
+        let increment: f64 = increment.trim().parse().map_err(|_| invalid())?;
+        if minutes <= 0.0 || increment < 0.0 {
+            return Err(invalid());
+        }
+        let base = Duration::from_secs_f64(minutes * 60.0);
+        Ok(Clock {
+            remaining: [base, base],
+            increment: Duration::from_secs_f64(increment),
+            started: None,
+        })
+    }
+}
+
+fn side(team: Team) -> usize {
+    match team {
+        Team::White => 0,
+        Team::Black => 1,
+    }
+}
+
+impl Clock {
+    /// Starts counting for the team to move, unless it is counting already.
+    pub fn start(&mut self) {
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    /// Charges `team` for the move it just finished. Returns false if its flag fell.
+    pub fn stop(&mut self, team: Team) -> bool {
+        let spent = self
+            .started
+            .take()
+            .map_or(Duration::ZERO, |started| started.elapsed());
+        self.charge(team, spent)
+    }
+
+    /// Time `team` has left, not counting the turn in progress.
+    pub fn remaining(&self, team: Team) -> Duration {
+        self.remaining[side(team)]
+    }
+
+    /// Time added after every move.
+    pub fn increment(&self) -> Duration {
+        self.increment
+    }
+
+    /// How long the turn in progress has taken so far.
+    pub fn elapsed(&self) -> Duration {
+        self.started
+            .map_or(Duration::ZERO, |started| started.elapsed())
+    }
+
+    fn charge(&mut self, team: Team, spent: Duration) -> bool {
+        let remaining = &mut self.remaining[side(team)];
+        match remaining.checked_sub(spent) {
+            Some(left) if !left.is_zero() => {
+                *remaining = left + self.increment;
+                true
+            }
+            _ => {
+                *remaining = Duration::ZERO;
+                false
+            }
+        }
+    }
+}
+
+impl fmt::Display for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = |duration: Duration| {
+            let secs = duration.as_secs();
+            format!("{}:{:02}", secs / 60, secs % 60)
+        };
+        write!(
+            f,
+            "White {}  Black {}",
+            format(self.remaining[0]),
+            format(self.remaining[1])
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let clock = Clock::from_str("5+3").unwrap();
+        assert_eq!(clock.remaining, [Duration::from_secs(300); 2]);
+        assert_eq!(clock.increment, Duration::from_secs(3));
+        assert_eq!(
+            Clock::from_str("0.5").unwrap().to_string(),
+            "White 0:30  Black 0:30"
+        );
+        assert!(Clock::from_str("fast").is_err());
+        assert!(Clock::from_str("0+1").is_err());
+    }
+
+    #[test]
+    fn test_increment_and_flag() {
+        let mut clock = Clock::from_str("1+2").unwrap();
+        assert!(clock.charge(Team::White, Duration::from_secs(10)));
+        assert_eq!(clock.to_string(), "White 0:52  Black 1:00");
+        assert!(!clock.charge(Team::Black, Duration::from_secs(60)));
+        assert_eq!(clock.to_string(), "White 0:52  Black 0:00");
+    }
+}
@@ -1,57 +1,201 @@
-use crate::display;
+use crate::board::state::{BoardState, GameState};
+use crate::display::theme::Theme;
 use crate::execute;
 use crate::input;
 use crate::input::pgn::Turn;
 use crate::{Board, Team};
+use std::cell::RefCell;
 use std::convert::TryInto;
 use std::fs;
 use std::io;
+use std::rc::Rc;
 use std::str::FromStr;
 use std::{thread, time};
 
-mod opponent;
+pub mod clock;
+pub mod opponent;
+pub mod screen;
+pub mod session;
+mod timeline;
+use crate::bot::{JsonPlayer, JsonScreen};
+use crate::computer::{Action, Playable};
+use crate::tui::{Tui, TuiPlayer, TuiScreen};
 use crate::Args;
 use opponent::Opponent;
+use screen::{Screen, Terminal};
+use session::{Flow, Session};
 
-pub fn manual_game_loop(args: Args) -> io::Result<()> {
-    let white = Opponent::from_str(&args.white).unwrap().init();
-    let black = Opponent::from_str(&args.black).unwrap().init();
-    let short_dur = time::Duration::from_millis(120);
+/// How boards are drawn, from `--theme` and `--palette`. Turns colors off without a palette.
+pub fn theme(args: &Args) -> io::Result<Theme> {
+    let theme = Theme::select(args.theme.as_deref(), &args.palette)?;
+    if theme.palette.is_none() {
+        colored::control::set_override(false);
+    }
+    Ok(theme)
+}
 
-    let mut board = match args.fen {
-        Some(fen) => fen.try_into()?,
-        None => Board::new(),
+pub fn manual_game_loop(args: Args) -> io::Result<()> {
+    let theme = theme(&args)?;
+    if args.tui && args.json {
+        return Err(io::Error::other("--tui and --json can't be used together"));
+    }
+    // The full screen is taken before the players, so they can read their moves from it.
+    let tui = match args.tui {
+        true => Some(Rc::new(RefCell::new(Tui::new()?))),
+        false => None,
+    };
+    let mut resumed = match &args.resume {
+        Some(path) => Some(Session::resume(path)?),
+        None => None,
+    };
+    // A resumed session brings its own players.
+    let [white, black] = match &resumed {
+        Some(session) => session.names().clone(),
+        None => [args.white.clone(), args.black.clone()],
+    };
+    let init = |name: &str| -> io::Result<Box<dyn Playable>> {
+        let opponent = Opponent::from_str(name).map_err(io::Error::other)?;
+        match (opponent, &tui) {
+            (Opponent::Player, Some(tui)) => Ok(Box::new(TuiPlayer::new(tui.clone()))),
+            (Opponent::Player, None) if args.json => Ok(Box::new(JsonPlayer)),
+            (opponent, _) => opponent.init(&args),
+        }
     };
+    let mut players = [
+        (white.clone(), init(&white)?),
+        (black.clone(), init(&black)?),
+    ];
+    let mut screen: Box<dyn Screen> = match &tui {
+        Some(tui) => Box::new(TuiScreen(tui.clone())),
+        None if args.json => Box::new(JsonScreen::new(io::stdout())),
+        None => Box::new(Terminal),
+    };
+    // Resuming saves back to the same file, unless told otherwise.
+    let autosave = args.save.as_ref().or(args.resume.as_ref());
 
-    display::present(&board);
     loop {
+        let mut session = match resumed.take() {
+            Some(session) => session,
+            None => {
+                let board = match (&args.fen, args.chess960) {
+                    (Some(_), Some(_)) => {
+                        return Err(io::Error::other("--fen and --chess960 don't go together"))
+                    }
+                    (Some(fen), None) => fen.clone().try_into()?,
+                    (None, Some(Some(index))) => Board::chess960(index)?,
+                    (None, Some(None)) => Board::chess960_random(),
+                    (None, None) => Board::new(),
+                };
+                let [(white, _), (black, _)] = &players;
+                let clock = args.time_control.clone();
+                Session::new(board, white, black, humans(&players), clock)
+            }
+        };
+        session.view.theme = theme.clone();
+        let ended = play(&mut players, &mut session, screen.as_mut());
+        if let Some(path) = autosave {
+            session.save(path)?;
+        }
+        match ended? {
+            Ended::Quit => return Ok(()),
+            Ended::Restarted => continue,
+            Ended::Finished if !game_over(&session, screen.as_mut())? => return Ok(()),
+            // Rematch with colors swapped.
+            Ended::Finished => players.swap(0, 1),
+        }
+    }
+}
+
+/// Why `play` stopped.
+enum Ended {
+    Finished,
+    /// A player asked for a new game.
+    Restarted,
+    Quit,
+}
+
+/// The teams played by a person.
+fn humans(players: &[(String, Box<dyn Playable>); 2]) -> Vec<Team> {
+    let [(white, _), (black, _)] = players;
+    [(white, Team::White), (black, Team::Black)]
+        .iter()
+        .filter(|(opponent, _)| Opponent::from_str(opponent) == Ok(Opponent::Player))
+        .map(|(_, team)| *team)
+        .collect()
+}
+
+/// Plays the session until the game is over or a player leaves it.
+fn play(
+    players: &mut [(String, Box<dyn Playable>); 2],
+    session: &mut Session,
+    screen: &mut dyn Screen,
+) -> io::Result<Ended> {
+    let [(_, white), (_, black)] = players;
+    let short_dur = time::Duration::from_millis(120);
+
+    white.new_game(&session.context())?;
+    black.new_game(&session.context())?;
+    screen.present(session)?;
+    while !session.board.state.is_over() {
         thread::sleep(short_dur);
-        let result = match board.turn_order {
-            Team::White => white.action(&mut board),
-            Team::Black => black.action(&mut board),
+        if let Some(clock) = session.clock.as_mut() {
+            clock.start();
+        }
+        let team = session.board.turn_order;
+        let player = match team {
+            Team::White => &mut *white,
+            Team::Black => &mut *black,
+        };
+        let flow = match player.action(&session.context()) {
+            Ok(Action::Move(mov)) => session.play(&mov).map(|_| Flow::Redraw),
+            Ok(Action::Command(command)) => session.command(command),
+            Err(e) => Err(e),
         };
-        match result {
-            Ok(_) => (),
+        match flow {
+            Ok(Flow::Redraw) => (),
+            Ok(Flow::Show(text)) => {
+                screen.show(&text)?;
+                continue;
+            }
+            Ok(Flow::Restart) => return Ok(Ended::Restarted),
+            Ok(Flow::Quit) => return Ok(Ended::Quit),
+            // People get to try again, any other player that fails to move forfeits.
+            Err(e) if !session.is_human(team) => {
+                screen.error(e)?;
+                session.board.state = BoardState::won(team.not(), GameState::Forfeit);
+            }
             Err(e) => {
-                display::print_error(e);
+                screen.error(e)?;
                 continue;
             }
-        };
+        }
         thread::sleep(short_dur);
-        display::present(&board);
+        screen.present(session)?;
         thread::sleep(short_dur);
     }
+    white.game_over(&session.context())?;
+    black.game_over(&session.context())?;
+    Ok(Ended::Finished)
 }
 
-pub fn automatic_game_loop(pgn: String) -> io::Result<()> {
-    let data = fs::read_to_string(pgn)?;
-    let mut pgns = Vec::new();
-
-    for (n, line) in data.lines().enumerate() {
-        if line != "" && !line.starts_with('[') {
-            pgns.push(input::pgn::parse(line));
+/// Shows the result, offers to save the game and asks for a rematch.
+fn game_over(session: &Session, screen: &mut dyn Screen) -> io::Result<bool> {
+    screen.result(session)?;
+    if let Some(path) = screen.save_as()? {
+        match fs::write(&path, session.pgn()?) {
+            Ok(()) => screen.show(&format!("Game saved to {}", path))?,
+            Err(e) => screen.error(e)?,
         }
     }
+    screen.rematch()
+}
+
+pub fn automatic_game_loop(pgn: String) -> io::Result<()> {
+    let data = fs::read_to_string(pgn)?;
+    let pgns: Vec<Vec<Turn>> = input::pgn::games(&data)
+        .into_iter()
+        .map(|game| game.turns)
+        .collect();
     println!("{} game(s) parsed", pgns.len());
     let mut failed_count: usize = 0;
     let mut successful_count: usize = 0;
@@ -87,6 +231,7 @@ pub fn automatic_game_loop(pgn: String) -> io::Result<()> {
     Ok(())
 }
 
+#[allow(dead_code)]
 pub fn legacy_automatic_game_loop(pgn: String) -> io::Result<()> {
     let short_dur = time::Duration::from_millis(20);
     // let long_dur = time::Duration::from_millis(2000);
@@ -101,8 +246,6 @@ pub fn legacy_automatic_game_loop(pgn: String) -> io::Result<()> {
 
     for (n, line) in lines.enumerate() {
         // print!("{}[2J", 27 as char);
-        #[cfg(debug)]
-        meta.push(line);
         // filter meta data.
         if line.starts_with('[') {
             // #[cfg(debug)]
@@ -114,7 +257,7 @@ pub fn legacy_automatic_game_loop(pgn: String) -> io::Result<()> {
             continue;
         }
         // seperator
-        if line == "" {
+        if line.is_empty() {
             continue;
         }
 
@@ -160,8 +303,6 @@ pub fn legacy_automatic_game_loop(pgn: String) -> io::Result<()> {
                             // if !failed_commands.contains(&string) {
                             //     failed_commands.push(string.clone());
                             // }
-                            #[cfg(debug)]
-                            thread::sleep(long_dur);
                             break;
                         }
                     },
@@ -1,33 +1,136 @@
 use crate::computer;
+use crate::computer::book::{Book, BookOptions};
+use crate::computer::search::Limits;
+#[cfg(feature = "syzygy")]
+use crate::tablebase::syzygy::Syzygy;
+use crate::tablebase::Tablebase;
+use crate::Args;
+use std::io;
 use std::str::FromStr;
+use std::time::Duration;
 
+/// Who plays a side, as given to `--white` and `--black`.
 #[derive(Debug, PartialEq)]
 pub enum Opponent {
     Player,
-    Computer,
+    /// "computer", optionally followed by options, e.g. "computer:depth=4,movetime=2000".
+    Computer(Limits),
+    Random,
+    /// The computer playing its book, and random moves after.
+    BookOnly,
+    /// An engine speaking UCI, run from the path after "uci:".
+    Uci(String),
 }
 
+pub const USAGE: &str =
+    "player, computer[:depth=N,movetime=MS,elo=N], random, book-only or uci:<path>";
+
 impl FromStr for Opponent {
-    type Err = ();
+    type Err = String;
 
     fn from_str(input: &str) -> Result<Opponent, Self::Err> {
-        match input {
-            "player" => Ok(Opponent::Player),
-            "computer" => Ok(Opponent::Computer),
-            _ => Err(()),
+        let (name, options) = match input.split_once(':') {
+            Some((name, options)) => (name, Some(options)),
+            None => (input, None),
+        };
+        match (name, options) {
+            ("player", None) => Ok(Opponent::Player),
+            ("computer", None) => Ok(Opponent::Computer(Limits::default())),
+            ("computer", Some(options)) => limits(options).map(Opponent::Computer),
+            ("random", None) => Ok(Opponent::Random),
+            ("book-only", None) => Ok(Opponent::BookOnly),
+            ("uci", Some(path)) if !path.is_empty() => Ok(Opponent::Uci(path.to_string())),
+            ("uci", _) => {
+                Err("uci needs the path to an engine, e.g. uci:/usr/bin/stockfish".to_string())
+            }
+            ("player" | "random" | "book-only", Some(_)) => {
+                Err(format!("{} takes no options", name))
+            }
+            _ => Err(format!("unknown player '{}', expected {}", input, USAGE)),
+        }
+    }
+}
+
+/// Reads comma separated options of the computer, e.g. "depth=4,movetime=2000".
+fn limits(options: &str) -> Result<Limits, String> {
+    let mut limits = Limits::default();
+    for option in options.split(',') {
+        let (key, value) = option
+            .split_once('=')
+            .ok_or_else(|| format!("computer option '{}' needs a value, e.g. depth=4", option))?;
+        let number = |min: u64, max: u64| match value.parse::<u64>() {
+            Ok(n) if (min..=max).contains(&n) => Ok(n),
+            _ => Err(format!(
+                "invalid {} '{}', expected a number from {} to {}",
+                key, value, min, max
+            )),
+        };
+        match key {
+            "depth" => limits.depth = Some(number(1, 32)? as usize),
+            "movetime" => limits.movetime = Some(Duration::from_millis(number(1, u64::MAX)?)),
+            "elo" => {
+                let range = Limits::ELO_RANGE;
+                limits.elo = Some(number(*range.start() as u64, *range.end() as u64)? as u32)
+            }
+            _ => {
+                return Err(format!(
+                    "unknown computer option '{}', expected depth, movetime or elo",
+                    key
+                ))
+            }
         }
     }
+    Ok(limits)
 }
 
 impl Opponent {
-    pub fn init(self) -> Box<dyn computer::Playable> {
-        match self {
+    pub fn init(self, args: &Args) -> io::Result<Box<dyn computer::Playable>> {
+        Ok(match self {
             Opponent::Player => Box::new(computer::player::Player::new()),
-            Opponent::Computer => Box::new(computer::Computer::new()),
-        }
+            Opponent::Computer(limits) => Box::new(self::computer(args)?.with_search(limits)),
+            Opponent::BookOnly => Box::new(self::computer(args)?.book_only()),
+            Opponent::Random => Box::new(computer::Random),
+            Opponent::Uci(path) => Box::new(computer::uci::Uci::spawn(&path)?),
+        })
     }
 }
 
+/// The computer with the book and tablebases given on the command line.
+fn computer(args: &Args) -> io::Result<computer::Computer> {
+    let options = BookOptions {
+        policy: args.book_policy.clone(),
+        depth: args.book_depth,
+    };
+    let mut computer = computer::Computer::with_book(book(args)?, options);
+    if let Some(dir) = &args.tablebases {
+        computer = computer.with_tablebase(Tablebase::open(dir)?);
+    }
+    if let Some(dir) = &args.syzygy {
+        computer = with_syzygy(computer, dir)?;
+    }
+    Ok(computer)
+}
+
+/// The Polyglot book given by `--book`, or the openings of `OPENINGS_FILE`.
+pub fn book(args: &Args) -> io::Result<Book> {
+    Ok(match &args.book {
+        Some(path) => Book::Polyglot(computer::polyglot::Book::open(path)?),
+        None => Book::Openings(computer::read_openings_file(computer::OPENINGS_FILE)?),
+    })
+}
+
+#[cfg(feature = "syzygy")]
+fn with_syzygy(computer: computer::Computer, dir: &str) -> io::Result<computer::Computer> {
+    Ok(computer.with_syzygy(Syzygy::open(dir)?))
+}
+
+#[cfg(not(feature = "syzygy"))]
+fn with_syzygy(_: computer::Computer, _: &str) -> io::Result<computer::Computer> {
+    Err(io::Error::other(
+        "--syzygy needs a build with the syzygy feature",
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -37,10 +140,53 @@ mod tests {
     }
     #[test]
     fn test_parse_computer_from_string() {
-        assert_eq!(Opponent::from_str("computer"), Ok(Opponent::Computer));
+        assert_eq!(
+            Opponent::from_str("computer"),
+            Ok(Opponent::Computer(Limits::default()))
+        );
     }
     #[test]
     fn test_parse_invalid_from_string() {
-        assert_eq!(Opponent::from_str("zebra").is_err(), true);
+        assert!(Opponent::from_str("zebra").is_err());
+    }
+    #[test]
+    fn test_parse_computer_options() {
+        assert_eq!(
+            Opponent::from_str("computer:depth=6,movetime=2000,elo=1400"),
+            Ok(Opponent::Computer(Limits {
+                depth: Some(6),
+                movetime: Some(Duration::from_millis(2000)),
+                elo: Some(1400),
+            }))
+        );
+        assert_eq!(Opponent::from_str("random"), Ok(Opponent::Random));
+        assert_eq!(Opponent::from_str("book-only"), Ok(Opponent::BookOnly));
+        assert_eq!(
+            Opponent::from_str("uci:/usr/bin/stockfish"),
+            Ok(Opponent::Uci("/usr/bin/stockfish".to_string()))
+        );
+    }
+    #[test]
+    fn test_parse_errors() {
+        for (input, error) in [
+            ("zebra", "unknown player 'zebra', expected player, computer"),
+            (
+                "computer:depth=0",
+                "invalid depth '0', expected a number from 1 to 32",
+            ),
+            ("computer:depth=six", "invalid depth 'six'"),
+            (
+                "computer:elo=100",
+                "invalid elo '100', expected a number from 600 to 2400",
+            ),
+            ("computer:speed=1", "unknown computer option 'speed'"),
+            ("computer:depth", "computer option 'depth' needs a value"),
+            ("computer:", "computer option '' needs a value"),
+            ("random:depth=2", "random takes no options"),
+            ("uci:", "uci needs the path to an engine"),
+        ] {
+            let err = Opponent::from_str(input).unwrap_err();
+            assert!(err.starts_with(error), "{}: {}", input, err);
+        }
     }
 }
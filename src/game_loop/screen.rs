@@ -0,0 +1,74 @@
+use super::session::Session;
+use crate::display;
+use crate::input;
+use std::io;
+
+/// Where a game played at the prompt is shown, and its questions are asked.
+pub trait Screen {
+    fn present(&mut self, session: &Session) -> io::Result<()>;
+    /// Text from a command, e.g. the FEN of the position.
+    fn show(&mut self, text: &str) -> io::Result<()>;
+    fn error(&mut self, err: io::Error) -> io::Result<()>;
+    /// Announces how a finished game ended.
+    fn result(&mut self, session: &Session) -> io::Result<()>;
+    /// Asks for a line of input, which is empty when there is no answer.
+    fn ask(&mut self, question: &str) -> io::Result<String>;
+
+    /// Where to save a finished game as PGN, if anywhere.
+    fn save_as(&mut self) -> io::Result<Option<String>> {
+        let path = self.ask("Save the game as PGN? Enter a file name, or nothing to skip:")?;
+        Ok(Some(path).filter(|path| !path.is_empty()))
+    }
+
+    /// Whether to play again, with colors swapped.
+    fn rematch(&mut self) -> io::Result<bool> {
+        let answer = self.ask("Play a rematch with colors swapped? [y/N]")?;
+        Ok(matches!(answer.as_str(), "y" | "Y" | "yes"))
+    }
+}
+
+/// Prints line by line to stdout and reads answers from stdin.
+pub struct Terminal;
+
+impl Screen for Terminal {
+    fn present(&mut self, session: &Session) -> io::Result<()> {
+        display::present_with(&session.board, &session.view);
+        if let Some(clock) = &session.clock {
+            println!("    {}", clock);
+        }
+        if let Some(from) = session.view.selected {
+            let labels: Vec<String> = session
+                .board
+                .legal_moves()
+                .into_iter()
+                .filter(|mov| mov.from == from)
+                .filter_map(|mov| mov.label)
+                .collect();
+            println!("    Moves from {}: {}", from, labels.join(" "));
+        }
+        Ok(())
+    }
+
+    fn show(&mut self, text: &str) -> io::Result<()> {
+        println!("{}", text);
+        Ok(())
+    }
+
+    fn error(&mut self, err: io::Error) -> io::Result<()> {
+        display::print_error(err);
+        Ok(())
+    }
+
+    fn result(&mut self, session: &Session) -> io::Result<()> {
+        display::print_result(&session.board.state.to_string());
+        Ok(())
+    }
+
+    fn ask(&mut self, question: &str) -> io::Result<String> {
+        println!("{}", question);
+        match input::read() {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(String::new()),
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,526 @@
+use super::clock::Clock;
+use super::timeline::Timeline;
+use crate::board::history::Move;
+use crate::board::state::{BoardState, DrawState, GameState};
+use crate::computer::Context;
+use crate::display::View;
+use crate::input::command::{Command, HELP};
+use crate::input::pgn;
+use crate::{Board, Team};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::convert::TryInto;
+use std::fs;
+use std::io::{Error, Result};
+
+/// What the game loop does after a command.
+#[derive(Debug, PartialEq)]
+pub enum Flow {
+    /// The position or how it is shown changed.
+    Redraw,
+    /// Text for the player, the board stays as it is.
+    Show(String),
+    /// Abandon the game and start a new one.
+    Restart,
+    Quit,
+}
+
+/// The game played at the prompt, and everything the commands can change about it.
+///
+/// Saved as JSON by `--save` and continued by `--resume`. How the board is shown is not saved,
+/// it comes from the options of the run that resumes.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub board: Board,
+    #[serde(skip)]
+    pub view: View,
+    start: Board,
+    timeline: Timeline,
+    /// Names of White and Black, e.g. "player" or "computer".
+    names: [String; 2],
+    humans: Vec<Team>,
+    /// The team whose draw offer is waiting for an answer.
+    draw_offer: Option<Team>,
+    pub clock: Option<Clock>,
+}
+
+impl Session {
+    pub fn new(
+        board: Board,
+        white: &str,
+        black: &str,
+        humans: Vec<Team>,
+        clock: Option<Clock>,
+    ) -> Self {
+        Session {
+            start: board.clone(),
+            board,
+            view: View {
+                perspective: perspective(&humans),
+                ..View::default()
+            },
+            timeline: Timeline::default(),
+            names: [white.to_string(), black.to_string()],
+            humans,
+            draw_offer: None,
+            clock,
+        }
+    }
+
+    /// Carries on from `board`, a position reached from the start by the moves in its history.
+    pub fn continued(mut self, board: Board) -> Self {
+        self.board = board;
+        self
+    }
+
+    /// Continues a session written by `save`.
+    pub fn resume(path: &str) -> Result<Self> {
+        let mut session: Session = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| Error::other(format!("can't resume from {}: {}", path, e)))?;
+        session.view.perspective = perspective(&session.humans);
+        Ok(session)
+    }
+
+    /// Writes the session as JSON, so it can be resumed exactly where it stopped.
+    pub fn save(&self, path: &str) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Names of White and Black, e.g. "player" or "computer".
+    pub fn names(&self) -> &[String; 2] {
+        &self.names
+    }
+
+    /// The game for programs: the position as FEN, its legal moves in SAN and UCI, the moves
+    /// played, the clocks in milliseconds and the result. `positions` holds the FEN before each
+    /// move and the current one, to step through the game.
+    pub fn describe(&self) -> Result<Value> {
+        let board = &self.board;
+        let played = pgn::played(&self.start, board)?;
+        let mut positions: Vec<String> = played.iter().map(|(before, _)| before.fen()).collect();
+        positions.push(board.fen());
+        let last_move = played
+            .last()
+            .map(|(_, mov)| json!({ "san": mov.label, "uci": mov.uci() }));
+        let history: Vec<String> = played
+            .into_iter()
+            .filter_map(|(_, mov)| mov.label)
+            .collect();
+        let running = match board.state.is_over() {
+            true => None,
+            false => Some(board.turn_order),
+        };
+        let context = self.context();
+        let clock = self.clock.as_ref().map(|_| {
+            let left = |team: Team| context.remaining(team).unwrap_or_default().as_millis() as u64;
+            json!({
+                "white": left(Team::White),
+                "black": left(Team::Black),
+                "running": running.map(|team| team.to_string()),
+            })
+        });
+        let legal_moves: Vec<Value> = match board.state.is_over() {
+            true => Vec::new(),
+            false => board
+                .legal_moves()
+                .iter()
+                .map(|mov| json!({ "san": mov.label, "uci": mov.uci() }))
+                .collect(),
+        };
+        Ok(json!({
+            "fen": board.fen(),
+            "turn": board.turn_order.to_string(),
+            "legal_moves": legal_moves,
+            "last_move": last_move,
+            "history": history,
+            "positions": positions,
+            "clock": clock,
+            "over": board.state.is_over(),
+            "result": board.state.score(),
+            "status": board.state.to_string(),
+        }))
+    }
+
+    /// Registers the move just played from `before`, and ends the game if it is over. Moving on
+    /// declines a pending draw offer.
+    pub fn moved(&mut self, before: &Board) {
+        let team = before.turn_order;
+        self.view.selected = None;
+        self.timeline.record(before);
+        if self.draw_offer == Some(team.not()) {
+            self.draw_offer = None;
+        }
+        self.board.adjudicate(self.timeline.positions());
+        let flagged = self.clock.as_mut().is_some_and(|clock| !clock.stop(team));
+        if flagged && !self.board.state.is_over() {
+            self.board.state = match self.board.can_mate(team.not()) {
+                true => BoardState::won(team.not(), GameState::Timeout),
+                false => BoardState::drawn(DrawState::TimeoutVsInsufficientMaterial),
+            };
+        }
+    }
+
+    /// Plays `mov` for the team to move, if it is legal.
+    pub fn play(&mut self, mov: &Move) -> Result<()> {
+        let legal = self.board.legal(mov)?;
+        let before = self.board.clone();
+        if let Err(e) = self.board.apply(&legal) {
+            self.board = before;
+            return Err(e);
+        }
+        self.moved(&before);
+        Ok(())
+    }
+
+    /// What the player to move gets to see of the game.
+    pub fn context(&self) -> Context<'_> {
+        Context {
+            board: &self.board,
+            start: &self.start,
+            clock: self.clock.as_ref(),
+            draw_offered: self.draw_offer == Some(self.board.turn_order.not()),
+        }
+    }
+
+    /// The game so far as PGN, with its result if it is over.
+    pub fn pgn(&self) -> Result<String> {
+        self.pgn_as("Casual game", "-")
+    }
+
+    /// The game as PGN, tagged as a round of an event.
+    pub fn pgn_as(&self, event: &str, round: &str) -> Result<String> {
+        let result = self.board.state.score();
+        let tags = [
+            ("Event", event),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", round),
+            ("White", self.names[0].as_str()),
+            ("Black", self.names[1].as_str()),
+            ("Result", result),
+        ];
+        pgn::export(&self.start, &self.board, &tags)
+    }
+
+    pub fn command(&mut self, command: Command) -> Result<Flow> {
+        let team = self.board.turn_order;
+        self.view.selected = None;
+        let text = match command {
+            Command::Help => HELP.to_string(),
+            Command::Fen => self.board.fen(),
+            Command::Pgn => self.pgn()?,
+            Command::Moves(from) => {
+                let labels: Vec<String> = self
+                    .board
+                    .legal_moves()
+                    .into_iter()
+                    .filter(|mov| from.is_none_or(|sq| sq == mov.from))
+                    .filter_map(|mov| mov.label)
+                    .collect();
+                match (labels.is_empty(), from) {
+                    (true, Some(sq)) => {
+                        return Err(Error::other(format!("no legal moves from {}", sq)))
+                    }
+                    (true, None) => return Err(Error::other("no legal moves")),
+                    (false, Some(sq)) => {
+                        self.view.selected = Some(sq);
+                        return Ok(Flow::Redraw);
+                    }
+                    (false, None) => labels.join(" "),
+                }
+            }
+            Command::Flip => {
+                self.view.perspective = self.view.perspective.not();
+                return Ok(Flow::Redraw);
+            }
+            Command::Undo | Command::Redo => {
+                let stepped = match command {
+                    Command::Undo => self.timeline.undo(&mut self.board, &self.humans),
+                    _ => self.timeline.redo(&mut self.board, &self.humans),
+                };
+                if !stepped {
+                    return Err(Error::other("nothing to take back or replay"));
+                }
+                self.draw_offer = None;
+                return Ok(Flow::Redraw);
+            }
+            Command::Resign => {
+                self.board.state = BoardState::won(team.not(), GameState::Resignation);
+                return Ok(Flow::Redraw);
+            }
+            Command::Draw => return Ok(self.draw(team)),
+            Command::Save(path) => {
+                fs::write(&path, self.pgn()?)?;
+                format!("Game saved to {}", path)
+            }
+            Command::Load(path) => {
+                let (start, board) = load(&fs::read_to_string(&path)?)?;
+                self.start = start;
+                self.board = board;
+                self.timeline = Timeline::default();
+                self.draw_offer = None;
+                return Ok(Flow::Redraw);
+            }
+            Command::New => return Ok(Flow::Restart),
+            Command::Quit => return Ok(Flow::Quit),
+        };
+        Ok(Flow::Show(text))
+    }
+
+    /// Whether a person, rather than the computer, plays `team`.
+    pub fn is_human(&self, team: Team) -> bool {
+        self.humans.contains(&team)
+    }
+
+    fn draw(&mut self, team: Team) -> Flow {
+        if self.draw_offer == Some(team.not()) {
+            self.board.state = BoardState::drawn(DrawState::Agreement);
+            return Flow::Redraw;
+        }
+        self.draw_offer = Some(team);
+        Flow::Show(match self.is_human(team.not()) {
+            true => format!(
+                "{} offers a draw, {} can type `draw` to accept.",
+                team,
+                team.not()
+            ),
+            false => format!("{} offers a draw, the computer answers on its turn.", team),
+        })
+    }
+}
+
+/// Shows the board from the side of the only human player.
+fn perspective(humans: &[Team]) -> Team {
+    match humans {
+        [Team::Black] => Team::Black,
+        _ => Team::White,
+    }
+}
+
+/// Reads a single FEN, or the first game of a PGN, into its start and current position.
+fn load(data: &str) -> Result<(Board, Board)> {
+    let data = data.trim();
+    if data.lines().count() == 1 && data.contains('/') && !data.starts_with('[') {
+        let board: Board = data.to_string().try_into()?;
+        return Ok((board.clone(), board));
+    }
+    match pgn::games(data).first() {
+        Some(game) => pgn::replay(game),
+        None => Err(Error::other("no FEN or PGN game found")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute;
+    use crate::Sq;
+    use std::str::FromStr;
+
+    fn session(humans: Vec<Team>) -> Session {
+        Session::new(Board::new(), "player", "computer", humans, None)
+    }
+
+    fn play(session: &mut Session, san: &str) {
+        let before = session.board.clone();
+        execute::execute(&mut session.board, san.chars()).unwrap();
+        session.moved(&before);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut session = session(vec![Team::White]);
+        for san in ["e4", "c5", "Nf3"] {
+            play(&mut session, san);
+        }
+        let path = std::env::temp_dir().join("chess_session_test.pgn");
+        let path = path.to_str().unwrap().to_string();
+        assert_eq!(
+            session.command(Command::Save(path.clone())).unwrap(),
+            Flow::Show(format!("Game saved to {}", path))
+        );
+
+        let mut other = Session::new(Board::new(), "player", "player", vec![], None);
+        assert_eq!(
+            other.command(Command::Load(path.clone())).unwrap(),
+            Flow::Redraw
+        );
+        assert_eq!(other.board.fen(), session.board.fen());
+        assert!(!other.timeline.undo(&mut other.board, &[Team::White]));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_save_and_resume() {
+        let clock = Clock::from_str("5+3").ok();
+        let humans = vec![Team::White, Team::Black];
+        let mut session = Session::new(Board::new(), "player", "player", humans, clock);
+        for san in ["d4", "d5", "c4", "dxc4", "e4"] {
+            play(&mut session, san);
+        }
+        session.command(Command::Draw).unwrap();
+        let path = std::env::temp_dir().join("chess_session_test.json");
+        let path = path.to_str().unwrap().to_string();
+        session.save(&path).unwrap();
+
+        let mut resumed = Session::resume(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(resumed.board, session.board);
+        assert_eq!(resumed.start, session.start);
+        assert_eq!(resumed.names(), session.names());
+        assert_eq!(resumed.humans, session.humans);
+        assert_eq!(resumed.draw_offer, Some(Team::Black));
+        assert_eq!(
+            resumed.clock.as_ref().map(Clock::to_string),
+            session.clock.as_ref().map(Clock::to_string)
+        );
+        assert_eq!(resumed.pgn().unwrap(), session.pgn().unwrap());
+        // The history is there to take back.
+        assert_eq!(resumed.command(Command::Undo).unwrap(), Flow::Redraw);
+        assert_eq!(resumed.board.history.len(Team::White), 2);
+    }
+
+    #[test]
+    fn test_resume_invalid() {
+        let path = std::env::temp_dir().join("chess_session_invalid.json");
+        let path = path.to_str().unwrap().to_string();
+        fs::write(&path, "{\"board\": 1}").unwrap();
+        let err = Session::resume(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(err.to_string().starts_with("can't resume from"));
+        assert!(Session::resume("no/such/session.json").is_err());
+    }
+
+    #[test]
+    fn test_load_fen() {
+        let fen = "8/8/8/8/8/2k5/8/K7 w - - 12 60";
+        let (start, board) = load(fen).unwrap();
+        assert_eq!(start.fen(), fen);
+        assert_eq!(board.fen(), fen);
+        assert!(load("").is_err());
+    }
+
+    #[test]
+    fn test_draw_offer() {
+        let mut session = session(vec![Team::White, Team::Black]);
+        assert!(matches!(
+            session.command(Command::Draw).unwrap(),
+            Flow::Show(_)
+        ));
+        play(&mut session, "e4");
+        assert_eq!(session.command(Command::Draw).unwrap(), Flow::Redraw);
+        assert_eq!(
+            session.board.state.to_string(),
+            "Draw by agreement, 1/2-1/2"
+        );
+
+        // Moving instead of answering declines the offer.
+        let mut session = session_with_offer();
+        play(&mut session, "e5");
+        play(&mut session, "Nf3");
+        assert!(matches!(
+            session.command(Command::Draw).unwrap(),
+            Flow::Show(_)
+        ));
+    }
+
+    fn session_with_offer() -> Session {
+        let mut session = session(vec![Team::White, Team::Black]);
+        play(&mut session, "e4");
+        session.command(Command::Draw).unwrap();
+        session
+    }
+
+    #[test]
+    fn test_draw_offer_to_computer() {
+        // The computer answers on its turn, see `Computer::accepts_draw`.
+        let mut session = session(vec![Team::White]);
+        assert!(matches!(
+            session.command(Command::Draw).unwrap(),
+            Flow::Show(_)
+        ));
+        play(&mut session, "e4");
+        assert!(session.context().draw_offered);
+        assert_eq!(session.command(Command::Draw).unwrap(), Flow::Redraw);
+        assert_eq!(session.board.state.score(), "1/2-1/2");
+    }
+
+    #[test]
+    fn test_moves_from_empty_square() {
+        let mut session = session(vec![Team::White]);
+        assert!(session
+            .command(Command::Moves(Sq::notation("e4").ok()))
+            .is_err());
+        match session.command(Command::Moves(None)).unwrap() {
+            Flow::Show(moves) => assert_eq!(moves.split(' ').count(), 20),
+            flow => panic!("{:?}", flow),
+        }
+    }
+
+    #[test]
+    fn test_perspective_follows_human() {
+        assert_eq!(session(vec![Team::White]).view.perspective, Team::White);
+        assert_eq!(session(vec![Team::Black]).view.perspective, Team::Black);
+        assert_eq!(session(vec![]).view.perspective, Team::White);
+        let mut session = session(vec![Team::Black]);
+        assert_eq!(session.command(Command::Flip).unwrap(), Flow::Redraw);
+        assert_eq!(session.view.perspective, Team::White);
+    }
+
+    #[test]
+    fn test_play_checks_moves() {
+        let mut session = session(vec![Team::White, Team::Black]);
+        session.command(Command::Draw).unwrap();
+        assert!(!session.context().draw_offered);
+        let mut mov = session.board.find_move("e4").unwrap();
+        mov.label = None;
+        session.play(&mov).unwrap();
+        assert_eq!(session.board.turn_order, Team::Black);
+        assert!(session.context().draw_offered);
+        // The same move again is not legal for Black.
+        let before = session.board.clone();
+        assert!(session.play(&mov).is_err());
+        assert_eq!(session.board, before);
+        // Moving on declines the offer.
+        session
+            .play(&session.board.find_move("e5").unwrap())
+            .unwrap();
+        assert!(!session.context().draw_offered);
+    }
+
+    #[test]
+    fn test_resign() {
+        let mut session = session(vec![Team::White]);
+        assert_eq!(session.command(Command::Resign).unwrap(), Flow::Redraw);
+        assert_eq!(session.board.state.score(), "0-1");
+        assert!(session.pgn().unwrap().contains("[Result \"0-1\"]"));
+    }
+
+    #[test]
+    fn test_mate_ends_game() {
+        let mut session = session(vec![Team::White, Team::Black]);
+        for san in ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6"] {
+            play(&mut session, san);
+            assert!(!session.board.state.is_over());
+        }
+        play(&mut session, "Qxf7#");
+        assert_eq!(
+            session.board.state.to_string(),
+            "White wins by checkmate, 1-0"
+        );
+        assert!(session.pgn().unwrap().ends_with("4. Qxf7# 1-0\n"));
+    }
+
+    #[test]
+    fn test_flag_fall() {
+        let clock = Clock::from_str("0.0001").ok();
+        let mut session = Session::new(Board::new(), "player", "player", vec![], clock);
+        session.clock.as_mut().unwrap().start();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        play(&mut session, "e4");
+        assert_eq!(
+            session.board.state.to_string(),
+            "Black wins by timeout, 0-1"
+        );
+    }
+}
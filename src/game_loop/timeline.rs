@@ -0,0 +1,120 @@
+use crate::{Board, Team};
+use serde::{Deserialize, Serialize};
+use std::mem;
+
+/// Positions before and after the current one, for taking moves back and replaying them.
+///
+/// Whole boards are kept rather than moves, so undoing restores castling rights, the en
+/// passant square, the clocks and the move history exactly as they were.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Timeline {
+    past: Vec<Board>,
+    future: Vec<Board>,
+}
+
+impl Timeline {
+    /// Remembers the position a move was played from. Anything undone before can no longer
+    /// be redone.
+    pub fn record(&mut self, before: &Board) {
+        self.past.push(before.clone());
+        self.future.clear();
+    }
+
+    /// The positions played before the current one.
+    pub fn positions(&self) -> &[Board] {
+        &self.past
+    }
+
+    /// Steps back until one of `humans` is to move again, so a move against the computer
+    /// takes back the computer's reply too. Returns false if there was nothing to undo.
+    pub fn undo(&mut self, board: &mut Board, humans: &[Team]) -> bool {
+        step(&mut self.past, &mut self.future, board, humans)
+    }
+
+    /// Replays undone moves until one of `humans` is to move again. Returns false if there
+    /// was nothing to redo.
+    pub fn redo(&mut self, board: &mut Board, humans: &[Team]) -> bool {
+        step(&mut self.future, &mut self.past, board, humans)
+    }
+}
+
+fn step(from: &mut Vec<Board>, to: &mut Vec<Board>, board: &mut Board, humans: &[Team]) -> bool {
+    let mut stepped = false;
+    while let Some(next) = from.pop() {
+        to.push(mem::replace(board, next));
+        stepped = true;
+        if humans.contains(&board.turn_order) {
+            break;
+        }
+    }
+    stepped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute;
+    use std::convert::TryInto;
+
+    fn play(board: &mut Board, timeline: &mut Timeline, san: &str) {
+        let before = board.clone();
+        execute::execute(board, san.chars()).unwrap();
+        timeline.record(&before);
+    }
+
+    #[test]
+    fn test_undo_restores_exact_position() {
+        let mut board: Board = "r3k2r/8/8/8/3p4/8/4P3/R3K2R w KQkq - 3 20"
+            .to_string()
+            .try_into()
+            .unwrap();
+        let start = board.clone();
+        let mut timeline = Timeline::default();
+        play(&mut board, &mut timeline, "e4");
+        let after_e4 = board.clone();
+        play(&mut board, &mut timeline, "O-O");
+        let humans = [Team::White, Team::Black];
+
+        assert!(timeline.undo(&mut board, &humans));
+        assert_eq!(board, after_e4);
+        assert!(timeline.undo(&mut board, &humans));
+        assert_eq!(board, start);
+        assert!(!timeline.undo(&mut board, &humans));
+
+        assert!(timeline.redo(&mut board, &humans));
+        assert!(timeline.redo(&mut board, &humans));
+        assert_eq!(board.castling.fen(), "KQ");
+        assert!(!timeline.redo(&mut board, &humans));
+    }
+
+    #[test]
+    fn test_undo_takes_back_computer_reply() {
+        let mut board = Board::new();
+        let mut timeline = Timeline::default();
+        for san in ["e4", "e5", "Nf3", "Nc6"] {
+            play(&mut board, &mut timeline, san);
+        }
+        let mut after_e5 = Board::new();
+        for san in ["e4", "e5"] {
+            execute::execute(&mut after_e5, san.chars()).unwrap();
+        }
+        let end = board.clone();
+        let humans = [Team::White];
+
+        assert!(timeline.undo(&mut board, &humans));
+        assert_eq!(board, after_e5);
+        assert!(timeline.redo(&mut board, &humans));
+        assert_eq!(board, end);
+    }
+
+    #[test]
+    fn test_new_move_clears_redo() {
+        let mut board = Board::new();
+        let mut timeline = Timeline::default();
+        let humans = [Team::White, Team::Black];
+        play(&mut board, &mut timeline, "e4");
+        timeline.undo(&mut board, &humans);
+        play(&mut board, &mut timeline, "d4");
+        assert!(!timeline.redo(&mut board, &humans));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::Sq;
+use std::io::{Error, Result};
+
+/// Instructions typed at the prompt instead of a move.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    Fen,
+    Pgn,
+    /// Legal moves, optionally only those starting from a square.
+    Moves(Option<Sq>),
+    Flip,
+    Resign,
+    /// Offers a draw, or accepts the one on the table.
+    Draw,
+    Save(String),
+    Load(String),
+    Undo,
+    Redo,
+    /// Abandons the game for a new one.
+    New,
+    Quit,
+}
+
+pub const HELP: &str = "\
+Type a move in SAN (e4, Nf3, exd5, O-O, e8=Q) or one of:
+  help          show this list
+  fen           print the position as FEN
+  pgn           print the game as PGN
+  moves [sq]    list legal moves, or only those from a square
+  flip          turn the board around
+  undo, redo    take back or replay a move
+  draw          offer a draw, or accept one
+  resign        give up the game
+  save <file>   write the game to a PGN file
+  load <file>   continue from a PGN or FEN file
+  new           abandon the game and start over
+  quit          leave without finishing the game";
+
+impl Command {
+    /// Parses a line of input, or returns None when it is not a command and should be read as a
+    /// move. None of the command names can be mistaken for SAN.
+    pub fn parse(line: &str) -> Option<Result<Command>> {
+        let line = line.trim();
+        let (name, rest) = match line.split_once(char::is_whitespace) {
+            Some((name, rest)) => (name, rest.trim()),
+            None => (line, ""),
+        };
+        let command = match name {
+            "help" => Command::Help,
+            "fen" => Command::Fen,
+            "pgn" => Command::Pgn,
+            "moves" if rest.is_empty() => Command::Moves(None),
+            "moves" => match rest.as_bytes() {
+                [b'a'..=b'h', b'1'..=b'8'] => {
+                    return Some(Sq::notation(rest).map(|sq| Command::Moves(Some(sq))))
+                }
+                _ => return Some(Err(Error::other(format!("'{}' is not a square", rest)))),
+            },
+            "flip" => Command::Flip,
+            "resign" => Command::Resign,
+            "draw" => Command::Draw,
+            "save" | "load" if rest.is_empty() => {
+                return Some(Err(Error::other(format!("{} needs a file name", name))));
+            }
+            "save" => return Some(Ok(Command::Save(rest.to_string()))),
+            "load" => return Some(Ok(Command::Load(rest.to_string()))),
+            "undo" => Command::Undo,
+            "redo" => Command::Redo,
+            "new" => Command::New,
+            "quit" | "exit" => Command::Quit,
+            _ => return None,
+        };
+        match rest.is_empty() {
+            true => Some(Ok(command)),
+            false => Some(Err(Error::other(format!("{} takes no arguments", name)))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(Command::parse("help").unwrap().unwrap(), Command::Help);
+        assert_eq!(Command::parse(" quit ").unwrap().unwrap(), Command::Quit);
+        assert_eq!(
+            Command::parse("moves e2").unwrap().unwrap(),
+            Command::Moves(Some(Sq::new(1, 4)))
+        );
+        assert_eq!(
+            Command::parse("moves").unwrap().unwrap(),
+            Command::Moves(None)
+        );
+        assert_eq!(
+            Command::parse("save my game.pgn").unwrap().unwrap(),
+            Command::Save("my game.pgn".to_string())
+        );
+    }
+    #[test]
+    fn test_parse_moves_are_not_commands() {
+        for san in ["e4", "Nf3", "exd5", "O-O", "e8=Q", "Rdxd8+", "b4"] {
+            assert!(Command::parse(san).is_none(), "{}", san);
+        }
+    }
+    #[test]
+    fn test_parse_invalid_arguments() {
+        assert!(Command::parse("moves z9").unwrap().is_err());
+        assert!(Command::parse("save").unwrap().is_err());
+        assert!(Command::parse("fen now").unwrap().is_err());
+    }
+}
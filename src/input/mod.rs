@@ -4,31 +4,28 @@ use std::io::{Error, ErrorKind, Result};
 use std::iter::Rev;
 use std::str::Chars;
 
+pub mod command;
 pub mod pgn;
 
+/// Reads a line from stdin, failing with `UnexpectedEof` once stdin is closed.
 pub fn read() -> Result<String> {
     let mut input = String::new();
     match io::stdin().read_line(&mut input) {
+        Ok(0) => Err(Error::new(ErrorKind::UnexpectedEof, "end of input")),
         Ok(_) => Ok(input.trim().to_string()),
         Err(e) => Err(e),
     }
 }
 
 pub fn letter_index(ch: char) -> Option<usize> {
-    match ch.to_digit(18) {
-        Some(x) => Some(x as usize - 10),
-        None => None,
-    }
+    ch.to_digit(18).map(|x| x as usize - 10)
 }
 
 pub fn to_index_pos(chars: &mut Rev<Chars>) -> Result<[usize; 2]> {
     let mut digit_raw = match chars.next() {
         Some(d) => d,
         None => {
-            return Err(Error::new(
-                ErrorKind::Other,
-                "not enough chars in input for digit indice",
-            ));
+            return Err(Error::other("not enough chars in input for digit indice"));
         }
     };
 
@@ -36,12 +33,7 @@ pub fn to_index_pos(chars: &mut Rev<Chars>) -> Result<[usize; 2]> {
         '+' | '#' => {
             digit_raw = match chars.next() {
                 Some(d) => d,
-                None => {
-                    return Err(Error::new(
-                        ErrorKind::Other,
-                        "not enough chars in input after check/mate",
-                    ))
-                }
+                None => return Err(Error::other("not enough chars in input after check/mate")),
             }
         }
         _ => (),
@@ -50,20 +42,14 @@ pub fn to_index_pos(chars: &mut Rev<Chars>) -> Result<[usize; 2]> {
     let digit = match digit_raw.to_digit(10) {
         Some(d) => d as usize,
         None => {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("last char ({}) not digit", digit_raw),
-            ));
+            return Err(Error::other(format!("last char ({}) not digit", digit_raw)));
         }
     };
 
     let letter_raw = match chars.next() {
         Some(l) => l,
         None => {
-            return Err(Error::new(
-                ErrorKind::Other,
-                "not enough chars in input for letter indice",
-            ));
+            return Err(Error::other("not enough chars in input for letter indice"));
         }
     };
 
@@ -71,8 +57,8 @@ pub fn to_index_pos(chars: &mut Rev<Chars>) -> Result<[usize; 2]> {
     Ok([digit - 1, letter])
 }
 
-pub fn chars_to_sq(mut chars: &mut Rev<Chars>) -> Result<Sq> {
-    let [digit, letter] = to_index_pos(&mut chars)?;
+pub fn chars_to_sq(chars: &mut Rev<Chars>) -> Result<Sq> {
+    let [digit, letter] = to_index_pos(chars)?;
     Ok(Sq::new(digit, letter))
 }
 
@@ -91,30 +77,12 @@ mod tests {
 
     #[test]
     fn test_to_index_pos_valid() -> Result<()> {
-        assert_eq!(
-            to_index_pos(&mut "a1".chars().rev())?,
-            [0 as usize, 0 as usize]
-        );
-        assert_eq!(
-            to_index_pos(&mut "a2".chars().rev())?,
-            [1 as usize, 0 as usize]
-        );
-        assert_eq!(
-            to_index_pos(&mut "b4".chars().rev())?,
-            [3 as usize, 1 as usize]
-        );
-        assert_eq!(
-            to_index_pos(&mut "d2".chars().rev())?,
-            [1 as usize, 3 as usize]
-        );
-        assert_eq!(
-            to_index_pos(&mut "a8".chars().rev())?,
-            [7 as usize, 0 as usize]
-        );
-        assert_eq!(
-            to_index_pos(&mut "h7".chars().rev())?,
-            [6 as usize, 7 as usize]
-        );
+        assert_eq!(to_index_pos(&mut "a1".chars().rev())?, [0_usize, 0_usize]);
+        assert_eq!(to_index_pos(&mut "a2".chars().rev())?, [1_usize, 0_usize]);
+        assert_eq!(to_index_pos(&mut "b4".chars().rev())?, [3_usize, 1_usize]);
+        assert_eq!(to_index_pos(&mut "d2".chars().rev())?, [1_usize, 3_usize]);
+        assert_eq!(to_index_pos(&mut "a8".chars().rev())?, [7_usize, 0_usize]);
+        assert_eq!(to_index_pos(&mut "h7".chars().rev())?, [6_usize, 7_usize]);
         Ok(())
     }
 }
@@ -1,4 +1,8 @@
+use crate::board::history::Move;
+use crate::{Board, Team};
+use std::convert::TryInto;
 use std::fmt;
+use std::io::{Error, ErrorKind, Result};
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Turn {
@@ -7,6 +11,7 @@ pub struct Turn {
     pub black: Option<String>,
 }
 
+#[allow(dead_code)]
 #[derive(Clone, PartialEq)]
 pub struct Instruction {
     pub white: String,
@@ -16,8 +21,23 @@ pub struct Instruction {
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Pgn {
-    turns: Vec<Turn>,
-    meta: Vec<String>,
+    pub turns: Vec<Turn>,
+    pub meta: Vec<String>,
+}
+
+impl Pgn {
+    /// Value of a tag pair, e.g. "1-0" for `[Result "1-0"]`.
+    pub fn tag(&self, name: &str) -> Option<String> {
+        let prefix = format!("[{} \"", name);
+        self.meta
+            .iter()
+            .find(|line| line.starts_with(&prefix))
+            .map(|line| {
+                line[prefix.len()..]
+                    .trim_end_matches([']', '"'])
+                    .to_string()
+            })
+    }
 }
 
 // impl Pgn {
@@ -50,42 +70,291 @@ impl fmt::Debug for Instruction {
     }
 }
 
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// Splits a collection into games, each made of its tag pairs and movetext.
+pub fn games(data: &str) -> Vec<Pgn> {
+    let mut games = Vec::new();
+    let mut meta = Vec::new();
+    let mut movetext = String::new();
+
+    for line in data.lines().map(|line| line.trim()) {
+        let tag = line.starts_with('[');
+        if (tag || line.is_empty()) && !movetext.is_empty() {
+            games.push(Pgn {
+                turns: parse(&movetext),
+                meta: std::mem::take(&mut meta),
+            });
+            movetext.clear();
+        }
+        if tag {
+            meta.push(line.to_string());
+        } else if !line.is_empty() {
+            movetext.push_str(line);
+            movetext.push(' ');
+        }
+    }
+    if !movetext.is_empty() {
+        games.push(Pgn {
+            turns: parse(&movetext),
+            meta,
+        });
+    }
+    games
+}
+
+/// Extracts the moves from movetext, skipping move numbers, comments, variations and results.
 pub fn parse<T: ToString>(game: T) -> Vec<Turn> {
-    let mut vec: Vec<Turn> = Vec::new();
-    // println!("game: {}", game);
-    let string = game.to_string();
-    let mut split: Vec<&str> = string.split_whitespace().into_iter().collect();
-    // println!("split: {:?}", split);
-    while split.len() > 3 {
-        // We want to split it into groups of three because the format to decode is: "1. e4 e5"
-        let drain: Vec<String> = split
-            .drain(0..3)
-            .map(|x| x.to_string())
-            .collect::<Vec<String>>();
-        // println!("drain: {:?}", drain);
-        match drain[0].contains('{') {
-            true => break,
-            false => (),
-        };
-        // let nr = split.pop().unwrap().to_string();
-        // println!("nr: {}", nr);
-        let mut will_break = false;
-        // If the annotation contains the meta data character '{}'
-        // Then it is at the end of a game, e.g. 34. Qf4# {black wins by checkmate}
-        let black = match drain[2].contains('{') {
-            true => {
-                will_break = true;
-                None
+    let mut moves: Vec<String> = Vec::new();
+    let mut depth = 0;
+    let mut in_comment = false;
+    let mut token = String::new();
+
+    for ch in game.to_string().chars().chain(std::iter::once(' ')) {
+        match ch {
+            '{' => in_comment = true,
+            '}' => in_comment = false,
+            '(' if !in_comment => depth += 1,
+            ')' if !in_comment => depth -= 1,
+            _ if in_comment || depth > 0 => (),
+            _ if ch.is_whitespace() => {
+                // Move numbers can be glued to the move, e.g. "1.e4".
+                let san = token
+                    .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.')
+                    .trim_end_matches(['!', '?']);
+                if !san.is_empty() && !san.starts_with('$') && !RESULTS.contains(&token.as_str()) {
+                    moves.push(san.to_string());
+                }
+                token.clear();
             }
-            false => Some(drain[2].clone()),
+            _ => token.push(ch),
+        }
+    }
+
+    moves
+        .chunks(2)
+        .map(|pair| Turn {
+            white: pair.first().cloned(),
+            black: pair.get(1).cloned(),
+        })
+        .collect()
+}
+
+/// Replays a game from its FEN tag, or the initial position. Returns where it started and ended.
+pub fn replay(game: &Pgn) -> Result<(Board, Board)> {
+    let start: Board = match game.tag("FEN") {
+        Some(fen) => fen.try_into()?,
+        None => Board::new(),
+    };
+    let mut board = start.clone();
+    let sans = game
+        .turns
+        .iter()
+        .flat_map(|turn| [turn.white.as_ref(), turn.black.as_ref()])
+        .flatten();
+    for san in sans {
+        let mov = board.find_move(san).map_err(|_| {
+            let msg = format!("{} is not a legal move in {}", san, board.fen());
+            Error::new(ErrorKind::InvalidData, msg)
+        })?;
+        board.apply(&mov)?;
+    }
+    Ok((start, board))
+}
+
+/// The moves played from `start` to reach `board`, each with the position it was played in.
+/// The SAN is recomputed from the History, so moves are disambiguated and marked with checks
+/// however they were entered.
+pub fn played(start: &Board, board: &Board) -> Result<Vec<(Board, Move)>> {
+    let mut replay = start.clone();
+    let mut played = [
+        start.history.len(Team::White),
+        start.history.len(Team::Black),
+    ];
+    let mut moves = Vec::new();
+    loop {
+        let team = replay.turn_order;
+        let side = match team {
+            Team::White => 0,
+            Team::Black => 1,
         };
-        vec.push(Turn {
-            white: Some(drain[1].clone()),
-            black,
-        });
-        if will_break {
-            break;
+        let recorded = match board.history.get(team, played[side]) {
+            Some(mov) => mov,
+            None => break,
+        };
+        let mov = replay
+            .legal_moves()
+            .into_iter()
+            .find(|m| {
+                m.from == recorded.from && m.to == recorded.to && m.promotion == recorded.promotion
+            })
+            .ok_or_else(|| {
+                let msg = format!(
+                    "{}{} is not legal in {}",
+                    recorded.from,
+                    recorded.to,
+                    replay.fen()
+                );
+                Error::new(ErrorKind::InvalidData, msg)
+            })?;
+        let before = replay.clone();
+        replay.apply(&mov)?;
+        moves.push((before, mov));
+        played[side] += 1;
+    }
+    Ok(moves)
+}
+
+/// Move numbers and SAN of moves, each given with the position it is played in.
+fn numbered(moves: Vec<(Board, Move)>) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for (before, mov) in moves {
+        match before.turn_order {
+            Team::White => tokens.push(format!("{}.", before.fullmove)),
+            Team::Black if tokens.is_empty() => tokens.push(format!("{}...", before.fullmove)),
+            Team::Black => (),
+        }
+        tokens.push(mov.label.unwrap_or_default());
+    }
+    tokens
+}
+
+/// A line of moves from `board` as numbered SAN, e.g. "12... Nf6 13. O-O".
+pub fn line(board: &Board, moves: &[Move]) -> Result<String> {
+    let mut board = board.clone();
+    let mut labeled = Vec::new();
+    for mov in moves {
+        let legal = board.legal(mov)?;
+        let before = board.clone();
+        board.apply(&legal)?;
+        labeled.push((before, legal));
+    }
+    Ok(numbered(labeled).join(" "))
+}
+
+/// Writes the game from `start` to `board` as PGN, with the SAN of `played`.
+pub fn export(start: &Board, board: &Board, tags: &[(&str, &str)]) -> Result<String> {
+    let mut tokens = numbered(played(start, board)?);
+    let result = tags
+        .iter()
+        .find(|(name, _)| *name == "Result")
+        .map_or("*", |(_, value)| value);
+    tokens.push(result.to_string());
+
+    let mut pgn = String::new();
+    for (name, value) in tags {
+        pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+    }
+    if start.castling.chess960 {
+        pgn.push_str("[Variant \"Chess960\"]\n");
+    }
+    if start.fen() != Board::new().fen() {
+        pgn.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", start.fen()));
+    }
+    pgn.push('\n');
+    pgn.push_str(&wrap(&tokens));
+    Ok(pgn)
+}
+
+/// Movetext as lines of less than 80 characters.
+pub fn wrap(tokens: &[String]) -> String {
+    let mut text = String::new();
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + token.len() >= 80 {
+            text.push_str(&line);
+            text.push('\n');
+            line.clear();
         }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(token);
+    }
+    text.push_str(&line);
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_line() {
+        let board: Board = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"
+            .to_string()
+            .try_into()
+            .unwrap();
+        let moves: Vec<Move> = ["Bb5", "a6", "Bxc6"]
+            .iter()
+            .scan(board.clone(), |board, san| {
+                let mov = board.find_move(san).unwrap();
+                board.apply(&mov).unwrap();
+                Some(mov)
+            })
+            .collect();
+        assert_eq!(line(&board, &moves).unwrap(), "3. Bb5 a6 4. Bxc6");
+        assert_eq!(
+            line(&board, &moves[1..]).unwrap_err().to_string(),
+            "a7a6 is not a legal move"
+        );
+        let mut black = board.clone();
+        black.apply(&moves[0]).unwrap();
+        assert_eq!(line(&black, &moves[1..]).unwrap(), "3... a6 4. Bxc6");
+    }
+
+    #[test]
+    fn test_parse_movetext() {
+        let turns = parse("1. e4 e5 2.Nf3 {Main line} Nc6 (2... d6) 3. Bb5!? 1-0");
+        assert_eq!(turns.len(), 3);
+        assert_eq!(turns[1].white, Some("Nf3".to_string()));
+        assert_eq!(turns[1].black, Some("Nc6".to_string()));
+        assert_eq!(turns[2].white, Some("Bb5".to_string()));
+        assert_eq!(turns[2].black, None);
+    }
+    #[test]
+    fn test_games() {
+        let data = "[Event \"A\"]\n[Result \"0-1\"]\n\n1. f3 e5 2. g4\nQh4# 0-1\n\n[Event \"B\"]\n\n1. e4 *\n";
+        let games = games(data);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Result"), Some("0-1".to_string()));
+        assert_eq!(games[0].turns[1].black, Some("Qh4#".to_string()));
+        assert_eq!(games[1].tag("Result"), None);
+    }
+    #[test]
+    fn test_export_recomputes_san() {
+        let mut board = Board::new();
+        for san in [
+            "e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6", "Ng5", "d5", "exd5", "Nxd5",
+        ] {
+            crate::execute::execute(&mut board, san.chars()).unwrap();
+        }
+        crate::execute::execute(&mut board, "Nxf7".chars()).unwrap();
+        let pgn = export(&Board::new(), &board, &[("Event", "Test"), ("Result", "*")]).unwrap();
+        assert_eq!(
+            pgn,
+            "[Event \"Test\"]\n[Result \"*\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. Ng5 d5 5. exd5 Nxd5 6. Nxf7 *\n"
+        );
+    }
+    #[test]
+    fn test_export_round_trip_from_fen() {
+        let fen = "4k3/8/8/8/8/8/8/R3K2R b KQ - 0 30";
+        let start: Board = fen.to_string().try_into().unwrap();
+        let mut board = start.clone();
+        for san in ["Kd7", "O-O-O+"] {
+            crate::execute::execute(&mut board, san.chars()).unwrap();
+        }
+        let pgn = export(&start, &board, &[]).unwrap();
+        assert!(pgn.contains("[FEN \"4k3/8/8/8/8/8/8/R3K2R b KQ - 0 30\"]"));
+        assert!(pgn.ends_with("30... Kd7 31. O-O-O+ *\n"));
+
+        let (replayed_start, replayed) = replay(&games(&pgn)[0]).unwrap();
+        assert_eq!(replayed_start.fen(), start.fen());
+        assert_eq!(replayed.fen(), board.fen());
+    }
+    #[test]
+    fn test_replay_illegal_move() {
+        let game = &games("1. e4 e4 *")[0];
+        assert_eq!(replay(game).unwrap_err().kind(), ErrorKind::InvalidData);
     }
-    vec
 }
@@ -1,13 +1,20 @@
 extern crate colored;
 extern crate paw;
 
+mod analysis;
 mod board;
+mod bot;
 mod computer;
 mod display;
 mod execute;
 mod game_loop;
 mod input;
 mod place;
+mod puzzle;
+mod server;
+mod tablebase;
+mod tournament;
+mod tui;
 
 use crate::board::history::History;
 use crate::board::king_status::KingStatus;
@@ -20,25 +27,165 @@ use crate::place::sq::{Sq, SqLike};
 
 const SIZE: usize = 8;
 
+/// Shown under --help, since the modes are flags rather than subcommands.
+const MODES: &str = "MODES:
+    Modes are flags rather than subcommands, the one given first below wins.
+    --serve <addr>    The serve mode, a local HTTP/JSON API and browser board
+    --analyze         The analyze mode, the best lines in the position given by --fen
+    --puzzles <file>  The puzzle mode, solving the puzzles of a Lichess-style CSV file
+    --match <games>   The match mode, games between --white and --black with their score";
+
 // With the "paw" feature enabled in structopt
 #[derive(structopt::StructOpt)]
+#[structopt(after_help = MODES)]
 pub struct Args {
     /// FEN formatted Board.
     #[structopt(long = "fen")]
     fen: Option<String>,
+    /// Starts from Chess960 position 0 to 959, or a random one each game without a number.
+    #[structopt(long = "chess960")]
+    chess960: Option<Option<usize>>,
     /// Parses input as one or more PGN(s), and execute them.
     #[structopt(long = "pgn")]
     pgn: Option<String>,
-    /// Who is playing as White
+    /// Who is playing as White: player, computer[:depth=N,movetime=MS,elo=N], random, book-only
+    /// or uci:<path>.
     #[structopt(short = "w", long = "white", default_value = "player")]
     white: String,
-    /// Who is playing as Black
+    /// Who is playing as Black, like --white.
     #[structopt(short = "b", long = "black", default_value = "player")]
     black: String,
+    /// Time control for both sides in minutes plus increment in seconds, e.g. 5+3.
+    #[structopt(long = "time-control")]
+    time_control: Option<game_loop::clock::Clock>,
+    /// How pieces are drawn: letters, unicode or ascii. Plain ascii when not printing to a terminal.
+    #[structopt(long = "theme")]
+    theme: Option<String>,
+    /// Plays on the full terminal, moving pieces with the arrow keys or the mouse.
+    #[structopt(long = "tui")]
+    tui: bool,
+    /// Reads JSON commands from stdin and writes one JSON event per line, for bots.
+    #[structopt(long = "json")]
+    json: bool,
+    /// Board colors: classic, wood, high-contrast, colorblind, or light,dark[,white,black] names.
+    #[structopt(long = "palette", default_value = "classic")]
+    palette: String,
+    /// Serves games over a local HTTP/JSON API at this address, e.g. 127.0.0.1:8080.
+    #[structopt(long = "serve")]
+    serve: Option<String>,
+    /// Lets --serve listen on addresses other machines can reach, like 0.0.0.0. There is no login.
+    #[structopt(long = "serve-public")]
+    serve_public: bool,
+    /// Saves the game, players, clocks and history to this JSON file when leaving.
+    #[structopt(long = "save")]
+    save: Option<String>,
+    /// Continues a game saved with --save, and saves it back there unless --save says otherwise.
+    #[structopt(long = "resume")]
+    resume: Option<String>,
+    /// Polyglot opening book (.bin) for the computer, instead of data/openings.json.
+    #[structopt(long = "book")]
+    book: Option<String>,
+    /// How the computer picks book moves: best, weighted or repertoire:<name>.
+    #[structopt(long = "book-policy", default_value = "weighted")]
+    book_policy: computer::book::BookPolicy,
+    /// Leave the opening book after this many moves per side.
+    #[structopt(long = "book-depth")]
+    book_depth: Option<usize>,
+    /// Writes a Polyglot opening book built from the games given by --pgn.
+    #[structopt(long = "build-book")]
+    build_book: Option<String>,
+    /// Directory of endgame tablebases the computer probes, and --generate-tablebase writes to.
+    #[structopt(long = "tablebases")]
+    tablebases: Option<String>,
+    /// Directory of Syzygy WDL/DTZ tables (.rtbw/.rtbz) the computer probes. Needs a build with
+    /// `--features syzygy`.
+    #[structopt(long = "syzygy")]
+    syzygy: Option<String>,
+    /// Generates the tablebase for a material like KQK or KQKR, and the smaller ones it needs.
+    #[structopt(long = "generate-tablebase")]
+    generate_tablebase: Option<String>,
+    /// Plays this many games between --white and --black, alternating colors, and reports the score.
+    #[structopt(long = "match")]
+    games: Option<usize>,
+    /// EPD file of opening positions for --match, instead of random book lines.
+    #[structopt(long = "openings")]
+    openings: Option<String>,
+    /// How many --match games are played at once, by default one per core.
+    #[structopt(long = "concurrency")]
+    concurrency: Option<usize>,
+    /// Where --match writes its games.
+    #[structopt(long = "match-pgn", default_value = "match.pgn")]
+    match_pgn: String,
+    /// Stops --match once "elo0,elo1[,alpha,beta]" is decided by a sequential probability ratio test.
+    #[structopt(long = "sprt")]
+    sprt: Option<String>,
+    /// Prints the best lines in the position given by --fen, as JSON with --json.
+    #[structopt(long = "analyze")]
+    analyze: bool,
+    /// Writes the games of this PGN file with the engine's evaluations, marked mistakes and better
+    /// lines, and reports the average centipawn loss of each player.
+    #[structopt(long = "annotate")]
+    annotate: Option<String>,
+    /// How many plies --analyze, --annotate and --extract-puzzles look ahead.
+    #[structopt(long = "depth")]
+    depth: Option<usize>,
+    /// How many milliseconds --analyze thinks, or --annotate and --extract-puzzles think per
+    /// position, instead of going to a depth.
+    #[structopt(long = "time")]
+    time: Option<u64>,
+    /// How many of the best lines --analyze prints.
+    #[structopt(long = "multipv", default_value = "1")]
+    multipv: usize,
+    /// Tactics puzzles to solve, from a CSV file in the layout of the Lichess puzzle database.
+    #[structopt(long = "puzzles")]
+    puzzles: Option<String>,
+    /// Where the puzzles solved, the success rate and streaks are kept between runs.
+    #[structopt(long = "puzzle-stats", default_value = "puzzles.json")]
+    puzzle_stats: String,
+    /// Writes puzzles found in the games given by --pgn to this CSV file, in the layout --puzzles
+    /// reads.
+    #[structopt(long = "extract-puzzles")]
+    extract_puzzles: Option<String>,
 }
 
 #[paw::main]
 fn main(args: Args) -> std::io::Result<()> {
+    if let Some(book) = args.build_book {
+        return match args.pgn {
+            Some(pgn) => computer::polyglot::build(pgn, book),
+            None => Err(std::io::Error::other("--build-book needs games from --pgn")),
+        };
+    }
+    if let Some(out) = &args.extract_puzzles {
+        return match &args.pgn {
+            Some(pgn) => puzzle::extract::run(&args, pgn, out),
+            None => Err(std::io::Error::other(
+                "--extract-puzzles needs games from --pgn",
+            )),
+        };
+    }
+    if let Some(material) = args.generate_tablebase {
+        let dir = args
+            .tablebases
+            .unwrap_or_else(|| tablebase::DIRECTORY.to_string());
+        return tablebase::build(material, dir);
+    }
+    if let Some(addr) = &args.serve {
+        let computer = game_loop::opponent::Opponent::Computer(Default::default()).init(&args)?;
+        return server::serve(addr, computer, args.serve_public);
+    }
+    if let Some(path) = &args.annotate {
+        return analysis::annotate(&args, path);
+    }
+    if args.analyze {
+        return analysis::run(&args);
+    }
+    if let Some(path) = &args.puzzles {
+        return puzzle::run(&args, path);
+    }
+    if let Some(games) = args.games {
+        return tournament::run(&args, games);
+    }
     match args.pgn {
         Some(pgn) => game_loop::automatic_game_loop(pgn),
         None => game_loop::manual_game_loop(args),
@@ -1,7 +1,8 @@
 use crate::place::sq::Sq;
 use crate::{Piece, Team};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Entity {
     pub kind: Piece,
     pub team: Team,
@@ -1,14 +1,17 @@
-use std::convert::TryInto;
-use std::io::{Error, ErrorKind, Result};
+use std::io::{Error, Result};
 use std::ops::{Add, Sub};
 use std::{char, cmp, fmt};
 
 use crate::{OptSq, Piece, SIZE};
+use serde::{Deserialize, Serialize};
 
 pub trait SqLike {
     fn into(sq: Sq, piece: Option<Piece>) -> Self;
+    #[allow(dead_code)]
     fn get_rank(&self) -> usize;
+    #[allow(dead_code)]
     fn get_file(&self) -> usize;
+    #[allow(dead_code)]
     fn get_sq(&self) -> &Sq;
 }
 
@@ -27,7 +30,7 @@ impl SqLike for Sq {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Sq {
     pub digit: usize,
     pub letter: usize,
@@ -53,30 +56,31 @@ impl Sq {
         let mut chars = notation.chars().rev();
         let rank = match chars.next() {
             Some(c) => c,
-            None => return Err(Error::new(ErrorKind::Other, "len was 0, must be 2")),
+            None => return Err(Error::other("len was 0, must be 2")),
         };
         let rank = match rank.to_digit(10) {
-            Some(d) => (d as usize - 1),
-            None => return Err(Error::new(ErrorKind::Other, "invalid notation")),
+            Some(d) => d as usize - 1,
+            None => return Err(Error::other("invalid notation")),
         };
         let file = match chars.next() {
             Some(c) => c,
-            None => return Err(Error::new(ErrorKind::Other, "len was 1, must be 2")),
+            None => return Err(Error::other("len was 1, must be 2")),
         };
         let file = match file.to_digit(10 + SIZE as u32) {
             Some(d) => d as usize - 10,
-            None => return Err(Error::new(ErrorKind::Other, "not letter")),
+            None => return Err(Error::other("not letter")),
         };
 
         Ok(Sq::new(rank, file))
     }
     pub fn dark_square(&self) -> bool {
-        self.digit % 2 == 0 && self.letter % 2 == 0 || self.digit % 2 != 0 && self.letter % 2 != 0
+        self.digit.is_multiple_of(2) && self.letter.is_multiple_of(2)
+            || !self.digit.is_multiple_of(2) && !self.letter.is_multiple_of(2)
     }
     pub fn valid_idx<T: Into<isize>>(rank: T, file: T) -> bool {
         let rank = rank.into();
         let file = file.into();
-        !(rank > 7 || rank < 0 || file > 7 || file < 0)
+        !(!(0..=7).contains(&rank) || !(0..=7).contains(&file))
     }
     pub fn try_into(rank: isize, file: isize) -> Option<Sq> {
         match Sq::valid_idx(rank, file) {
@@ -95,8 +99,8 @@ impl Sq {
         sq
     }
     pub fn mutate(&self, rank: isize, file: isize) -> Option<Sq> {
-        let i_rank: isize = self.digit as isize + rank as isize;
-        let i_file: isize = self.letter as isize + file as isize;
+        let i_rank: isize = self.digit as isize + rank;
+        let i_file: isize = self.letter as isize + file;
         match Sq::valid_idx(i_rank, i_file) {
             true => Some(Sq::new(i_rank as usize, i_file as usize)),
             false => None,
@@ -112,8 +116,8 @@ impl Sub for Sq {
 
     fn sub(self, other: Self) -> Self::Output {
         Self {
-            digit: self.digit.checked_sub(other.digit).or(Some(0)).unwrap(),
-            letter: self.letter.checked_sub(other.letter).or(Some(0)).unwrap(),
+            digit: self.digit.saturating_sub(other.digit),
+            letter: self.letter.saturating_sub(other.letter),
         }
     }
 }
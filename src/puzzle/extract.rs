@@ -0,0 +1,470 @@
+//! Puzzles found in played games: positions where one move, and no other, mates or wins
+//! decisive material. Each is written as it would be in the Lichess puzzle database, starting
+//! before the opponent's move that allowed it, with theme tags for the tactics in the solution.
+
+use super::{Puzzle, HEADER};
+use crate::analysis;
+use crate::board::history::Move;
+use crate::computer::search::{self, Limits, Line, Score};
+use crate::input::pgn::{self, Pgn};
+use crate::place::entity::Entity;
+use crate::{Args, Board, Piece, Sq, Team};
+use std::fs::{self, File};
+use std::io::{self, Write};
+
+/// What the solution has to win when it doesn't mate, in centipawns.
+const DECISIVE: i32 = 300;
+/// The most the second best move may get, for the solution to be the only move.
+const UNCLEAR: i32 = 100;
+/// The most moves a solution winning material takes.
+const MOVES: usize = 3;
+
+const ROOK: [(isize, isize); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP: [(isize, isize); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const QUEEN: [(isize, isize); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// A puzzle taken from a game.
+pub struct Found {
+    pub puzzle: Puzzle,
+    /// Lichess names of the tactics, e.g. "fork" or "mateIn2".
+    pub themes: Vec<&'static str>,
+    /// Whether the player in the game found the solution.
+    pub found: bool,
+}
+
+impl Found {
+    /// The puzzle as a line of the file `--puzzles` reads.
+    pub fn record(&self, url: &str) -> String {
+        let moves: Vec<String> = self.puzzle.moves.iter().map(Move::uci).collect();
+        format!(
+            "{},{},{},,,,,{},{},",
+            self.puzzle.id,
+            self.puzzle.start.fen(),
+            moves.join(" "),
+            self.themes.join(" "),
+            url
+        )
+    }
+}
+
+/// Looks for puzzles in every game of the PGN file at `path`, and writes them to `out`.
+pub fn run(args: &Args, path: &str, out: &str) -> io::Result<()> {
+    let limits = analysis::limits(args)?;
+    let games = pgn::games(&fs::read_to_string(path)?);
+    let mut file = File::create(out)?;
+    writeln!(file, "{}", HEADER)?;
+    let (mut puzzles, mut missed) = (0, 0);
+    for (n, game) in games.iter().enumerate() {
+        let found = match find(game, &limits) {
+            Ok(found) => found,
+            Err(e) => {
+                eprintln!("Game {} skipped: {}", n + 1, e);
+                continue;
+            }
+        };
+        let url = game
+            .tag("Site")
+            .filter(|site| site.starts_with("http"))
+            .unwrap_or_default();
+        for found in found {
+            writeln!(file, "{}", found.record(&url))?;
+            puzzles += 1;
+            if !found.found {
+                missed += 1;
+            }
+        }
+    }
+    println!(
+        "{} game(s) parsed, {} puzzles written to {}, {} of them missed in the game",
+        games.len(),
+        puzzles,
+        out,
+        missed
+    );
+    Ok(())
+}
+
+/// The puzzles of a game, each in the position before the opponent's move that set it up.
+pub fn find(game: &Pgn, limits: &Limits) -> io::Result<Vec<Found>> {
+    let (start, end) = pgn::replay(game)?;
+    let played = pgn::played(&start, &end)?;
+    let mut found = Vec::new();
+    // Positions inside the solution of the last puzzle are part of it.
+    let mut next = 1;
+    for n in 1..played.len() {
+        if n < next {
+            continue;
+        }
+        let (board, mov) = &played[n];
+        let analysis = search::analyze(board, limits, 2);
+        if !only_move(&analysis.lines) {
+            continue;
+        }
+        let solution = match solution(board, &analysis.lines[0], limits) {
+            Some(solution) => solution,
+            None => continue,
+        };
+        let (before, reply) = &played[n - 1];
+        let first = &solution[0];
+        let same = (first.from, first.to, first.promotion) == (mov.from, mov.to, mov.promotion);
+        next = n + solution.len();
+        let mut moves = vec![reply.clone()];
+        moves.extend(solution);
+        found.push(Found {
+            themes: themes(board, &moves[1..]),
+            puzzle: Puzzle {
+                id: format!("{:016x}", before.zobrist()),
+                start: before.clone(),
+                moves,
+                rating: None,
+            },
+            found: same,
+        });
+    }
+    Ok(found)
+}
+
+/// Whether the best of `lines` mates or wins, and the second doesn't. Mating in one is always
+/// a puzzle, since any mate solves it.
+fn only_move(lines: &[Line]) -> bool {
+    let (best, second) = match lines {
+        [best, second, ..] => (best.score, second.score),
+        _ => return false,
+    };
+    match (best, second) {
+        (Score::Mate(1), _) => true,
+        (Score::Mate(best), Score::Mate(second)) => best > 0 && second < 0,
+        (Score::Mate(moves), _) => moves > 0,
+        (Score::Centipawns(cp), Score::Centipawns(second)) => cp >= DECISIVE && second <= UNCLEAR,
+        (Score::Centipawns(cp), Score::Mate(moves)) => cp >= DECISIVE && moves < 0,
+    }
+}
+
+/// The moves of `line` the solver has to find, with the replies in between. Each of them has to
+/// be the only move too; a mate is dropped when one isn't, a win of material is cut short.
+fn solution(board: &Board, line: &Line, limits: &Limits) -> Option<Vec<Move>> {
+    let mate = matches!(line.score, Score::Mate(_));
+    let mut board = board.clone();
+    let mut moves = Vec::new();
+    for (ply, mov) in line.moves.iter().enumerate() {
+        if ply > 0 && ply.is_multiple_of(2) {
+            if !mate && ply / 2 >= MOVES {
+                break;
+            }
+            if !only_move(&search::analyze(&board, limits, 2).lines) {
+                match mate {
+                    true => return None,
+                    false => break,
+                }
+            }
+        }
+        let legal = board.legal(mov).ok()?;
+        board.apply(&legal).ok()?;
+        moves.push(legal);
+    }
+    // The solver has the last move.
+    if moves.len().is_multiple_of(2) {
+        moves.pop();
+    }
+    let mated = moves
+        .last()
+        .and_then(|mov| mov.label.as_ref())
+        .is_some_and(|san| san.ends_with('#'));
+    match mate == mated && !moves.is_empty() {
+        true => Some(moves),
+        false => None,
+    }
+}
+
+/// The tactics in `solution`, played from `board`.
+fn themes(board: &Board, solution: &[Move]) -> Vec<&'static str> {
+    let mut themes = Vec::new();
+    let mut board = board.clone();
+    for (ply, mov) in solution.iter().enumerate() {
+        let before = board.clone();
+        if board.apply(mov).is_err() {
+            break;
+        }
+        if !ply.is_multiple_of(2) {
+            continue;
+        }
+        let found = [
+            ("fork", fork(&board, mov)),
+            ("pin", lined_up(&board, mov, |front, back| back > front)),
+            (
+                "skewer",
+                lined_up(&board, mov, |front, back| front > back && back > 1),
+            ),
+            ("discoveredAttack", discovered(&before, &board, mov)),
+        ];
+        for (theme, _) in found.iter().filter(|(_, found)| *found) {
+            if !themes.contains(theme) {
+                themes.push(*theme);
+            }
+        }
+    }
+    let mated = solution
+        .last()
+        .and_then(|mov| mov.label.as_ref())
+        .is_some_and(|san| san.ends_with('#'));
+    if mated {
+        themes.push("mate");
+        themes.push(match solution.len().div_ceil(2) {
+            1 => "mateIn1",
+            2 => "mateIn2",
+            3 => "mateIn3",
+            4 => "mateIn4",
+            _ => "mateIn5",
+        });
+        if back_rank(&board, &solution[solution.len() - 1]) {
+            themes.push("backRankMate");
+        }
+    }
+    themes
+}
+
+/// Enemy pieces the piece on `sq` attacks.
+fn attacks(board: &Board, sq: Sq) -> Vec<(Sq, Entity)> {
+    let attacker = match board.get(sq) {
+        Some(entity) => entity,
+        None => return Vec::new(),
+    };
+    attacker.kind.get_translations()(board, sq, attacker.team, attacker.kind)
+        .into_iter()
+        .filter_map(|target| {
+            board
+                .get(target)
+                .filter(|entity| entity.team != attacker.team)
+                .map(|entity| (target, entity))
+        })
+        .collect()
+}
+
+/// Whether a piece on `sq` is covered by one of its own.
+fn defended(board: &Board, sq: Sq) -> bool {
+    let entity = match board.get(sq) {
+        Some(entity) => entity,
+        None => return false,
+    };
+    // Pieces only ever attack the other team, so the defender sees an enemy there.
+    let mut probe = board.clone();
+    probe.place(sq, Entity::new(entity.kind, entity.team.not()));
+    probe
+        .find_by_team(entity.team)
+        .iter()
+        .any(|defender| attacks(&probe, defender.sq).iter().any(|(to, _)| *to == sq))
+}
+
+/// Whether attacking `target` on `sq` with a piece worth `value` wins something: the king, a
+/// more valuable piece, or one left undefended.
+fn worth(board: &Board, sq: Sq, target: Entity, value: usize) -> bool {
+    target.kind == Piece::King || target.kind.value() > value || !defended(board, sq)
+}
+
+/// The piece that just moved attacks two pieces worth attacking at once.
+fn fork(board: &Board, mov: &Move) -> bool {
+    let piece = match board.get(mov.to) {
+        Some(entity) => entity.kind,
+        None => return false,
+    };
+    let targets = attacks(board, mov.to)
+        .into_iter()
+        .filter(|(sq, target)| {
+            target.kind != Piece::Pawn && worth(board, *sq, *target, piece.value())
+        })
+        .count();
+    targets >= 2
+}
+
+fn directions(piece: Piece) -> &'static [(isize, isize)] {
+    match piece {
+        Piece::Rook => &ROOK,
+        Piece::Bishop => &BISHOP,
+        Piece::Queen => &QUEEN,
+        _ => &[],
+    }
+}
+
+/// The first two pieces seen from `sq` looking in `direction`, with the squares before them.
+fn ray(board: &Board, sq: Sq, (rank, file): (isize, isize)) -> (Vec<Sq>, Vec<(Sq, Entity)>) {
+    let mut empty = Vec::new();
+    let mut pieces = Vec::new();
+    let mut next = sq.mutate(rank, file);
+    while let Some(sq) = next {
+        match board.get(sq) {
+            Some(entity) => {
+                pieces.push((sq, entity));
+                if pieces.len() == 2 {
+                    break;
+                }
+            }
+            None if pieces.is_empty() => empty.push(sq),
+            None => (),
+        }
+        next = sq.mutate(rank, file);
+    }
+    (empty, pieces)
+}
+
+/// The piece that just moved attacks two enemy pieces lined up behind each other, whose
+/// values satisfy `order`, e.g. a more valuable one behind for a pin.
+fn lined_up(board: &Board, mov: &Move, order: impl Fn(usize, usize) -> bool) -> bool {
+    let attacker = match board.get(mov.to) {
+        Some(entity) => entity,
+        None => return false,
+    };
+    directions(attacker.kind)
+        .iter()
+        .any(|direction| match ray(board, mov.to, *direction).1[..] {
+            [(_, front), (_, back)] => {
+                front.team != attacker.team
+                    && back.team != attacker.team
+                    && order(front.kind.value(), back.kind.value())
+            }
+            _ => false,
+        })
+}
+
+/// Moving out of the way let another piece attack something worth attacking.
+fn discovered(before: &Board, board: &Board, mov: &Move) -> bool {
+    let team = before.turn_order;
+    board
+        .find_by_team(team)
+        .iter()
+        .filter(|slider| slider.sq != mov.to)
+        .any(|slider| {
+            let value = slider.entity.kind.value();
+            directions(slider.entity.kind).iter().any(|direction| {
+                match ray(board, slider.sq, *direction) {
+                    (empty, pieces) if empty.contains(&mov.from) => {
+                        pieces.first().is_some_and(|(sq, target)| {
+                            target.team != team && worth(board, *sq, *target, value)
+                        })
+                    }
+                    _ => false,
+                }
+            })
+        })
+}
+
+/// Mate by a rook or queen along the back rank of the mated king.
+fn back_rank(board: &Board, mate: &Move) -> bool {
+    let mated = board.turn_order;
+    let home = match mated {
+        Team::White => 0,
+        Team::Black => 7,
+    };
+    let king = board
+        .find_by_team(mated)
+        .into_iter()
+        .find(|piece| piece.entity.kind == Piece::King);
+    let checker = board.get(mate.to).map(|entity| entity.kind);
+    king.is_some_and(|king| king.sq.digit == home)
+        && mate.to.digit == home
+        && matches!(checker, Some(Piece::Rook | Piece::Queen))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn board(fen: &str) -> Board {
+        fen.to_string().try_into().unwrap()
+    }
+
+    fn moves(board: &Board, sans: &[&str]) -> Vec<Move> {
+        let mut board = board.clone();
+        sans.iter()
+            .map(|san| {
+                let mov = board.find_move(san).unwrap();
+                board.apply(&mov).unwrap();
+                mov
+            })
+            .collect()
+    }
+
+    fn themes_of(fen: &str, sans: &[&str]) -> Vec<&'static str> {
+        let board = board(fen);
+        themes(&board, &moves(&board, sans))
+    }
+
+    #[test]
+    fn test_themes() {
+        // The knight checks the king and attacks the rook.
+        assert_eq!(
+            themes_of("r3k3/8/8/1N6/8/8/8/4K3 w - - 0 1", &["Nc7+"]),
+            ["fork"]
+        );
+        // The rook can't leave the file without exposing its king.
+        assert_eq!(
+            themes_of("4k3/4r3/8/8/8/8/8/R5K1 w - - 0 1", &["Re1"]),
+            ["pin"]
+        );
+        assert_eq!(
+            themes_of("8/8/q3k3/8/8/8/8/4K2R w - - 0 1", &["Rh6+"]),
+            ["skewer"]
+        );
+        // The bishop leaves the file with check, and the rook takes the queen.
+        assert_eq!(
+            themes_of("4q1k1/8/8/8/4B3/8/8/4R1K1 w - - 0 1", &["Bd5+"]),
+            ["discoveredAttack"]
+        );
+        assert_eq!(
+            themes_of("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", &["Ra8#"]),
+            ["mate", "mateIn1", "backRankMate"]
+        );
+    }
+
+    #[test]
+    fn test_find() {
+        // 3... Nf6?? lets White mate, and White takes the chance.
+        let data = "[Site \"https://example.org/1\"]\n\n\
+            1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n";
+        let game = &pgn::games(data)[0];
+        let limits = Limits {
+            depth: Some(3),
+            ..Limits::default()
+        };
+        let found = find(game, &limits).unwrap();
+        let mate = found.last().unwrap();
+        assert!(mate.found);
+        assert_eq!(mate.themes, ["mate", "mateIn1"]);
+        let uci: Vec<String> = mate.puzzle.moves.iter().map(Move::uci).collect();
+        assert_eq!(uci, ["g8f6", "h5f7"]);
+        let record = mate.record("https://example.org/1");
+        assert!(record.ends_with(",https://example.org/1,"), "{}", record);
+        // The record reads back as a puzzle, starting with the move that allowed the tactic.
+        let puzzle = Puzzle::parse(&record).unwrap();
+        assert_eq!(puzzle.id, mate.puzzle.id);
+        assert_eq!(puzzle.start.fen(), mate.puzzle.start.fen());
+    }
+
+    #[test]
+    fn test_only_move() {
+        let line = |score| Line {
+            score,
+            moves: Vec::new(),
+        };
+        let cp = Score::Centipawns;
+        assert!(only_move(&[line(cp(500)), line(cp(0))]));
+        assert!(!only_move(&[line(cp(500)), line(cp(400))]));
+        assert!(!only_move(&[line(cp(200)), line(cp(0))]));
+        assert!(!only_move(&[line(cp(900))]));
+        assert!(only_move(&[line(Score::Mate(2)), line(cp(900))]));
+        assert!(!only_move(&[line(Score::Mate(2)), line(Score::Mate(3))]));
+        assert!(only_move(&[line(Score::Mate(1)), line(Score::Mate(1))]));
+        assert!(only_move(&[line(Score::Mate(3)), line(Score::Mate(-2))]));
+        // Every move loses, there is nothing to find.
+        assert!(!only_move(&[line(Score::Mate(-3)), line(Score::Mate(-1))]));
+    }
+}
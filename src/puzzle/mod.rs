@@ -0,0 +1,401 @@
+//! Tactics puzzles from a local file, in the layout of the Lichess puzzle database:
+//!
+//! ```text
+//! PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags
+//! ```
+//!
+//! The FEN is the position before the opponent's move, the first of the UCI moves. The player
+//! finds the others, and the opponent's replies are played in between.
+
+use crate::board::history::Move;
+use crate::bot::{JsonPlayer, JsonScreen};
+use crate::computer::player::Player;
+use crate::computer::{Action, Playable};
+use crate::game_loop::screen::{Screen, Terminal};
+use crate::game_loop::session::{Flow, Session};
+use crate::input::command::Command;
+use crate::input::pgn;
+use crate::tui::{Tui, TuiPlayer, TuiScreen};
+use crate::{Args, Board, Team};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+pub mod extract;
+
+/// First line of the Lichess puzzle database, which `load` skips.
+pub const HEADER: &str =
+    "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags";
+
+pub struct Puzzle {
+    pub id: String,
+    /// The position before the opponent's first move.
+    pub start: Board,
+    /// The opponent's first move, then the solution and the replies to it.
+    pub moves: Vec<Move>,
+    pub rating: Option<u32>,
+}
+
+/// What a move makes of the puzzle.
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    /// The move of the solution, there is more to find.
+    Right,
+    Solved,
+    Wrong,
+}
+
+impl Puzzle {
+    /// Reads a line of the puzzle file, checking that the moves are legal.
+    pub fn parse(line: &str) -> io::Result<Puzzle> {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let (id, fen, moves) = match fields[..] {
+            [id, fen, moves, ..] => (id, fen, moves),
+            _ => return Err(io::Error::other("a puzzle needs an id, a FEN and moves")),
+        };
+        let start: Board = fen.to_string().try_into()?;
+        let mut board = start.clone();
+        let mut solution = Vec::new();
+        for uci in moves.split_whitespace() {
+            let mov = board.find_move(uci).map_err(|_| {
+                io::Error::other(format!("{} is not a legal move in {}", uci, board.fen()))
+            })?;
+            board.apply(&mov)?;
+            solution.push(mov);
+        }
+        // The opponent's move and at least one to find.
+        if solution.len() < 2 {
+            return Err(io::Error::other(format!("puzzle {} has no solution", id)));
+        }
+        // Every reply is followed by a move to find, so the solver plays the last move.
+        if solution.len() % 2 != 0 {
+            return Err(io::Error::other(format!(
+                "puzzle {} ends on the opponent's move",
+                id
+            )));
+        }
+        Ok(Puzzle {
+            id: id.to_string(),
+            start,
+            moves: solution,
+            rating: fields.get(3).and_then(|rating| rating.parse().ok()),
+        })
+    }
+
+    /// Judges `mov`, played in `board` as the `ply`th move of the puzzle. Any mate solves it,
+    /// even one the solution doesn't give.
+    pub fn check(&self, ply: usize, board: &Board, mov: &Move) -> io::Result<Verdict> {
+        let legal = board.legal(mov)?;
+        let expected = &self.moves[ply];
+        let same = (legal.from, legal.to, legal.promotion)
+            == (expected.from, expected.to, expected.promotion);
+        let mate = legal.label.as_ref().is_some_and(|san| san.ends_with('#'));
+        Ok(match (same, mate) {
+            (true, _) if ply + 1 == self.moves.len() => Verdict::Solved,
+            (true, _) => Verdict::Right,
+            (false, true) => Verdict::Solved,
+            (false, false) => Verdict::Wrong,
+        })
+    }
+}
+
+/// Reads every puzzle of the file, skipping the header line.
+pub fn load(data: &str) -> io::Result<Vec<Puzzle>> {
+    data.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with(HEADER))
+        .map(|(n, line)| {
+            Puzzle::parse(line).map_err(|e| io::Error::other(format!("line {}: {}", n + 1, e)))
+        })
+        .collect()
+}
+
+/// How the player has done at puzzles so far, kept in a JSON file between runs.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Stats {
+    pub solved: usize,
+    pub failed: usize,
+    /// Puzzles solved in a row.
+    pub streak: usize,
+    pub best_streak: usize,
+    /// Ids of the puzzles tried, which are not given again.
+    seen: BTreeSet<String>,
+}
+
+impl Stats {
+    /// The stats saved at `path`, or none yet if there is no such file.
+    pub fn load(path: &str) -> io::Result<Stats> {
+        if !Path::new(path).exists() {
+            return Ok(Stats::default());
+        }
+        serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::other(format!("can't read puzzle stats {}: {}", path, e)))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn record(&mut self, id: &str, solved: bool) {
+        self.seen.insert(id.to_string());
+        if solved {
+            self.solved += 1;
+            self.streak += 1;
+            self.best_streak = self.best_streak.max(self.streak);
+        } else {
+            self.failed += 1;
+            self.streak = 0;
+        }
+    }
+
+    pub fn seen(&self, id: &str) -> bool {
+        self.seen.contains(id)
+    }
+
+    /// Share of the puzzles tried that were solved, in percent.
+    pub fn success_rate(&self) -> f64 {
+        match self.solved + self.failed {
+            0 => 0.0,
+            tried => 100.0 * self.solved as f64 / tried as f64,
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Solved {} of {} ({:.0}%), streak {}, best streak {}",
+            self.solved,
+            self.solved + self.failed,
+            self.success_rate(),
+            self.streak,
+            self.best_streak
+        )
+    }
+}
+
+/// How a puzzle was left.
+enum Outcome {
+    Solved,
+    Failed,
+    /// Left for the next one with `new`, without counting it.
+    Skipped,
+    Quit,
+}
+
+/// Gives the puzzles of the file at `path` not tried before, one after the other, and keeps
+/// the stats in `--puzzle-stats`.
+pub fn run(args: &Args, path: &str) -> io::Result<()> {
+    let theme = crate::game_loop::theme(args)?;
+    if args.tui && args.json {
+        return Err(io::Error::other("--tui and --json can't be used together"));
+    }
+    let puzzles = load(&fs::read_to_string(path)?)?;
+    let mut stats = Stats::load(&args.puzzle_stats)?;
+    let tui = match args.tui {
+        true => Some(Rc::new(RefCell::new(Tui::new()?))),
+        false => None,
+    };
+    let (mut player, mut screen): (Box<dyn Playable>, Box<dyn Screen>) = match &tui {
+        Some(tui) => (
+            Box::new(TuiPlayer::new(tui.clone())),
+            Box::new(TuiScreen(tui.clone())),
+        ),
+        None if args.json => (
+            Box::new(JsonPlayer),
+            Box::new(JsonScreen::new(io::stdout())),
+        ),
+        None => (Box::new(Player::new()), Box::new(Terminal)),
+    };
+
+    for puzzle in &puzzles {
+        if stats.seen(&puzzle.id) {
+            continue;
+        }
+        let team = puzzle.start.turn_order.not();
+        let [white, black] = match team {
+            Team::White => ["player", "puzzle"],
+            Team::Black => ["puzzle", "player"],
+        };
+        let mut session = Session::new(puzzle.start.clone(), white, black, vec![team], None);
+        session.view.theme = theme.clone();
+        let solved = match solve(puzzle, &mut session, player.as_mut(), screen.as_mut())? {
+            Outcome::Solved => true,
+            Outcome::Failed => false,
+            Outcome::Skipped => continue,
+            Outcome::Quit => return Ok(()),
+        };
+        stats.record(&puzzle.id, solved);
+        stats.save(&args.puzzle_stats)?;
+        screen.show(&stats.to_string())?;
+    }
+    screen.show("No puzzles left.")
+}
+
+/// Plays the opponent's first move, then asks for the solution until it is found or missed.
+fn solve(
+    puzzle: &Puzzle,
+    session: &mut Session,
+    player: &mut dyn Playable,
+    screen: &mut dyn Screen,
+) -> io::Result<Outcome> {
+    session.play(&puzzle.moves[0])?;
+    let rating = match puzzle.rating {
+        Some(rating) => format!(", rated {}", rating),
+        None => String::new(),
+    };
+    screen.present(session)?;
+    screen.show(&format!(
+        "Puzzle {}{}: find the best move for {}.",
+        puzzle.id, rating, session.board.turn_order
+    ))?;
+    let mut ply = 1;
+    loop {
+        let mov = match player.action(&session.context()) {
+            Ok(Action::Move(mov)) => mov,
+            Ok(Action::Command(Command::Quit)) => return Ok(Outcome::Quit),
+            Ok(Action::Command(Command::New)) => return Ok(Outcome::Skipped),
+            Ok(Action::Command(Command::Resign)) => {
+                let solution = pgn::line(&session.board, &puzzle.moves[ply..])?;
+                screen.show(&format!("The solution was {}.", solution))?;
+                return Ok(Outcome::Failed);
+            }
+            Ok(Action::Command(
+                Command::Undo | Command::Redo | Command::Draw | Command::Load(_),
+            )) => {
+                screen.error(io::Error::other("not available in puzzles"))?;
+                continue;
+            }
+            Ok(Action::Command(command)) => {
+                match session.command(command) {
+                    Ok(Flow::Show(text)) => screen.show(&text)?,
+                    Ok(_) => screen.present(session)?,
+                    Err(e) => screen.error(e)?,
+                }
+                continue;
+            }
+            Err(e) => {
+                screen.error(e)?;
+                continue;
+            }
+        };
+        let verdict = match puzzle.check(ply, &session.board, &mov) {
+            Ok(verdict) => verdict,
+            Err(e) => {
+                screen.error(e)?;
+                continue;
+            }
+        };
+        if verdict == Verdict::Wrong {
+            let solution = pgn::line(&session.board, &puzzle.moves[ply..])?;
+            screen.show(&format!("That's not it, the solution was {}.", solution))?;
+            return Ok(Outcome::Failed);
+        }
+        session.play(&mov)?;
+        if verdict == Verdict::Solved {
+            screen.present(session)?;
+            screen.show("Solved!")?;
+            return Ok(Outcome::Solved);
+        }
+        session.play(&puzzle.moves[ply + 1])?;
+        ply += 2;
+        screen.present(session)?;
+        screen.show("Right, keep going.")?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LICHESS: &str = "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags
+00sHx,q3k1nr/1pp1nQpp/3p4/1P2p3/4P3/B1PP1b2/B5PP/5K2 b k - 0 17,e8d7 a2e6 d7d8 f7f8,1760,80,83,72,mate mateIn2 middlegame short,https://lichess.org/yyznGmXs/black#34,Italian_Game
+";
+    /// Two rooks with two ways to mate.
+    const MATES: &str = "mates,6k1/p4ppp/8/8/8/8/8/2RR2K1 b - - 0 1,a7a6 c1c8";
+
+    #[test]
+    fn test_load() {
+        let puzzles = load(LICHESS).unwrap();
+        assert_eq!(puzzles.len(), 1);
+        let puzzle = &puzzles[0];
+        assert_eq!(puzzle.id, "00sHx");
+        assert_eq!(puzzle.rating, Some(1760));
+        assert_eq!(puzzle.moves.len(), 4);
+        assert_eq!(puzzle.moves[1].label.as_deref(), Some("Be6+"));
+
+        let err = load("x,8/8/8/8/8/2k5/8/K7 w - - 0 1,a1a3").err().unwrap();
+        assert!(err
+            .to_string()
+            .starts_with("line 1: a1a3 is not a legal move"));
+        assert!(load("x,8/8/8/8/8/2k5/8/K7 w - - 0 1,a1a2").is_err());
+        assert!(load("x,not a fen").is_err());
+        let odd = "x,q3k1nr/1pp1nQpp/3p4/1P2p3/4P3/B1PP1b2/B5PP/5K2 b k - 0 17,e8d7 a2e6 d7d8";
+        let err = load(odd).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "line 1: puzzle x ends on the opponent's move"
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let puzzle = &load(LICHESS).unwrap()[0];
+        let mut board = puzzle.start.clone();
+        board.apply(&puzzle.moves[0]).unwrap();
+        let right = board.find_move("Be6+").unwrap();
+        assert_eq!(puzzle.check(1, &board, &right).unwrap(), Verdict::Right);
+        let wrong = board.find_move("Qxg7").unwrap();
+        assert_eq!(puzzle.check(1, &board, &wrong).unwrap(), Verdict::Wrong);
+        board.apply(&right).unwrap();
+        board.apply(&puzzle.moves[2]).unwrap();
+        let mate = board.find_move("Qf8#").unwrap();
+        assert_eq!(puzzle.check(3, &board, &mate).unwrap(), Verdict::Solved);
+        // Moves that can't be played are not judged.
+        assert!(puzzle.check(3, &board, &right).is_err());
+    }
+
+    #[test]
+    fn test_other_mate() {
+        let puzzle = Puzzle::parse(MATES).unwrap();
+        let mut board = puzzle.start.clone();
+        board.apply(&puzzle.moves[0]).unwrap();
+        for (san, verdict) in [
+            ("Rc8#", Verdict::Solved),
+            ("Rd8#", Verdict::Solved),
+            ("Rc7", Verdict::Wrong),
+        ] {
+            let mov = board.find_move(san).unwrap();
+            assert_eq!(puzzle.check(1, &board, &mov).unwrap(), verdict, "{}", san);
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut stats = Stats::default();
+        for (id, solved) in [("a", true), ("b", true), ("c", false), ("d", true)] {
+            stats.record(id, solved);
+        }
+        assert_eq!((stats.streak, stats.best_streak), (1, 2));
+        assert!(stats.seen("c") && !stats.seen("e"));
+        assert_eq!(
+            stats.to_string(),
+            "Solved 3 of 4 (75%), streak 1, best streak 2"
+        );
+
+        let path = std::env::temp_dir().join("chess_puzzle_stats.json");
+        let path = path.to_str().unwrap().to_string();
+        stats.save(&path).unwrap();
+        let loaded = Stats::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, stats);
+        assert_eq!(Stats::load("no/such/stats.json").unwrap(), Stats::default());
+    }
+}
@@ -0,0 +1,565 @@
+use crate::analysis;
+use crate::board::history::Move;
+use crate::computer::search::{self, Limits, Score};
+use crate::computer::{Action, Playable};
+use crate::game_loop::clock::Clock;
+use crate::game_loop::session::Session;
+use crate::input::command::Command;
+use crate::{Board, Team};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::{self, Read};
+use std::net::ToSocketAddrs;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// The board in the browser, served at `/`.
+const INDEX: &str = include_str!("index.html");
+
+/// Games kept at once, the least recently used one is dropped to make room for another.
+const MAX_GAMES: usize = 64;
+/// Games nobody asked about for this long are dropped.
+const IDLE: Duration = Duration::from_secs(60 * 60);
+/// Plies looked ahead to score each position of a game.
+const EVALUATION_DEPTH: usize = 2;
+/// Scores kept at once, they are all forgotten when there would be more.
+const MAX_EVALUATIONS: usize = 4096;
+/// Request bodies are a move or a FEN, anything longer than this is turned away.
+const MAX_BODY: u64 = 64 * 1024;
+
+/// Body of `POST /games`, which may be empty to start from the initial position.
+#[derive(Debug, Default, Deserialize)]
+struct NewGame {
+    fen: Option<String>,
+    /// Minutes plus increment, e.g. "5+3".
+    time_control: Option<String>,
+}
+
+/// Body of `POST /games/{id}/moves`.
+#[derive(Debug, Deserialize)]
+struct Submitted {
+    /// SAN or UCI, e.g. "Nf3" or "g1f3".
+    #[serde(rename = "move")]
+    mov: String,
+}
+
+/// What a request is answered with.
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: u16, value: Value) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: value.to_string(),
+        }
+    }
+
+    fn error(status: u16, message: impl ToString) -> Self {
+        Response::json(status, json!({ "error": message.to_string() }))
+    }
+}
+
+/// Games played over HTTP, each kept as a `Session` between requests.
+///
+/// | Request                     | Does                                        |
+/// |-----------------------------|---------------------------------------------|
+/// | `POST /games`               | starts a game, from `{"fen": ...}` if given |
+/// | `GET /games/{id}`           | the state of a game                         |
+/// | `POST /games/{id}/moves`    | plays `{"move": "e4"}`, in SAN or UCI       |
+/// | `POST /games/{id}/computer` | lets the computer move                      |
+/// | `POST /games/{id}/undo`     | takes back the last move                    |
+/// | `GET /games/{id}/pgn`       | the game as PGN                             |
+/// | `GET /`                     | the board to play on in a browser           |
+///
+/// Every answer but the PGN and the page is JSON, errors are `{"error": ...}`. At most
+/// `MAX_GAMES` are kept, and each only until it has been left alone for `IDLE`.
+pub struct Server {
+    games: HashMap<usize, Session>,
+    /// Scores of the positions the games went through, by FEN.
+    evaluations: HashMap<String, Value>,
+    /// When each game was last asked about.
+    used: HashMap<usize, Instant>,
+    idle: Duration,
+    next_id: usize,
+    computer: Box<dyn Playable>,
+}
+
+impl Server {
+    pub fn new(computer: Box<dyn Playable>) -> Self {
+        Server {
+            games: HashMap::new(),
+            evaluations: HashMap::new(),
+            used: HashMap::new(),
+            idle: IDLE,
+            next_id: 1,
+            computer,
+        }
+    }
+
+    /// Answers a request for `path`, which may carry a query string that is ignored.
+    pub fn handle(&mut self, method: &str, path: &str, body: &str) -> Response {
+        self.expire();
+        let path = path.split('?').next().unwrap_or_default();
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        match (method, segments.as_slice()) {
+            ("GET", []) => Response {
+                status: 200,
+                content_type: "text/html; charset=utf-8",
+                body: INDEX.to_string(),
+            },
+            ("POST", ["games"]) => self.create(body),
+            (method, ["games", id, rest @ ..]) => {
+                let id = match id.parse::<usize>() {
+                    Ok(id) if self.games.contains_key(&id) => id,
+                    _ => return Response::error(404, format!("no game {}", id)),
+                };
+                self.used.insert(id, Instant::now());
+                match (method, rest) {
+                    ("GET", []) => self.state(id, 200),
+                    ("POST", ["moves"]) => self.submit(id, body),
+                    ("POST", ["computer"]) => self.computer(id),
+                    ("POST", ["undo"]) => self.undo(id),
+                    ("GET", ["pgn"]) => self.pgn(id),
+                    _ => Response::error(404, format!("no route for {} {}", method, path)),
+                }
+            }
+            _ => Response::error(404, format!("no route for {} {}", method, path)),
+        }
+    }
+
+    fn create(&mut self, body: &str) -> Response {
+        let request: NewGame = match body.trim() {
+            "" => NewGame::default(),
+            body => match serde_json::from_str(body) {
+                Ok(request) => request,
+                Err(e) => return Response::error(400, e),
+            },
+        };
+        let board: Board = match request.fen {
+            Some(fen) => match fen.try_into() {
+                Ok(board) => board,
+                Err(e) => return Response::error(400, e),
+            },
+            None => Board::new(),
+        };
+        let clock = match request.time_control.as_deref().map(Clock::from_str) {
+            Some(Ok(mut clock)) => {
+                clock.start();
+                Some(clock)
+            }
+            Some(Err(e)) => return Response::error(400, e),
+            None => None,
+        };
+        let humans = vec![Team::White, Team::Black];
+        let session = Session::new(board, "player", "player", humans, clock);
+        if self.games.len() >= MAX_GAMES {
+            let oldest = self.used.iter().min_by_key(|(_, used)| **used);
+            if let Some(id) = oldest.map(|(id, _)| *id) {
+                self.remove(id);
+            }
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.games.insert(id, session);
+        self.used.insert(id, Instant::now());
+        self.state(id, 201)
+    }
+
+    /// Drops the games left alone for longer than `idle`.
+    fn expire(&mut self) {
+        let idle = self.idle;
+        let expired: Vec<usize> = self
+            .used
+            .iter()
+            .filter(|(_, used)| used.elapsed() > idle)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            self.remove(id);
+        }
+    }
+
+    fn remove(&mut self, id: usize) {
+        self.games.remove(&id);
+        self.used.remove(&id);
+    }
+
+    /// The game as `Session::describe` has it, with its id and `evaluations`, the score of each
+    /// of its `positions` from White's side.
+    fn state(&mut self, id: usize, status: u16) -> Response {
+        let mut state = match self.games[&id].describe() {
+            Ok(state) => state,
+            Err(e) => return Response::error(500, e),
+        };
+        if self.evaluations.len() > MAX_EVALUATIONS {
+            self.evaluations.clear();
+        }
+        let scores = &mut self.evaluations;
+        let evaluations: Vec<Value> = state["positions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(|fen| {
+                scores
+                    .entry(fen.to_string())
+                    .or_insert_with(|| evaluate(fen))
+                    .clone()
+            })
+            .collect();
+        state["evaluations"] = json!(evaluations);
+        state["id"] = json!(id);
+        Response::json(status, state)
+    }
+
+    fn submit(&mut self, id: usize, body: &str) -> Response {
+        let request: Submitted = match serde_json::from_str(body) {
+            Ok(request) => request,
+            Err(e) => return Response::error(400, e),
+        };
+        let session = self.games.get_mut(&id).expect("game exists");
+        if session.board.state.is_over() {
+            return Response::error(409, &session.board.state);
+        }
+        let mov = match session.board.find_move(&request.mov) {
+            Ok(mov) => mov,
+            Err(e) => return Response::error(400, e),
+        };
+        if let Err(e) = play(session, &mov) {
+            return Response::error(400, e);
+        }
+        self.state(id, 200)
+    }
+
+    fn computer(&mut self, id: usize) -> Response {
+        let session = self.games.get_mut(&id).expect("game exists");
+        if session.board.state.is_over() {
+            return Response::error(409, &session.board.state);
+        }
+        let played = match self.computer.action(&session.context()) {
+            Ok(Action::Move(mov)) => play(session, &mov),
+            Ok(_) => Err(io::Error::other("the computer did not move")),
+            Err(e) => Err(e),
+        };
+        match played {
+            Ok(()) => self.state(id, 200),
+            Err(e) => Response::error(500, e),
+        }
+    }
+
+    fn undo(&mut self, id: usize) -> Response {
+        let session = self.games.get_mut(&id).expect("game exists");
+        match session.command(Command::Undo) {
+            Ok(_) => self.state(id, 200),
+            Err(e) => Response::error(409, e),
+        }
+    }
+
+    fn pgn(&self, id: usize) -> Response {
+        match self.games[&id].pgn() {
+            Ok(pgn) => Response {
+                status: 200,
+                content_type: "application/x-chess-pgn",
+                body: pgn,
+            },
+            Err(e) => Response::error(500, e),
+        }
+    }
+}
+
+/// How good the position is for White, as `analysis::score` writes it, or null when there are
+/// no moves left to look at.
+fn evaluate(fen: &str) -> Value {
+    let board: Board = match fen.to_string().try_into() {
+        Ok(board) => board,
+        Err(_) => return Value::Null,
+    };
+    let limits = Limits {
+        depth: Some(EVALUATION_DEPTH),
+        ..Limits::default()
+    };
+    let score = match search::analyze(&board, &limits, 1).lines.first() {
+        Some(line) => line.score,
+        None => return Value::Null,
+    };
+    match (board.turn_order, score) {
+        (Team::White, score) => analysis::score(score),
+        (Team::Black, Score::Centipawns(cp)) => analysis::score(Score::Centipawns(-cp)),
+        (Team::Black, Score::Mate(moves)) => analysis::score(Score::Mate(-moves)),
+    }
+}
+
+/// Plays a move and sets the clock running for the other side.
+fn play(session: &mut Session, mov: &Move) -> io::Result<()> {
+    session.play(mov)?;
+    if let Some(clock) = session.clock.as_mut() {
+        if !session.board.state.is_over() {
+            clock.start();
+        }
+    }
+    Ok(())
+}
+
+/// Serves games at `addr`, e.g. "127.0.0.1:8080", until the process is stopped. Anyone who can
+/// reach the address can play, so only loopback addresses are served unless `public` is set.
+pub fn serve(addr: &str, computer: Box<dyn Playable>, public: bool) -> io::Result<()> {
+    if !public && !loopback(addr)? {
+        let msg = format!(
+            "{} can be reached from other machines, add --serve-public to serve it anyway",
+            addr
+        );
+        return Err(io::Error::other(msg));
+    }
+    let http = tiny_http::Server::http(addr).map_err(io::Error::other)?;
+    println!(
+        "Serving games at http://{}, open it in a browser to play",
+        http.server_addr()
+    );
+    run(http, Server::new(computer))
+}
+
+/// Whether every address `addr` resolves to is on this machine.
+fn loopback(addr: &str) -> io::Result<bool> {
+    let addrs: Vec<_> = addr.to_socket_addrs()?.collect();
+    Ok(!addrs.is_empty() && addrs.iter().all(|addr| addr.ip().is_loopback()))
+}
+
+/// Reads a request body of at most `MAX_BODY` bytes.
+fn read_body(reader: impl Read) -> Result<String, Response> {
+    let mut body = String::new();
+    match reader.take(MAX_BODY + 1).read_to_string(&mut body) {
+        Ok(len) if len as u64 > MAX_BODY => Err(Response::error(
+            413,
+            format!("request body is over {} bytes", MAX_BODY),
+        )),
+        Ok(_) => Ok(body),
+        Err(e) => Err(Response::error(400, e)),
+    }
+}
+
+fn run(http: tiny_http::Server, mut server: Server) -> io::Result<()> {
+    for mut request in http.incoming_requests() {
+        let response = match read_body(request.as_reader()) {
+            Ok(body) => server.handle(request.method().as_str(), request.url(), &body),
+            Err(response) => response,
+        };
+        let header = tiny_http::Header::from_bytes("Content-Type", response.content_type)
+            .expect("valid header");
+        let answer = tiny_http::Response::from_string(response.body)
+            .with_status_code(response.status)
+            .with_header(header);
+        // A client that hung up before its answer shouldn't stop the others from being served.
+        if let Err(e) = request.respond(answer) {
+            eprintln!("Could not answer a request: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computer::book::{Book, BookOptions};
+    use crate::computer::{read_openings_file, Computer, OPENINGS_FILE};
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::thread;
+
+    fn server() -> Server {
+        let book = Book::Openings(read_openings_file(OPENINGS_FILE).unwrap());
+        Server::new(Box::new(Computer::with_book(book, BookOptions::default())))
+    }
+
+    fn body(response: &Response) -> Value {
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    #[test]
+    fn test_play_and_undo() {
+        let mut server = server();
+        let created = server.handle("POST", "/games", "");
+        assert_eq!(created.status, 201);
+        assert_eq!(body(&created)["id"], 1);
+        assert_eq!(body(&created)["legal_moves"].as_array().unwrap().len(), 20);
+
+        let san = server.handle("POST", "/games/1/moves", r#"{"move": "e4"}"#);
+        let uci = server.handle("POST", "/games/1/moves", r#"{"move": "e7e5"}"#);
+        assert_eq!(san.status, 200);
+        assert_eq!(body(&uci)["history"], json!(["e4", "e5"]));
+        assert_eq!(body(&uci)["turn"], "White");
+
+        let illegal = server.handle("POST", "/games/1/moves", r#"{"move": "Ke3"}"#);
+        assert_eq!(illegal.status, 400);
+        assert_eq!(body(&illegal)["error"], "Ke3 is not a legal move");
+
+        let undone = server.handle("POST", "/games/1/undo", "");
+        assert_eq!(body(&undone)["history"], json!(["e4"]));
+        let pgn = server.handle("GET", "/games/1/pgn", "");
+        assert_eq!(pgn.content_type, "application/x-chess-pgn");
+        assert!(pgn.body.ends_with("1. e4 *\n"));
+    }
+
+    #[test]
+    fn test_games_are_dropped() {
+        let mut server = server();
+        for _ in 0..=MAX_GAMES {
+            server.handle("POST", "/games", "");
+        }
+        assert_eq!(server.games.len(), MAX_GAMES);
+        assert_eq!(server.handle("GET", "/games/1", "").status, 404);
+        assert_eq!(server.handle("GET", "/games/2", "").status, 200);
+
+        server.idle = Duration::ZERO;
+        thread::sleep(Duration::from_millis(1));
+        assert_eq!(server.handle("GET", "/games/2", "").status, 404);
+        assert!(server.games.is_empty());
+    }
+
+    #[test]
+    fn test_loopback() {
+        assert!(loopback("127.0.0.1:8080").unwrap());
+        assert!(loopback("[::1]:8080").unwrap());
+        assert!(!loopback("0.0.0.0:8080").unwrap());
+        assert!(!loopback("192.168.1.2:8080").unwrap());
+        let refused = serve("0.0.0.0:0", server().computer, false);
+        assert!(refused.unwrap_err().to_string().contains("--serve-public"));
+    }
+
+    #[test]
+    fn test_computer_and_result() {
+        let mut server = server();
+        server.handle("POST", "/games", "");
+        let moved = server.handle("POST", "/games/1/computer", "");
+        assert_eq!(moved.status, 200);
+        assert_eq!(body(&moved)["turn"], "Black");
+
+        let fen = r#"{"fen": "k7/8/1K6/8/8/8/8/7Q w - - 0 1"}"#;
+        assert_eq!(server.handle("POST", "/games", fen).status, 201);
+        let mate = server.handle("POST", "/games/2/moves", r#"{"move": "Qh8#"}"#);
+        assert_eq!(body(&mate)["over"], true);
+        assert_eq!(body(&mate)["result"], "1-0");
+        assert_eq!(server.handle("POST", "/games/2/computer", "").status, 409);
+    }
+
+    #[test]
+    fn test_clock_and_positions() {
+        let mut server = server();
+        let created = server.handle("POST", "/games", r#"{"time_control": "5+3"}"#);
+        let clock = &body(&created)["clock"];
+        assert_eq!(clock["running"], "White");
+        assert!(clock["white"].as_u64().unwrap() <= 300_000);
+        assert_eq!(clock["black"], 300_000);
+        let moved = server.handle("POST", "/games/1/moves", r#"{"move": "d4"}"#);
+        let state = body(&moved);
+        assert_eq!(state["clock"]["running"], "Black");
+        assert!(state["clock"]["white"].as_u64().unwrap() > 300_000);
+        assert_eq!(
+            state["positions"][1],
+            "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq d3 0 1"
+        );
+        assert_eq!(state["positions"].as_array().unwrap().len(), 2);
+        assert_eq!(state["evaluations"].as_array().unwrap().len(), 2);
+        assert!(state["evaluations"][1]["cp"].is_i64());
+        assert_eq!(
+            body(&server.handle("POST", "/games", ""))["clock"],
+            Value::Null
+        );
+        let invalid = server.handle("POST", "/games", r#"{"time_control": "fast"}"#);
+        assert_eq!(invalid.status, 400);
+    }
+
+    #[test]
+    fn test_evaluations() {
+        let mut server = server();
+        let fen = r#"{"fen": "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"}"#;
+        let created = body(&server.handle("POST", "/games", fen));
+        assert_eq!(created["evaluations"], json!([{ "mate": 1 }]));
+        let mated = body(&server.handle("POST", "/games/1/moves", r#"{"move": "Ra8"}"#));
+        assert_eq!(mated["evaluations"], json!([{ "mate": 1 }, null]));
+
+        // Scores are from White's side, whoever is to move.
+        let fen = r#"{"fen": "r5k1/8/8/8/8/8/5PPP/6K1 b - - 0 1"}"#;
+        let created = body(&server.handle("POST", "/games", fen));
+        assert_eq!(created["evaluations"], json!([{ "mate": -1 }]));
+    }
+    #[test]
+    fn test_index() {
+        let index = server().handle("GET", "/", "");
+        assert_eq!(index.status, 200);
+        assert!(index.content_type.starts_with("text/html"));
+        assert!(index.body.contains("/games"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut server = server();
+        assert_eq!(server.handle("GET", "/games/1", "").status, 404);
+        assert_eq!(server.handle("GET", "/nothing", "").status, 404);
+        assert_eq!(server.handle("POST", "/games", "{").status, 400);
+        assert_eq!(
+            server.handle("POST", "/games", r#"{"fen": "x"}"#).status,
+            400
+        );
+        server.handle("POST", "/games", "");
+        assert_eq!(server.handle("POST", "/games/1/undo", "").status, 409);
+        assert_eq!(server.handle("POST", "/games/1/moves", "{}").status, 400);
+        assert_eq!(server.handle("DELETE", "/games/1", "").status, 404);
+    }
+
+    /// Sends a request to the server at `addr` and returns the status and body.
+    fn request(addr: &str, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            addr,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, body.to_string())
+    }
+
+    #[test]
+    fn test_localhost() {
+        let http = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let addr = http.server_addr().to_ip().unwrap().to_string();
+        thread::spawn(move || run(http, server()));
+
+        let (status, created) = request(&addr, "POST", "/games", "");
+        assert_eq!(status, 201);
+        assert!(
+            created.contains(r#""fen":"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1""#)
+        );
+        let (status, moved) = request(&addr, "POST", "/games/1/moves", r#"{"move":"Nf3"}"#);
+        assert_eq!(status, 200);
+        assert!(moved.contains(r#""history":["Nf3"]"#));
+        let (status, _) = request(&addr, "GET", "/games/9", "");
+        assert_eq!(status, 404);
+
+        let too_long = "x".repeat(MAX_BODY as usize + 1);
+        let (status, _) = request(&addr, "POST", "/games", &too_long);
+        assert_eq!(status, 413);
+
+        // Hang up without reading the answer, the next client is still served.
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        write!(stream, "GET /games/1 HTTP/1.1\r\nHost: {}\r\n\r\n", addr).unwrap();
+        drop(stream);
+        let (status, _) = request(&addr, "GET", "/games/1", "");
+        assert_eq!(status, 200);
+    }
+}
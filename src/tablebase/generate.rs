@@ -0,0 +1,481 @@
+//! Retrograde analysis, working backwards from every checkmate.
+//!
+//! Positions are indexed by the squares of their pieces, in the slot order of the Material,
+//! and by the team to move. Captures and promotions leave the table, their outcome is looked
+//! up in the already generated tables of the resulting material.
+use super::{index_of, Material, Table, DRAW};
+use crate::{Piece, Team};
+use std::collections::HashMap;
+
+const ILLEGAL: u8 = 255;
+/// Neither a Conversion nor a mate, yet.
+const NONE: u8 = 0;
+/// Encodes a draw among the conversions, as 0 already means there are none.
+const CONVERSION_DRAW: u8 = 254;
+
+const ROOK_DIRECTIONS: [(isize, isize); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(isize, isize); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const KNIGHT_JUMPS: [(isize, isize); 8] = [
+    (2, 1),
+    (2, -1),
+    (-2, 1),
+    (-2, -1),
+    (1, 2),
+    (1, -2),
+    (-1, 2),
+    (-1, -2),
+];
+const KING_STEPS: [(isize, isize); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+fn offset(sq: u8, (rank, file): (isize, isize)) -> Option<u8> {
+    let rank = (sq / 8) as isize + rank;
+    let file = (sq % 8) as isize + file;
+    match (0..8).contains(&rank) && (0..8).contains(&file) {
+        true => Some((rank * 8 + file) as u8),
+        false => None,
+    }
+}
+
+/// Squares a piece attacks, or for Pawns, captures on.
+fn attacks(piece: Piece, team: Team, sq: u8, occupied: u64) -> u64 {
+    let mut res = 0;
+    let mut slide = |directions: &[(isize, isize)]| {
+        for direction in directions {
+            let mut cur = sq;
+            while let Some(next) = offset(cur, *direction) {
+                res |= 1 << next;
+                if occupied & 1 << next != 0 {
+                    break;
+                }
+                cur = next;
+            }
+        }
+    };
+    match piece {
+        Piece::Rook => slide(&ROOK_DIRECTIONS),
+        Piece::Bishop => slide(&BISHOP_DIRECTIONS),
+        Piece::Queen => {
+            slide(&ROOK_DIRECTIONS);
+            slide(&BISHOP_DIRECTIONS);
+        }
+        Piece::Knight | Piece::King => {
+            let steps = match piece {
+                Piece::Knight => &KNIGHT_JUMPS,
+                _ => &KING_STEPS,
+            };
+            for step in steps {
+                if let Some(next) = offset(sq, *step) {
+                    res |= 1 << next;
+                }
+            }
+        }
+        Piece::Pawn => {
+            let forward = forward(team);
+            for file in &[-1, 1] {
+                if let Some(next) = offset(sq, (forward, *file)) {
+                    res |= 1 << next;
+                }
+            }
+        }
+    }
+    res
+}
+
+fn forward(team: Team) -> isize {
+    match team {
+        Team::White => 1,
+        Team::Black => -1,
+    }
+}
+
+fn last_rank(team: Team) -> u8 {
+    match team {
+        Team::White => 7,
+        Team::Black => 0,
+    }
+}
+
+struct Generator<'a> {
+    material: &'a Material,
+    tables: &'a HashMap<String, Table>,
+    values: Vec<u8>,
+    /// In-table moves that have not been shown to lose yet.
+    counters: Vec<u8>,
+    /// Best outcome among the captures and promotions.
+    conversions: Vec<u8>,
+}
+
+impl<'a> Generator<'a> {
+    fn decode(&self, idx: usize) -> ([u8; 4], Team) {
+        let mut squares = [0u8; 4];
+        let mut rest = idx;
+        for sq in squares.iter_mut().take(self.material.len()) {
+            *sq = (rest % 64) as u8;
+            rest /= 64;
+        }
+        let team = match rest {
+            0 => Team::White,
+            _ => Team::Black,
+        };
+        (squares, team)
+    }
+
+    fn encode(&self, squares: &[u8; 4], team: Team) -> usize {
+        let mut idx = match team {
+            Team::White => 0,
+            Team::Black => 1,
+        };
+        for sq in squares[..self.material.len()].iter().rev() {
+            idx = idx * 64 + *sq as usize;
+        }
+        idx
+    }
+
+    fn occupied(&self, squares: &[u8; 4]) -> u64 {
+        squares[..self.material.len()]
+            .iter()
+            .fold(0, |acc, sq| acc | 1 << sq)
+    }
+
+    fn attacked(&self, squares: &[u8; 4], target: u8, by: Team, skip: Option<usize>) -> bool {
+        let occupied = self.occupied(squares);
+        self.material
+            .pieces
+            .iter()
+            .enumerate()
+            .filter(|(slot, (team, _))| *team == by && Some(*slot) != skip)
+            .any(|(slot, (team, piece))| {
+                attacks(*piece, *team, squares[slot], occupied) & 1 << target != 0
+            })
+    }
+
+    fn king(&self, squares: &[u8; 4], team: Team) -> u8 {
+        let slot = self
+            .material
+            .pieces
+            .iter()
+            .position(|(t, p)| *t == team && *p == Piece::King)
+            .unwrap();
+        squares[slot]
+    }
+
+    fn legal(&self, squares: &[u8; 4], team: Team) -> bool {
+        let n = self.material.len();
+        if self.occupied(squares).count_ones() as usize != n {
+            return false;
+        }
+        let pawn_on_edge = self
+            .material
+            .pieces
+            .iter()
+            .enumerate()
+            .any(|(slot, (_, p))| *p == Piece::Pawn && (squares[slot] < 8 || squares[slot] >= 56));
+        if pawn_on_edge {
+            return false;
+        }
+        // The team that just moved cannot be left in check.
+        !self.attacked(squares, self.king(squares, team.not()), team, None)
+    }
+
+    /// Looks up the outcome of a capture or promotion, from the perspective of the mover.
+    fn conversion(&self, pieces: Vec<(Team, Piece, u8)>, to_move: Team) -> u8 {
+        let (key, idx) = index_of(&pieces, to_move);
+        let value = match self.tables.get(&key) {
+            Some(table) => table.values[idx],
+            None => panic!("{} has to be generated before {}", key, self.material),
+        };
+        match value {
+            DRAW => CONVERSION_DRAW,
+            // A loss for the opponent is a win for the mover, one ply later, and vice versa.
+            v => v + 1,
+        }
+    }
+
+    fn better_conversion(current: u8, other: u8) -> u8 {
+        let rank = |v: u8| -> (u8, isize) {
+            match v {
+                NONE => (0, 0),
+                CONVERSION_DRAW => (2, 0),
+                v if v % 2 == 0 => (3, -(v as isize)),
+                v => (1, v as isize),
+            }
+        };
+        match rank(other) > rank(current) {
+            true => other,
+            false => current,
+        }
+    }
+
+    /// Counts the in-table moves and evaluates the conversions of a position.
+    fn init(&mut self, idx: usize) {
+        let (squares, team) = self.decode(idx);
+        if !self.legal(&squares, team) {
+            self.values[idx] = ILLEGAL;
+            return;
+        }
+        let occupied = self.occupied(&squares);
+        let king = self.king(&squares, team);
+        let mut counter: u8 = 0;
+        let mut conversion = NONE;
+
+        for (slot, (t, piece)) in self.material.pieces.iter().enumerate() {
+            if *t != team {
+                continue;
+            }
+            let from = squares[slot];
+            let mut targets: Vec<u8> = Vec::new();
+            if *piece == Piece::Pawn {
+                let captures = attacks(Piece::Pawn, team, from, occupied);
+                for target in 0..64u8 {
+                    if captures & 1 << target != 0 && occupied & 1 << target != 0 {
+                        targets.push(target);
+                    }
+                }
+                if let Some(step) = offset(from, (forward(team), 0)) {
+                    if occupied & 1 << step == 0 {
+                        targets.push(step);
+                        let start = match team {
+                            Team::White => 1,
+                            Team::Black => 6,
+                        };
+                        if from / 8 == start {
+                            let double = offset(step, (forward(team), 0)).unwrap();
+                            if occupied & 1 << double == 0 {
+                                targets.push(double);
+                            }
+                        }
+                    }
+                }
+            } else {
+                let reach = attacks(*piece, team, from, occupied);
+                for target in 0..64u8 {
+                    if reach & 1 << target != 0 {
+                        targets.push(target);
+                    }
+                }
+            }
+
+            for target in targets {
+                let captured = (0..self.material.len()).find(|s| squares[*s] == target);
+                if let Some(captured) = captured {
+                    let (captured_team, captured_piece) = self.material.pieces[captured];
+                    if captured_team == team || captured_piece == Piece::King {
+                        continue;
+                    }
+                }
+                let mut moved = squares;
+                moved[slot] = target;
+                // Own King safety, ignoring a captured attacker.
+                let own_king = match *piece {
+                    Piece::King => target,
+                    _ => king,
+                };
+                let mut check_squares = moved;
+                if let Some(captured) = captured {
+                    // Park the captured piece under the capturer, skipped as an attacker.
+                    check_squares[captured] = target;
+                }
+                if self.attacked(&check_squares, own_king, team.not(), captured) {
+                    continue;
+                }
+
+                let promotes = *piece == Piece::Pawn && target / 8 == last_rank(team);
+                if captured.is_none() && !promotes {
+                    counter += 1;
+                    continue;
+                }
+
+                let pieces: Vec<(Team, Piece, u8)> = self
+                    .material
+                    .pieces
+                    .iter()
+                    .enumerate()
+                    .filter(|(s, _)| Some(*s) != captured)
+                    .map(|(s, (t, p))| (*t, *p, moved[s]))
+                    .collect();
+                let promotions = match promotes {
+                    true => vec![Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight],
+                    false => vec![*piece],
+                };
+                for promotion in promotions {
+                    let mut pieces = pieces.clone();
+                    for p in pieces.iter_mut() {
+                        if p.2 == target {
+                            p.1 = promotion;
+                        }
+                    }
+                    let value = self.conversion(pieces, team.not());
+                    conversion = Generator::better_conversion(conversion, value);
+                }
+            }
+        }
+
+        self.counters[idx] = counter;
+        self.conversions[idx] = conversion;
+    }
+
+    /// Positions, with the other team to move, from which a quiet move leads to idx.
+    fn unmoves(&self, idx: usize) -> Vec<usize> {
+        let (squares, team) = self.decode(idx);
+        let mover = team.not();
+        let occupied = self.occupied(&squares);
+        let mut res = Vec::new();
+
+        for (slot, (t, piece)) in self.material.pieces.iter().enumerate() {
+            if *t != mover {
+                continue;
+            }
+            let to = squares[slot];
+            let mut origins: Vec<u8> = Vec::new();
+            if *piece == Piece::Pawn {
+                let back = -forward(mover);
+                if let Some(step) = offset(to, (back, 0)) {
+                    if occupied & 1 << step == 0 {
+                        origins.push(step);
+                        let double_rank = match mover {
+                            Team::White => 3,
+                            Team::Black => 4,
+                        };
+                        if to / 8 == double_rank {
+                            let start = offset(step, (back, 0)).unwrap();
+                            if occupied & 1 << start == 0 {
+                                origins.push(start);
+                            }
+                        }
+                    }
+                }
+            } else {
+                let reach = attacks(*piece, mover, to, occupied);
+                for origin in 0..64u8 {
+                    if reach & 1 << origin != 0 && occupied & 1 << origin == 0 {
+                        origins.push(origin);
+                    }
+                }
+            }
+            for origin in origins {
+                let mut pred = squares;
+                pred[slot] = origin;
+                let pred = self.encode(&pred, mover);
+                if self.values[pred] != ILLEGAL {
+                    res.push(pred);
+                }
+            }
+        }
+        res
+    }
+
+    fn run(mut self) -> Vec<u8> {
+        let size = self.values.len();
+        let mut buckets: Vec<Vec<u32>> = vec![Vec::new(); ILLEGAL as usize];
+
+        for idx in 0..size {
+            self.init(idx);
+            if self.values[idx] == ILLEGAL {
+                continue;
+            }
+            let conversion = self.conversions[idx];
+            if self.counters[idx] == 0 && conversion == NONE {
+                let (squares, team) = self.decode(idx);
+                if self.attacked(&squares, self.king(&squares, team), team.not(), None) {
+                    // Checkmated.
+                    buckets[0].push(idx as u32);
+                }
+            } else if conversion != NONE && conversion != CONVERSION_DRAW {
+                // Wins are scheduled right away, losses only once every move loses.
+                if conversion.is_multiple_of(2) || self.counters[idx] == 0 {
+                    buckets[conversion as usize - 1].push(idx as u32);
+                }
+            }
+        }
+
+        for dtm in 0..buckets.len() - 1 {
+            let bucket = std::mem::take(&mut buckets[dtm]);
+            for idx in bucket {
+                let idx = idx as usize;
+                if self.values[idx] != DRAW {
+                    continue;
+                }
+                self.values[idx] = dtm as u8 + 1;
+                for pred in self.unmoves(idx) {
+                    if self.values[pred] != DRAW {
+                        continue;
+                    }
+                    if dtm % 2 == 0 {
+                        // Moving into a lost position wins.
+                        buckets[dtm + 1].push(pred as u32);
+                    } else {
+                        self.counters[pred] -= 1;
+                        if self.counters[pred] != 0 {
+                            continue;
+                        }
+                        // Every quiet move loses, so it is lost unless a conversion helps.
+                        match self.conversions[pred] {
+                            NONE => buckets[dtm + 1].push(pred as u32),
+                            c if c % 2 == 1 => {
+                                buckets[usize::max(dtm + 1, c as usize - 1)].push(pred as u32)
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+            }
+        }
+
+        // Illegal positions are never probed, repeating the previous value makes longer runs.
+        let mut previous = DRAW;
+        for value in self.values.iter_mut() {
+            match *value {
+                ILLEGAL => *value = previous,
+                v => previous = v,
+            }
+        }
+        self.values
+    }
+}
+
+/// Generates the table of a Material, every table it converts into must already exist.
+pub fn generate(material: &Material, tables: &HashMap<String, Table>) -> Table {
+    let size = material.size();
+    let generator = Generator {
+        material,
+        tables,
+        values: vec![DRAW; size],
+        counters: vec![0; size],
+        conversions: vec![NONE; size],
+    };
+    Table {
+        material: material.clone(),
+        values: generator.run(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_knight_attacks() {
+        // Knight on a1 reaches b3 and c2.
+        let reach = attacks(Piece::Knight, Team::White, 0, 0);
+        assert_eq!(reach, 1 << 17 | 1 << 10);
+    }
+    #[test]
+    fn test_rook_attacks_blocked() {
+        // Rook on a1 blocked by a piece on a3.
+        let reach = attacks(Piece::Rook, Team::White, 0, 1 << 16);
+        assert_eq!(reach & 0x0101_0101_0101_0101, 1 << 8 | 1 << 16);
+    }
+    #[test]
+    fn test_pawn_attacks() {
+        // Black Pawn on a7 captures on b6.
+        assert_eq!(attacks(Piece::Pawn, Team::Black, 48, 0), 1 << 41);
+    }
+}
@@ -0,0 +1,548 @@
+//! Endgame tablebases with the distance to mate of every position, for up to four pieces.
+//!
+//! Tables are generated locally by retrograde analysis and stored one material per file,
+//! as run-length encoded bytes. A value of 0 is a draw, any other value is the distance to
+//! mate in plies plus one, odd values being lost and even values won for the team to move.
+use crate::board::history::Move;
+use crate::{Board, Piece, Sq, Team, SIZE};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::str::FromStr;
+
+pub mod generate;
+#[cfg(feature = "syzygy")]
+pub mod syzygy;
+
+const MAGIC: &[u8; 4] = b"DTM1";
+const EXTENSION: &str = "dtm";
+const MAX_PIECES: usize = 4;
+const DRAW: u8 = 0;
+
+/// Where generated tables go, unless told otherwise.
+pub const DIRECTORY: &str = "./data/tablebases";
+
+/// The value of a position for the team to move, in plies until mate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Win(u8),
+    Draw,
+    Loss(u8),
+}
+
+impl From<u8> for Outcome {
+    fn from(value: u8) -> Self {
+        match value {
+            DRAW => Outcome::Draw,
+            v if v % 2 == 0 => Outcome::Win(v - 1),
+            v => Outcome::Loss(v - 1),
+        }
+    }
+}
+
+fn order(piece: Piece) -> u8 {
+    match piece {
+        Piece::King => 0,
+        Piece::Queen => 1,
+        Piece::Rook => 2,
+        Piece::Bishop => 3,
+        Piece::Knight => 4,
+        Piece::Pawn => 5,
+    }
+}
+
+/// Which pieces are on the board, White's first. The stronger side always plays White.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pieces: Vec<(Team, Piece)>,
+}
+
+impl Material {
+    fn side(&self, team: Team) -> String {
+        self.pieces
+            .iter()
+            .filter(|(t, _)| *t == team)
+            .map(|(_, p)| <&str>::from(*p))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    /// Number of entries in the table, for both teams to move.
+    pub fn size(&self) -> usize {
+        2 * 64usize.pow(self.len() as u32)
+    }
+
+    /// Every material a capture or a promotion leads to.
+    fn conversions(&self) -> Vec<String> {
+        let mut res = Vec::new();
+        let others = |skip: usize| -> Vec<(Team, Piece, u8)> {
+            self.pieces
+                .iter()
+                .enumerate()
+                .filter(|(slot, _)| *slot != skip)
+                .map(|(_, (t, p))| (*t, *p, 0))
+                .collect()
+        };
+        for (slot, (team, piece)) in self.pieces.iter().enumerate() {
+            if *piece != Piece::King {
+                res.push(index_of(&others(slot), Team::White).0);
+            }
+            if *piece != Piece::Pawn {
+                continue;
+            }
+            for promotion in &[Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+                let mut promoted: Vec<(Team, Piece, u8)> =
+                    self.pieces.iter().map(|(t, p)| (*t, *p, 0)).collect();
+                promoted[slot].1 = *promotion;
+                res.push(index_of(&promoted, Team::White).0);
+                // Promoting with a capture.
+                for (captured, (t, p)) in self.pieces.iter().enumerate() {
+                    if t != team && *p != Piece::King {
+                        let mut pieces = promoted.clone();
+                        pieces.remove(captured);
+                        res.push(index_of(&pieces, Team::White).0);
+                    }
+                }
+            }
+        }
+        res.sort();
+        res.dedup();
+        res
+    }
+}
+
+impl fmt::Display for Material {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.side(Team::White), self.side(Team::Black))
+    }
+}
+
+impl FromStr for Material {
+    type Err = String;
+
+    /// Reads materials like KQK, KQvKR or KvKP.
+    fn from_str(input: &str) -> std::result::Result<Material, Self::Err> {
+        let err = || {
+            format!(
+                "invalid material '{}', expected something like KQK or KRvKP",
+                input
+            )
+        };
+        let input = input.to_uppercase().replace('V', "");
+        let second_king = match input.starts_with('K') {
+            true => input[1..].find('K').ok_or_else(err)? + 1,
+            false => return Err(err()),
+        };
+        let mut pieces = Vec::new();
+        for (idx, c) in input.chars().enumerate() {
+            let team = match idx < second_king {
+                true => Team::White,
+                false => Team::Black,
+            };
+            match Piece::from_char(c) {
+                Some(Piece::King) if idx != 0 && idx != second_king => return Err(err()),
+                Some(piece) => pieces.push((team, piece, 0)),
+                None => return Err(err()),
+            }
+        }
+        if pieces.len() > MAX_PIECES {
+            return Err(format!(
+                "{} has {} pieces, tablebases go up to {}",
+                input,
+                pieces.len(),
+                MAX_PIECES
+            ));
+        }
+        Ok(canonical(&pieces, Team::White).0)
+    }
+}
+
+/// Flips the colors when Black is the stronger side, and sorts the pieces into slot order.
+fn canonical(pieces: &[(Team, Piece, u8)], to_move: Team) -> (Material, Vec<u8>, Team) {
+    let strength = |team: Team| -> (usize, Vec<u8>) {
+        let side: Vec<Piece> = pieces
+            .iter()
+            .filter(|(t, _, _)| *t == team)
+            .map(|(_, p, _)| *p)
+            .collect();
+        let mut orders: Vec<u8> = side.iter().map(|p| order(*p)).collect();
+        orders.sort();
+        // Fewer points for a lower order, so a Queen outranks a Rook on ties in value.
+        let value = side.iter().map(|p| p.value()).sum();
+        (value, orders.iter().map(|o| u8::MAX - o).collect())
+    };
+    let flip = strength(Team::Black) > strength(Team::White);
+
+    let mut sorted: Vec<(Team, Piece, u8)> = pieces
+        .iter()
+        .map(|(t, p, sq)| match flip {
+            true => (t.not(), *p, sq ^ 56),
+            false => (*t, *p, *sq),
+        })
+        .collect();
+    sorted.sort_by_key(|(t, p, sq)| (*t == Team::Black, order(*p), *sq));
+
+    let material = Material {
+        pieces: sorted.iter().map(|(t, p, _)| (*t, *p)).collect(),
+    };
+    let squares = sorted.iter().map(|(_, _, sq)| *sq).collect();
+    let to_move = match flip {
+        true => to_move.not(),
+        false => to_move,
+    };
+    (material, squares, to_move)
+}
+
+/// The table a position belongs in, and its index there.
+fn index_of(pieces: &[(Team, Piece, u8)], to_move: Team) -> (String, usize) {
+    let (material, squares, to_move) = canonical(pieces, to_move);
+    let mut idx = match to_move {
+        Team::White => 0,
+        Team::Black => 1,
+    };
+    for sq in squares.iter().rev() {
+        idx = idx * 64 + *sq as usize;
+    }
+    (material.to_string(), idx)
+}
+
+/// Control bytes below this are followed by that many literal values plus one, the others
+/// by a single value repeated MIN_RUN times or more.
+const RUN: u8 = 128;
+const MIN_RUN: usize = 3;
+const MAX_RUN: usize = MIN_RUN + 127;
+
+fn flush(data: &mut Vec<u8>, literals: &mut Vec<u8>) {
+    if !literals.is_empty() {
+        data.push(literals.len() as u8 - 1);
+        data.append(literals);
+    }
+}
+
+/// The distance to mate of every position of one material.
+pub struct Table {
+    material: Material,
+    values: Vec<u8>,
+}
+
+impl Table {
+    /// The longest forced mate in the table, in plies.
+    pub fn longest_mate(&self) -> u8 {
+        self.values
+            .iter()
+            .filter(|v| **v != DRAW && *v % 2 == 0)
+            .max()
+            .map_or(0, |v| v - 1)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Table::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+        if data.len() < MAGIC.len() + 1 || &data[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a tablebase file"));
+        }
+        let len = data[MAGIC.len()] as usize;
+        let start = MAGIC.len() + 1;
+        let name = data
+            .get(start..start + len)
+            .and_then(|name| std::str::from_utf8(name).ok())
+            .ok_or_else(|| invalid("tablebase material is cut off"))?;
+        let material: Material = name.parse().map_err(|e: String| invalid(&e))?;
+
+        let size = material.size();
+        let mut values = Vec::with_capacity(size);
+        let mut rest = data[start + len..].iter();
+        while let Some(control) = rest.next() {
+            let cut_off = || invalid("tablebase is cut off");
+            match *control {
+                c if c < RUN => {
+                    for _ in 0..=c {
+                        values.push(*rest.next().ok_or_else(cut_off)?);
+                    }
+                }
+                c => {
+                    let value = *rest.next().ok_or_else(cut_off)?;
+                    values.resize(values.len() + (c - RUN) as usize + MIN_RUN, value);
+                }
+            }
+            if values.len() > size {
+                return Err(invalid("tablebase has too many positions"));
+            }
+        }
+        if values.len() != size {
+            return Err(invalid("tablebase has too few positions"));
+        }
+        Ok(Table { material, values })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let name = self.material.to_string();
+        let mut data = MAGIC.to_vec();
+        data.push(name.len() as u8);
+        data.extend_from_slice(name.as_bytes());
+        let mut literals: Vec<u8> = Vec::new();
+        let mut idx = 0;
+        while idx < self.values.len() {
+            let value = self.values[idx];
+            let run = self.values[idx..]
+                .iter()
+                .take(MAX_RUN)
+                .take_while(|v| **v == value)
+                .count();
+            if run >= MIN_RUN {
+                flush(&mut data, &mut literals);
+                data.push(RUN + (run - MIN_RUN) as u8);
+                data.push(value);
+                idx += run;
+            } else {
+                literals.push(value);
+                if literals.len() == RUN as usize {
+                    flush(&mut data, &mut literals);
+                }
+                idx += 1;
+            }
+        }
+        flush(&mut data, &mut literals);
+        data
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+}
+
+/// The tables available to the computer, by material.
+#[derive(Default)]
+pub struct Tablebase {
+    tables: HashMap<String, Table>,
+}
+
+impl Tablebase {
+    /// Loads every table in a directory.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let mut tablebase = Tablebase::default();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some(EXTENSION) {
+                tablebase.insert(Table::open(&path)?);
+            }
+        }
+        Ok(tablebase)
+    }
+
+    pub fn insert(&mut self, table: Table) {
+        self.tables.insert(table.material.to_string(), table);
+    }
+
+    /// Generates the table of a material, and every table it converts into that is missing.
+    pub fn generate(&mut self, material: &Material) {
+        let name = material.to_string();
+        if self.tables.contains_key(&name) {
+            return;
+        }
+        for conversion in material.conversions() {
+            self.generate(&conversion.parse().unwrap());
+        }
+        let table = generate::generate(material, &self.tables);
+        self.tables.insert(name, table);
+    }
+
+    /// The value of the position for the team to move, if it is in a table.
+    pub fn probe(&self, board: &Board) -> Option<Outcome> {
+        // Tables know neither castling nor en passant.
+        if board.castling.fen() != "-" || board.en_passant_capturable() {
+            return None;
+        }
+        let mut pieces = Vec::new();
+        for rank in 0..SIZE {
+            for file in 0..SIZE {
+                if let Some(entity) = board.get(Sq::new(rank, file)) {
+                    pieces.push((entity.team, entity.kind, (rank * SIZE + file) as u8));
+                }
+            }
+        }
+        if pieces.len() == 2 {
+            return Some(Outcome::Draw);
+        }
+        if pieces.len() > MAX_PIECES {
+            return None;
+        }
+        let (name, idx) = index_of(&pieces, board.turn_order);
+        let table = self.tables.get(&name)?;
+        Some(table.values[idx].into())
+    }
+
+    /// The fastest win, or the slowest loss, if every reply is in a table.
+    pub fn best_move(&self, board: &Board) -> Option<Move> {
+        self.probe(board)?;
+        let mut best: Option<((u8, isize), Move)> = None;
+        for mov in board.legal_moves() {
+            let mut child = board.clone();
+            child.apply(&mov).ok()?;
+            let score = match self.probe(&child)? {
+                Outcome::Loss(dtm) => (2, -(dtm as isize)),
+                Outcome::Draw => (1, 0),
+                Outcome::Win(dtm) => (0, dtm as isize),
+            };
+            if best.as_ref().is_none_or(|(s, _)| score > *s) {
+                best = Some((score, mov));
+            }
+        }
+        best.map(|(_, mov)| mov)
+    }
+}
+
+/// Generates the tables for a material, e.g. "KQK", into a directory.
+pub fn build(material: String, dir: String) -> Result<()> {
+    let material: Material = material.parse().map_err(Error::other)?;
+    fs::create_dir_all(&dir)?;
+    let mut tablebase = Tablebase::default();
+    tablebase.generate(&material);
+    let mut names: Vec<&String> = tablebase.tables.keys().collect();
+    names.sort();
+    for name in names {
+        let table = &tablebase.tables[name];
+        let path = Path::new(&dir).join(format!("{}.{}", name, EXTENSION));
+        table.write(&path)?;
+        println!(
+            "{}: {} positions, longest mate in {} plies, written to {}",
+            name,
+            table.values.len(),
+            table.longest_mate(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::sync::OnceLock;
+
+    pub(in crate::tablebase) fn tablebase() -> &'static Tablebase {
+        static TABLEBASE: OnceLock<Tablebase> = OnceLock::new();
+        TABLEBASE.get_or_init(|| {
+            let mut tablebase = Tablebase::default();
+            tablebase.generate(&"KQK".parse().unwrap());
+            tablebase.generate(&"KRK".parse().unwrap());
+            tablebase
+        })
+    }
+
+    fn board(fen: &str) -> Board {
+        Board::try_from(fen.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_material() {
+        let material: Material = "KQK".parse().unwrap();
+        assert_eq!(material.to_string(), "KQvK");
+        let material: Material = "KvKR".parse().unwrap();
+        assert_eq!(material.to_string(), "KRvK");
+        let material: Material = "kqkr".parse().unwrap();
+        assert_eq!(material.to_string(), "KQvKR");
+        let material: Material = "KBNK".parse().unwrap();
+        assert_eq!(material.to_string(), "KBNvK");
+        assert!("QK".parse::<Material>().is_err());
+        assert!("KQRKR".parse::<Material>().is_err());
+        assert!("KXK".parse::<Material>().is_err());
+    }
+    #[test]
+    fn test_conversions() {
+        let material: Material = "KPK".parse().unwrap();
+        assert_eq!(
+            material.conversions(),
+            vec!["KBvK", "KNvK", "KQvK", "KRvK", "KvK"]
+        );
+        let material: Material = "KQKR".parse().unwrap();
+        assert_eq!(material.conversions(), vec!["KQvK", "KRvK"]);
+    }
+    #[test]
+    fn test_longest_mates() {
+        // Mate in 10 and in 16 moves, with White to move.
+        assert_eq!(tablebase().tables["KQvK"].longest_mate(), 19);
+        assert_eq!(tablebase().tables["KRvK"].longest_mate(), 31);
+        assert_eq!(tablebase().tables["KvK"].longest_mate(), 0);
+    }
+    #[test]
+    fn test_probe() {
+        let tablebase = tablebase();
+        let mate_in_one = board("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1");
+        assert_eq!(tablebase.probe(&mate_in_one), Some(Outcome::Win(1)));
+        assert_eq!(
+            tablebase.best_move(&mate_in_one).unwrap().label,
+            Some("Qb8#".to_string())
+        );
+        // The Queen hangs, which leaves bare Kings.
+        let hanging = board("8/8/8/8/8/8/1k6/Q2K4 b - - 0 1");
+        assert_eq!(tablebase.probe(&hanging), Some(Outcome::Draw));
+        let stalemate = board("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1");
+        assert_eq!(tablebase.probe(&stalemate), Some(Outcome::Draw));
+        assert_eq!(tablebase.probe(&Board::new()), None);
+    }
+    #[test]
+    fn test_perfect_play() {
+        // Both sides play the table's moves until Black is mated, exactly on time.
+        let tablebase = tablebase();
+        let mut board = board("8/8/8/3k4/8/8/8/R3K3 b - - 0 1");
+        let dtm = match tablebase.probe(&board) {
+            Some(Outcome::Loss(dtm)) => dtm,
+            outcome => panic!("expected a loss, got {:?}", outcome),
+        };
+        for _ in 0..dtm {
+            let mov = tablebase.best_move(&board).unwrap();
+            board.apply(&mov).unwrap();
+        }
+        assert!(board.check_mate(board.turn_order));
+    }
+    // Four pieces take about a minute each to generate in a release build, and far longer in a
+    // debug one, so these run with `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore = "slow, run with --release"]
+    fn test_kbnk() {
+        let mut tablebase = Tablebase::default();
+        tablebase.generate(&"KBNK".parse().unwrap());
+        // Mate in 33 moves, with White to move.
+        assert_eq!(tablebase.tables["KBNvK"].longest_mate(), 65);
+        let mate_in_one = board("7k/4N3/7K/8/5B2/8/8/8 w - - 0 1");
+        assert_eq!(tablebase.probe(&mate_in_one), Some(Outcome::Win(1)));
+    }
+    #[test]
+    #[ignore = "slow, run with --release"]
+    fn test_kqkr() {
+        let mut tablebase = Tablebase::default();
+        tablebase.generate(&"KQKR".parse().unwrap());
+        // Mate in 35 moves, with White to move.
+        assert_eq!(tablebase.tables["KQvKR"].longest_mate(), 69);
+        // The Rook hangs.
+        let hanging = board("r6k/8/8/8/Q7/8/8/4K3 w - - 0 1");
+        assert!(matches!(tablebase.probe(&hanging), Some(Outcome::Win(_))));
+        assert_eq!(
+            tablebase.best_move(&hanging).unwrap().label,
+            Some("Qxa8+".to_string())
+        );
+        // The Queen is pinned, Black takes it and wins.
+        let pinned = board("k3r3/8/8/4Q3/8/8/8/4K3 b - - 0 1");
+        assert!(matches!(tablebase.probe(&pinned), Some(Outcome::Win(_))));
+    }
+    #[test]
+    fn test_round_trip() {
+        let table = &tablebase().tables["KQvK"];
+        let data = table.to_bytes();
+        assert!(data.len() < table.values.len() * 2 / 3);
+        let read = Table::from_bytes(&data).unwrap();
+        assert_eq!(read.material, table.material);
+        assert!(read.values == table.values);
+        assert!(Table::from_bytes(&data[..data.len() - 2]).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! Probing Syzygy tablebases, e.g. the files from https://tablebase.lichess.ovh/tables/standard/.
+use crate::board::history::Move;
+use crate::{Board, Piece, Sq, SIZE};
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess, Role};
+pub use shakmaty_syzygy::Wdl;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// The WDL and DTZ tables found in a directory.
+pub struct Syzygy {
+    tables: shakmaty_syzygy::Tablebase<Chess>,
+}
+
+impl Syzygy {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let mut tables = shakmaty_syzygy::Tablebase::new();
+        if tables.add_directory(&dir)? == 0 {
+            let msg = format!("no Syzygy tables in {}", dir.as_ref().display());
+            return Err(Error::new(ErrorKind::NotFound, msg));
+        }
+        Ok(Syzygy { tables })
+    }
+
+    /// Positions with more pieces are not in any table.
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces()
+    }
+
+    fn position(&self, board: &Board) -> Option<Chess> {
+        let pieces = (0..SIZE)
+            .flat_map(|rank| (0..SIZE).map(move |file| Sq::new(rank, file)))
+            .filter(|sq| board.get(*sq).is_some())
+            .count();
+        if pieces > self.max_pieces() {
+            return None;
+        }
+        // Our Board remembers every double step, Syzygy only wants capturable ones.
+        let mut fields: Vec<String> = board.fen().split(' ').map(String::from).collect();
+        if !board.en_passant_capturable() {
+            fields[3] = "-".to_string();
+        }
+        let fen: Fen = fields.join(" ").parse().ok()?;
+        fen.into_position(CastlingMode::Standard).ok()
+    }
+
+    /// Win, draw or loss for the team to move, and the plies until the next capture, pawn move
+    /// or mate, negative when losing.
+    pub fn probe(&self, board: &Board) -> Option<(Wdl, i32)> {
+        let pos = self.position(board)?;
+        let wdl = self.tables.probe_wdl_after_zeroing(&pos).ok()?;
+        let dtz = self.tables.probe_dtz(&pos).ok()?.ignore_rounding();
+        Some((wdl, dtz.0))
+    }
+
+    /// The move that keeps the best result, converting it as fast as the 50 move rule asks.
+    pub fn best_move(&self, board: &Board) -> Option<Move> {
+        let pos = self.position(board)?;
+        let (best, _) = self.tables.best_move(&pos).ok()??;
+        let promotion = best.promotion().map(|role| match role {
+            Role::Knight => Piece::Knight,
+            Role::Bishop => Piece::Bishop,
+            Role::Rook => Piece::Rook,
+            _ => Piece::Queen,
+        });
+        let from = best.from()?;
+        let from = Sq::new(from.rank() as usize, from.file() as usize);
+        let to = best.to();
+        let to = Sq::new(to.rank() as usize, to.file() as usize);
+        board
+            .legal_moves()
+            .into_iter()
+            .find(|mov| mov.from == from && mov.to == to && mov.promotion == promotion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    /// The KQvK and KRvK tables.
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/syzygy");
+
+    fn board(fen: &str) -> Board {
+        Board::try_from(fen.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_open_empty() {
+        let empty = concat!(env!("CARGO_MANIFEST_DIR"), "/src/tablebase");
+        assert_eq!(
+            Syzygy::open(empty).err().map(|e| e.kind()),
+            Some(ErrorKind::NotFound)
+        );
+    }
+    #[test]
+    fn test_probe() {
+        let syzygy = Syzygy::open(FIXTURES).unwrap();
+        assert_eq!(syzygy.max_pieces(), 3);
+        let mate_in_one = board("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1");
+        assert_eq!(syzygy.probe(&mate_in_one), Some((Wdl::Win, 1)));
+        assert_eq!(
+            syzygy.best_move(&mate_in_one).unwrap().label,
+            Some("Qb8#".to_string())
+        );
+        let hanging = board("8/8/8/8/8/8/1k6/Q2K4 b - - 0 1");
+        assert_eq!(syzygy.probe(&hanging), Some((Wdl::Draw, 0)));
+        assert_eq!(
+            syzygy.best_move(&hanging).unwrap().label,
+            Some("Kxa1".to_string())
+        );
+        // Too many pieces for the fixtures.
+        assert_eq!(syzygy.probe(&Board::new()), None);
+    }
+    #[test]
+    fn test_perfect_play() {
+        let syzygy = Syzygy::open(FIXTURES).unwrap();
+        let mut board = board("8/8/8/3k4/8/8/8/R3K3 b - - 0 1");
+        let (wdl, dtz) = syzygy.probe(&board).unwrap();
+        assert_eq!(wdl, Wdl::Loss);
+        for _ in 0..-dtz {
+            let mov = syzygy.best_move(&board).unwrap();
+            board.apply(&mov).unwrap();
+        }
+        assert!(board.check_mate(board.turn_order));
+    }
+}
@@ -0,0 +1,406 @@
+use crate::board::state::{BoardState, GameState};
+use crate::computer::book::Book;
+use crate::computer::{Action, Playable};
+use crate::game_loop::opponent::{self, Opponent};
+use crate::game_loop::session::{Flow, Session};
+use crate::{Args, Board, Team};
+use rand::seq::SliceRandom;
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+pub mod stats;
+
+use stats::{Score, Sprt, Verdict};
+
+/// How many plies of book moves open each pair of games, when there is no EPD file.
+const BOOK_PLIES: usize = 8;
+/// Commands a player may send before it has to move.
+const MAX_COMMANDS: usize = 8;
+
+/// A game starts from `board`, reached from `start` by the moves in its history.
+#[derive(Clone)]
+struct Opening {
+    start: Board,
+    board: Board,
+}
+
+/// What the games of a match have in common.
+struct Match<'a> {
+    args: &'a Args,
+    /// The player scored for, who has White in the even games.
+    first: String,
+    second: String,
+    games: usize,
+    openings: Vec<Opening>,
+    sprt: Option<Sprt>,
+}
+
+/// Everything the games running in parallel report to.
+struct Tally {
+    score: Score,
+    played: usize,
+    pgn: File,
+}
+
+/// Plays `games` games between `--white` and `--black` as the first and second player, who
+/// change colors every game and play each opening once with either color. Prints the score,
+/// the Elo difference and, with `--sprt`, stops as soon as the test is decided.
+pub fn run(args: &Args, games: usize) -> io::Result<()> {
+    for name in [&args.white, &args.black] {
+        if opponent(name)? == Opponent::Player {
+            return Err(io::Error::other("a match is played between computers"));
+        }
+    }
+    let pairs = games.div_ceil(2);
+    let openings = match &args.openings {
+        Some(path) => epd(&fs::read_to_string(path)?)?,
+        None => {
+            let book = opponent::book(args)?;
+            (0..pairs).map(|_| book_opening(&book)).collect()
+        }
+    };
+    if openings.is_empty() {
+        return Err(io::Error::other("no openings to play"));
+    }
+    let sprt = match &args.sprt {
+        Some(sprt) => Some(Sprt::from_str(sprt).map_err(io::Error::other)?),
+        None => None,
+    };
+    let setup = Match {
+        args,
+        first: args.white.clone(),
+        second: args.black.clone(),
+        games,
+        openings,
+        sprt,
+    };
+    let tally = Mutex::new(Tally {
+        score: Score::default(),
+        played: 0,
+        pgn: File::create(&args.match_pgn)?,
+    });
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let concurrency = match args.concurrency {
+        Some(n) => n.max(1),
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..concurrency.min(games))
+            .map(|_| scope.spawn(|| setup.work(&next, &stop, &tally)))
+            .collect();
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().expect("match worker panicked"))
+    })?;
+
+    let tally = tally.into_inner().expect("match worker panicked");
+    setup.report(&tally.score);
+    println!("Games saved to {}", args.match_pgn);
+    Ok(())
+}
+
+impl Match<'_> {
+    /// Plays games until they are all taken or the SPRT is decided.
+    fn work(&self, next: &AtomicUsize, stop: &AtomicBool, tally: &Mutex<Tally>) -> io::Result<()> {
+        let mut first = opponent(&self.first)?.init(self.args)?;
+        let mut second = opponent(&self.second)?.init(self.args)?;
+        loop {
+            let game = next.fetch_add(1, Ordering::SeqCst);
+            if game >= self.games || stop.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            let opening = &self.openings[(game / 2) % self.openings.len()];
+            let first_is_white = game.is_multiple_of(2);
+            let (white, black, names) = match first_is_white {
+                true => (&mut first, &mut second, [&self.first, &self.second]),
+                false => (&mut second, &mut first, [&self.second, &self.first]),
+            };
+            let clock = self.args.time_control.clone();
+            let mut session = Session::new(opening.start.clone(), names[0], names[1], vec![], clock)
+                .continued(opening.board.clone());
+            play(white.as_mut(), black.as_mut(), &mut session)?;
+            let pgn = session.pgn_as("Match", &(game + 1).to_string())?;
+
+            let mut tally = tally.lock().expect("match worker panicked");
+            let first_team = match first_is_white {
+                true => Team::White,
+                false => Team::Black,
+            };
+            match session.board.state.winner() {
+                Some(team) if team == first_team => tally.score.wins += 1,
+                Some(_) => tally.score.losses += 1,
+                None => tally.score.draws += 1,
+            }
+            tally.played += 1;
+            writeln!(tally.pgn, "{}", pgn)?;
+            println!(
+                "Game {} of {} ({} vs {}): {}  {}",
+                tally.played, self.games, names[0], names[1], session.board.state, tally.score
+            );
+            if let Some(sprt) = &self.sprt {
+                if sprt.verdict(&tally.score) != Verdict::Continue {
+                    stop.store(true, Ordering::SeqCst);
+                }
+            }
+        }
+    }
+
+    fn report(&self, score: &Score) {
+        println!(
+            "Score of {} vs {}: {} ({} games)",
+            self.first,
+            self.second,
+            score,
+            score.games()
+        );
+        if score.games() == 0 {
+            return;
+        }
+        match score.elo() {
+            (elo, margin) if elo.is_finite() => {
+                // Adding zero turns an even score's -0.0 into 0.0.
+                println!("Elo difference: {:+.1} +/- {:.1}", elo + 0.0, margin)
+            }
+            (elo, _) => println!(
+                "Elo difference: {}inf, one side won every game",
+                match elo > 0.0 {
+                    true => "+",
+                    false => "-",
+                }
+            ),
+        }
+        if let Some(sprt) = &self.sprt {
+            let (lower, upper) = sprt.bounds();
+            let verdict = match sprt.verdict(score) {
+                Verdict::H0 => "H0 accepted",
+                Verdict::H1 => "H1 accepted",
+                Verdict::Continue => "inconclusive",
+            };
+            println!(
+                "SPRT [{}, {}]: LLR {:.2} ({:.2}, {:.2}), {}",
+                sprt.elo0,
+                sprt.elo1,
+                sprt.llr(score),
+                lower,
+                upper,
+                verdict
+            );
+        }
+    }
+}
+
+fn opponent(name: &str) -> io::Result<Opponent> {
+    Opponent::from_str(name).map_err(io::Error::other)
+}
+
+/// Plays the session to its end. A player that fails to move, moves illegally, leaves the game
+/// or sends more than `MAX_COMMANDS` commands in a row forfeits.
+fn play(
+    white: &mut dyn Playable,
+    black: &mut dyn Playable,
+    session: &mut Session,
+) -> io::Result<()> {
+    white.new_game(&session.context())?;
+    black.new_game(&session.context())?;
+    let mut commands = 0;
+    while !session.board.state.is_over() {
+        if let Some(clock) = session.clock.as_mut() {
+            clock.start();
+        }
+        let team = session.board.turn_order;
+        let action = match team {
+            Team::White => white.action(&session.context()),
+            Team::Black => black.action(&session.context()),
+        };
+        let played = match action {
+            Ok(Action::Move(mov)) => {
+                commands = 0;
+                session.play(&mov).is_ok()
+            }
+            // Draw offers, claims and resignations are carried out as at the prompt.
+            Ok(Action::Command(command)) => {
+                commands += 1;
+                commands <= MAX_COMMANDS
+                    && matches!(
+                        session.command(command),
+                        Ok(Flow::Redraw) | Ok(Flow::Show(_))
+                    )
+            }
+            Err(_) => false,
+        };
+        if !played {
+            session.board.state = BoardState::won(team.not(), GameState::Forfeit);
+        }
+    }
+    white.game_over(&session.context())?;
+    black.game_over(&session.context())
+}
+
+/// A few moves into the book, each picked as often as its weight suggests.
+fn book_opening(book: &Book) -> Opening {
+    let start = Board::new();
+    let mut board = start.clone();
+    let mut rng = rand::thread_rng();
+    for _ in 0..BOOK_PLIES {
+        let mut candidates = book.candidates(&board);
+        candidates.retain(|c| c.weight > 0);
+        let candidate = match candidates.choose_weighted(&mut rng, |c| c.weight) {
+            Ok(candidate) => candidate,
+            Err(_) => break,
+        };
+        if board.apply(&candidate.mov).is_err() {
+            break;
+        }
+    }
+    Opening { start, board }
+}
+
+/// The positions of an EPD file, one per line. Only the position is read, the operations after
+/// it are ignored.
+fn epd(data: &str) -> io::Result<Vec<Opening>> {
+    data.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+            let board: Board = format!("{} 0 1", fields.join(" ")).try_into()?;
+            Ok(Opening {
+                start: board.clone(),
+                board,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::history::Move;
+    use crate::computer::read_openings_file;
+    use crate::computer::Context;
+    use crate::computer::OPENINGS_FILE;
+    use crate::game_loop::clock::Clock;
+    use crate::input::command::Command;
+    use crate::{Piece, Sq};
+    use std::time::Duration;
+
+    #[test]
+    fn test_epd() {
+        let data = "\
+# Two openings
+rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - id \"e4\";
+
+rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq -
+";
+        let openings = epd(data).unwrap();
+        assert_eq!(openings.len(), 2);
+        assert_eq!(
+            openings[1].board.fen(),
+            "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert!(epd("not a position").is_err());
+    }
+
+    #[test]
+    fn test_book_opening() {
+        let book = Book::Openings(read_openings_file(OPENINGS_FILE).unwrap());
+        let opening = book_opening(&book);
+        assert_eq!(opening.start, Board::new());
+        let plies = opening.board.history.len(Team::White) + opening.board.history.len(Team::Black);
+        assert!(plies > 0 && plies <= BOOK_PLIES);
+    }
+
+    /// Gives up, or when it doesn't, plays a move that is not legal.
+    struct Quitter {
+        resigns: bool,
+    }
+
+    impl Playable for Quitter {
+        fn action(&mut self, _context: &Context) -> io::Result<Action> {
+            match self.resigns {
+                true => Err(io::Error::other("gives up")),
+                false => Ok(Action::Move(Move {
+                    piece: Piece::Pawn,
+                    from: Sq::notation("e2")?,
+                    to: Sq::notation("e5")?,
+                    promotion: None,
+                    label: None,
+                })),
+            }
+        }
+    }
+
+    /// Offers a draw before its first move and takes any draw it is offered, otherwise plays
+    /// the first legal move after `think`ing.
+    struct Drawish {
+        offered: bool,
+        think: Duration,
+    }
+
+    impl Playable for Drawish {
+        fn action(&mut self, context: &Context) -> io::Result<Action> {
+            if context.draw_offered || !self.offered {
+                self.offered = true;
+                return Ok(Action::Command(Command::Draw));
+            }
+            thread::sleep(self.think);
+            Ok(Action::Move(context.board.legal_moves().remove(0)))
+        }
+    }
+
+    #[test]
+    fn test_draw_offer() {
+        let mut white = Drawish {
+            offered: false,
+            think: Duration::ZERO,
+        };
+        let mut black = Drawish {
+            offered: true,
+            think: Duration::ZERO,
+        };
+        let mut session = Session::new(Board::new(), "a", "b", vec![], None);
+        play(&mut white, &mut black, &mut session).unwrap();
+        assert_eq!(session.board.state.winner(), None);
+        assert_eq!(session.board.history.len(Team::White), 1);
+        assert!(session.board.state.to_string().contains("agreement"));
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut white = Drawish {
+            offered: true,
+            think: Duration::from_millis(100),
+        };
+        let mut black = Drawish {
+            offered: true,
+            think: Duration::ZERO,
+        };
+        // A minute per 1000 games, 60ms.
+        let clock = Clock::from_str("0.001").unwrap();
+        let mut session = Session::new(Board::new(), "a", "b", vec![], Some(clock));
+        play(&mut white, &mut black, &mut session).unwrap();
+        assert_eq!(session.board.state.winner(), Some(Team::Black));
+        assert_eq!(session.board.history.len(Team::White), 1);
+    }
+
+    #[test]
+    fn test_forfeit() {
+        for resigns in [true, false] {
+            let mut white = Quitter { resigns };
+            let mut black = Quitter { resigns };
+            let mut session = Session::new(Board::new(), "a", "b", vec![], None);
+            play(&mut white, &mut black, &mut session).unwrap();
+            assert_eq!(
+                session.board.state.to_string(),
+                "Black wins by forfeit, 0-1"
+            );
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Wins, draws and losses of the first player of a match.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Score {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+/// Two sided 95% confidence.
+const Z_95: f64 = 1.959964;
+
+impl Score {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points per game, a win counting 1 and a draw half.
+    pub fn ratio(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    /// Elo difference the score suggests, and the half width of its 95% confidence interval.
+    /// Infinite when every game was won or lost.
+    pub fn elo(&self) -> (f64, f64) {
+        let n = self.games() as f64;
+        let score = self.ratio();
+        let variance = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / n;
+        let margin = Z_95 * (variance / n).sqrt();
+        let low = elo(score - margin);
+        let high = elo(score + margin);
+        (elo(score), (high - low) / 2.0)
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)
+    }
+}
+
+/// Elo difference for an expected score.
+fn elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Expected score for an Elo difference.
+fn expected(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Sequential probability ratio test of whether the first player is `elo1` rather than `elo0`
+/// Elo stronger, wrongly accepting either with at most `alpha` and `beta` probability.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+/// What the SPRT says so far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    /// The first player is no better than `elo0`.
+    H0,
+    /// The first player is at least `elo1` better.
+    H1,
+    Continue,
+}
+
+impl FromStr for Sprt {
+    type Err = String;
+
+    /// "elo0,elo1" tested at 5% error both ways, or "elo0,elo1,alpha,beta".
+    fn from_str(input: &str) -> Result<Sprt, Self::Err> {
+        let invalid = || format!("invalid SPRT '{}', expected elo0,elo1[,alpha,beta]", input);
+        let values = input
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| invalid())?;
+        let (elo0, elo1, alpha, beta) = match values.as_slice() {
+            [elo0, elo1] => (*elo0, *elo1, 0.05, 0.05),
+            [elo0, elo1, alpha, beta] => (*elo0, *elo1, *alpha, *beta),
+            _ => return Err(invalid()),
+        };
+        let probability = |p: f64| p > 0.0 && p < 0.5;
+        if elo0 >= elo1 || !probability(alpha) || !probability(beta) {
+            return Err(invalid());
+        }
+        Ok(Sprt {
+            elo0,
+            elo1,
+            alpha,
+            beta,
+        })
+    }
+}
+
+impl Sprt {
+    /// Bounds the log likelihood ratio is tested against.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// Log likelihood ratio of H1 against H0, by the normal approximation of the trinomial
+    /// model. Zero until there are wins or losses to tell the hypotheses apart.
+    pub fn llr(&self, score: &Score) -> f64 {
+        let n = score.games() as f64;
+        if score.wins + score.losses == 0 {
+            return 0.0;
+        }
+        let mean = score.ratio();
+        let wins = score.wins as f64 / n;
+        let draws = score.draws as f64 / n;
+        let variance = wins + draws / 4.0 - mean.powi(2);
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        let (s0, s1) = (expected(self.elo0), expected(self.elo1));
+        (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance / n)
+    }
+
+    pub fn verdict(&self, score: &Score) -> Verdict {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Verdict::H1
+        } else if llr <= lower {
+            Verdict::H0
+        } else {
+            Verdict::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(wins: usize, draws: usize, losses: usize) -> Score {
+        Score {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    #[test]
+    fn test_elo() {
+        let (diff, margin) = score(10, 10, 10).elo();
+        assert!(diff.abs() < 1e-9);
+        assert!(margin > 100.0 && margin < 150.0, "{}", margin);
+        // 75% is about 191 Elo.
+        let (diff, margin) = score(50, 50, 0).elo();
+        assert!((diff - 190.85).abs() < 0.1, "{}", diff);
+        assert!(margin < 50.0);
+        assert!(score(3, 0, 0).elo().0.is_infinite());
+        assert_eq!(score(3, 2, 1).to_string(), "+3 =2 -1");
+    }
+
+    #[test]
+    fn test_sprt() {
+        let sprt = Sprt::from_str("0,10").unwrap();
+        let (lower, upper) = sprt.bounds();
+        assert!((upper - 2.944).abs() < 0.001);
+        assert!((lower + 2.944).abs() < 0.001);
+        assert_eq!(sprt.verdict(&score(0, 5, 0)), Verdict::Continue);
+        assert_eq!(sprt.verdict(&score(600, 400, 300)), Verdict::H1);
+        assert_eq!(sprt.verdict(&score(300, 400, 600)), Verdict::H0);
+        assert_eq!(sprt.verdict(&score(5, 5, 4)), Verdict::Continue);
+        assert!(Sprt::from_str("10,0").is_err());
+        assert!(Sprt::from_str("0,10,0.5,0.05").is_err());
+        assert!(Sprt::from_str("strong").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use crate::display::{self, View};
+use crate::game_loop::screen::Screen;
+use crate::game_loop::session::Session;
+use crate::Board;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::cell::RefCell;
+use std::io::{self, Stdout, Write};
+use std::rc::Rc;
+
+mod player;
+
+use player::Picker;
+pub use player::TuiPlayer;
+
+/// Row of the terminal the top rank is drawn on.
+const BOARD_TOP: u16 = 1;
+/// Column the side pane with the clock and the engine starts at, right of the move list.
+const PANE: u16 = 56;
+/// How many lines of messages are kept below the board.
+const MESSAGES: usize = 14;
+const TITLE: &str = "Arrows or mouse to move, Enter or Space to pick up and drop, \
+                     : to type, u/r undo/redo, f flip, ? help, q quit";
+
+/// The whole terminal, in raw mode on the alternate screen, until dropped.
+pub struct Tui {
+    out: Stdout,
+    pub picker: Picker,
+    /// The position and view of the last presented session.
+    board: Board,
+    view: View,
+    /// Clock and engine lines next to the board.
+    pane: Vec<String>,
+    messages: Vec<String>,
+}
+
+impl Tui {
+    pub fn new() -> io::Result<Self> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, EnableMouseCapture, Hide)?;
+        Ok(Tui {
+            out,
+            picker: Picker::default(),
+            board: Board::new(),
+            view: View::default(),
+            pane: Vec::new(),
+            messages: Vec::new(),
+        })
+    }
+
+    /// Takes over what the session shows: the board, the clock and what the engine did.
+    fn update(&mut self, session: &Session) {
+        self.board = session.board.clone();
+        self.view = session.view.clone();
+        self.picker.selected = None;
+        self.pane.clear();
+        if let Some(clock) = &session.clock {
+            self.pane.push(clock.to_string());
+        }
+        let team = session.board.turn_order;
+        let last = session.board.history.last(team.not());
+        if !session.board.state.is_over() && !session.is_human(team) {
+            self.pane.push("Engine is thinking...".to_string());
+        } else if let Some(label) = last.and_then(|mov| mov.label.as_ref()) {
+            if !session.is_human(team.not()) {
+                self.pane.push(format!("Engine played {}", label));
+            }
+        }
+    }
+
+    fn perspective(&self) -> crate::Team {
+        self.view.perspective
+    }
+
+    fn message(&mut self, text: &str) {
+        self.messages.extend(text.lines().map(String::from));
+        let excess = self.messages.len().saturating_sub(MESSAGES);
+        self.messages.drain(..excess);
+    }
+
+    /// Draws the board with the cursor and the picked up piece, the side pane, `prompt` and the
+    /// latest messages.
+    fn draw(&mut self, prompt: &str) -> io::Result<()> {
+        let mut view = self.view.clone();
+        view.selected = self.picker.selected.or(view.selected);
+        view.cursor = Some(self.picker.cursor);
+        let mut lines = vec![TITLE.to_string()];
+        lines.extend(
+            display::render(&self.board, &view)
+                .lines()
+                .map(String::from),
+        );
+        lines.push(String::new());
+        lines.push(prompt.to_string());
+        lines.push(String::new());
+        lines.extend(self.messages.iter().cloned());
+
+        for (row, line) in lines.iter().enumerate() {
+            let row = row as u16;
+            queue!(
+                self.out,
+                MoveTo(0, row),
+                Print(line),
+                Clear(ClearType::UntilNewLine)
+            )?;
+            if let Some(pane) = row
+                .checked_sub(BOARD_TOP)
+                .and_then(|idx| self.pane.get(idx as usize))
+                .cloned()
+            {
+                queue!(self.out, MoveTo(PANE, row), Print(pane))?;
+            }
+        }
+        queue!(self.out, Clear(ClearType::FromCursorDown))?;
+        self.out.flush()
+    }
+
+    /// Reads a line typed after `prompt`. Escape or Ctrl-C gives up and returns nothing.
+    fn read_line(&mut self, prompt: &str) -> io::Result<String> {
+        let mut line = String::new();
+        loop {
+            self.draw(&format!("{}{}_", prompt, line))?;
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Enter => return Ok(line.trim().to_string()),
+                KeyCode::Esc => return Ok(String::new()),
+                KeyCode::Char('c') if player::ctrl(&key) => return Ok(String::new()),
+                KeyCode::Backspace => {
+                    line.pop();
+                }
+                KeyCode::Char(ch) => line.push(ch),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, Show, DisableMouseCapture, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The game loop's view of the full screen, which it shares with the human players.
+pub struct TuiScreen(pub Rc<RefCell<Tui>>);
+
+impl Screen for TuiScreen {
+    fn present(&mut self, session: &Session) -> io::Result<()> {
+        let mut tui = self.0.borrow_mut();
+        tui.update(session);
+        tui.draw("")
+    }
+
+    fn show(&mut self, text: &str) -> io::Result<()> {
+        let mut tui = self.0.borrow_mut();
+        tui.message(text);
+        tui.draw("")
+    }
+
+    fn error(&mut self, err: io::Error) -> io::Result<()> {
+        self.show(&format!("Error: {}", err))
+    }
+
+    fn result(&mut self, session: &Session) -> io::Result<()> {
+        self.show(&session.board.state.to_string())
+    }
+
+    fn ask(&mut self, question: &str) -> io::Result<String> {
+        let mut tui = self.0.borrow_mut();
+        tui.message(question);
+        tui.read_line("> ")
+    }
+}
@@ -0,0 +1,282 @@
+use super::{Tui, BOARD_TOP};
+use crate::board::history::Move;
+use crate::computer::{Action, Context, Playable};
+use crate::input::command::Command;
+use crate::{Board, Piece, Sq, Team, SIZE};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::event::{MouseButton, MouseEventKind};
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+/// The keyboard cursor, and the piece picked up to be dropped somewhere else.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Picker {
+    pub cursor: Sq,
+    pub selected: Option<Sq>,
+}
+
+impl Default for Picker {
+    fn default() -> Self {
+        Picker {
+            cursor: Sq::new(1, 4),
+            selected: None,
+        }
+    }
+}
+
+impl Picker {
+    /// Moves the cursor a square up or right as seen from `perspective`, stopping at the edge.
+    pub fn step(&mut self, up: isize, right: isize, perspective: Team) {
+        let (up, right) = match perspective {
+            Team::White => (up, right),
+            Team::Black => (-up, -right),
+        };
+        if let Some(sq) = self.cursor.mutate(up, right) {
+            self.cursor = sq;
+        }
+    }
+
+    /// Picks up a piece of the team to move at `sq`, puts the held one back when it is `sq`, or
+    /// drops it. A legal drop returns its move, or one per piece a Pawn can promote to. In
+    /// Chess960 the King castles by being dropped on its own Rook.
+    pub fn pick(&mut self, board: &Board, sq: Sq) -> Vec<Move> {
+        let own = board
+            .get(sq)
+            .is_some_and(|entity| entity.team == board.turn_order);
+        match self.selected {
+            Some(from) if from == sq => self.selected = None,
+            Some(from) => {
+                let moves: Vec<Move> = board
+                    .legal_moves()
+                    .into_iter()
+                    .filter(|mov| mov.from == from && mov.to == sq)
+                    .collect();
+                if !moves.is_empty() {
+                    self.selected = None;
+                    return moves;
+                }
+                if own {
+                    self.selected = Some(sq);
+                }
+            }
+            None if own => self.selected = Some(sq),
+            None => (),
+        }
+        Vec::new()
+    }
+}
+
+/// The square drawn at a terminal cell, if any. Ranks start below the title, files after the
+/// rank number and three columns wide.
+pub fn square_at(column: u16, row: u16, perspective: Team) -> Option<Sq> {
+    let x = row.checked_sub(BOARD_TOP)? as usize;
+    let y = (column.checked_sub(3)? / 3) as usize;
+    if x >= SIZE || y >= SIZE {
+        return None;
+    }
+    Some(match perspective {
+        Team::White => Sq::new(SIZE - 1 - x, y),
+        Team::Black => Sq::new(x, SIZE - 1 - y),
+    })
+}
+
+pub fn ctrl(key: &KeyEvent) -> bool {
+    key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// Single keys for the common commands.
+fn shortcut(ch: char) -> Option<Command> {
+    match ch {
+        'u' => Some(Command::Undo),
+        'r' => Some(Command::Redo),
+        'f' => Some(Command::Flip),
+        'q' => Some(Command::Quit),
+        '?' => Some(Command::Help),
+        _ => None,
+    }
+}
+
+/// A person at the full screen, moving pieces with the cursor or the mouse.
+pub struct TuiPlayer {
+    tui: Rc<RefCell<Tui>>,
+}
+
+impl TuiPlayer {
+    pub fn new(tui: Rc<RefCell<Tui>>) -> Self {
+        TuiPlayer { tui }
+    }
+}
+
+impl Playable for TuiPlayer {
+    fn action(&mut self, context: &Context) -> io::Result<Action> {
+        let board = context.board;
+        let mut tui = self.tui.borrow_mut();
+        let prompt = format!("{} to move", board.turn_order);
+        loop {
+            tui.draw(&prompt)?;
+            let perspective = tui.perspective();
+            let sq = match event::read()? {
+                Event::Mouse(mouse) if mouse.kind == MouseEventKind::Down(MouseButton::Left) => {
+                    match square_at(mouse.column, mouse.row, perspective) {
+                        Some(sq) => sq,
+                        None => continue,
+                    }
+                }
+                Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Up => {
+                        tui.picker.step(1, 0, perspective);
+                        continue;
+                    }
+                    KeyCode::Down => {
+                        tui.picker.step(-1, 0, perspective);
+                        continue;
+                    }
+                    KeyCode::Left => {
+                        tui.picker.step(0, -1, perspective);
+                        continue;
+                    }
+                    KeyCode::Right => {
+                        tui.picker.step(0, 1, perspective);
+                        continue;
+                    }
+                    KeyCode::Esc => {
+                        tui.picker.selected = None;
+                        continue;
+                    }
+                    KeyCode::Enter | KeyCode::Char(' ') => tui.picker.cursor,
+                    KeyCode::Char('c') if ctrl(&key) => return Ok(Action::Command(Command::Quit)),
+                    KeyCode::Char(':') => {
+                        let line = tui.read_line(":")?;
+                        if line.is_empty() {
+                            continue;
+                        }
+                        if let Some(command) = Command::parse(&line) {
+                            return command.map(Action::Command);
+                        }
+                        return board.find_move(&line).map(Action::Move);
+                    }
+                    KeyCode::Char(ch) => match shortcut(ch) {
+                        Some(command) => return Ok(Action::Command(command)),
+                        None => continue,
+                    },
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            tui.picker.cursor = sq;
+            let moves = tui.picker.pick(board, sq);
+            let mov = match moves.as_slice() {
+                [] => continue,
+                [mov] => mov.clone(),
+                _ => match promotion(&mut tui, &moves)? {
+                    Some(mov) => mov,
+                    None => continue,
+                },
+            };
+            return Ok(Action::Move(mov));
+        }
+    }
+}
+
+/// Asks which piece a Pawn promotes to, or None when the move is called off with Escape.
+fn promotion(tui: &mut Tui, moves: &[Move]) -> io::Result<Option<Move>> {
+    loop {
+        tui.draw("Promote to? q, r, b or n")?;
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        let piece = match key.code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char(ch) => Piece::from_char(ch.to_ascii_uppercase()),
+            _ => None,
+        };
+        if let Some(mov) = moves
+            .iter()
+            .find(|mov| piece.is_some() && mov.promotion == piece)
+        {
+            return Ok(Some(mov.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn test_pick_and_drop() {
+        let board = Board::new();
+        let mut picker = Picker::default();
+        let e2 = Sq::notation("e2").unwrap();
+        // Empty squares and the other team's pieces can't be picked up.
+        assert!(picker.pick(&board, Sq::notation("e4").unwrap()).is_empty());
+        assert!(picker.pick(&board, Sq::notation("e7").unwrap()).is_empty());
+        assert_eq!(picker.selected, None);
+
+        assert!(picker.pick(&board, e2).is_empty());
+        assert_eq!(picker.selected, Some(e2));
+        // An illegal drop keeps the piece in hand, another own piece is picked up instead.
+        assert!(picker.pick(&board, Sq::notation("e5").unwrap()).is_empty());
+        assert_eq!(picker.selected, Some(e2));
+        let g1 = Sq::notation("g1").unwrap();
+        picker.pick(&board, g1);
+        assert_eq!(picker.selected, Some(g1));
+
+        let moves = picker.pick(&board, Sq::notation("f3").unwrap());
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].label.as_deref(), Some("Nf3"));
+        assert_eq!(picker.selected, None);
+    }
+
+    #[test]
+    fn test_pick_chess960_castle() {
+        let board: Board = "4k3/8/8/8/8/8/8/1R4KR w HB - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        let mut picker = Picker::default();
+        picker.pick(&board, Sq::notation("g1").unwrap());
+        let moves = picker.pick(&board, Sq::notation("h1").unwrap());
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].label.as_deref(), Some("O-O"));
+        assert_eq!(picker.selected, None);
+    }
+
+    #[test]
+    fn test_pick_promotion() {
+        let board: Board = "k7/4P3/8/8/8/8/8/7K w - - 0 1"
+            .to_string()
+            .try_into()
+            .unwrap();
+        let mut picker = Picker::default();
+        picker.pick(&board, Sq::notation("e7").unwrap());
+        let moves = picker.pick(&board, Sq::notation("e8").unwrap());
+        assert_eq!(moves.len(), 4);
+    }
+
+    #[test]
+    fn test_step() {
+        let mut picker = Picker::default();
+        picker.step(1, 1, Team::White);
+        assert_eq!(picker.cursor, Sq::notation("f3").unwrap());
+        picker.step(1, 1, Team::Black);
+        assert_eq!(picker.cursor, Sq::notation("e2").unwrap());
+        for _ in 0..10 {
+            picker.step(0, -1, Team::White);
+        }
+        assert_eq!(picker.cursor, Sq::notation("a2").unwrap());
+    }
+
+    #[test]
+    fn test_square_at() {
+        assert_eq!(square_at(3, 1, Team::White), Sq::notation("a8").ok());
+        assert_eq!(square_at(26, 8, Team::White), Sq::notation("h1").ok());
+        assert_eq!(square_at(3, 1, Team::Black), Sq::notation("h1").ok());
+        assert_eq!(square_at(2, 1, Team::White), None);
+        assert_eq!(square_at(27, 1, Team::White), None);
+        assert_eq!(square_at(5, 9, Team::White), None);
+    }
+}
@@ -0,0 +1,31 @@
+#!/bin/sh
+# A stand-in UCI engine for the tests. It knows three moves of the Ruy Lopez as White and has
+# no move anywhere else, except after 1. a3, where it never answers. Everything it is told is
+# appended to the file named by UCI_LOG.
+move=0000
+while read -r line; do
+    if [ -n "$UCI_LOG" ]; then
+        echo "$line" >> "$UCI_LOG"
+    fi
+    case "$line" in
+        uci)
+            echo "id name Stand-in"
+            echo "id author chess tests"
+            echo "option name Hash type spin default 16 min 1 max 1024"
+            echo "uciok"
+            ;;
+        isready) echo "readyok" ;;
+        "position startpos") move=e2e4 ;;
+        "position startpos moves e2e4 e7e5") move=g1f3 ;;
+        "position startpos moves e2e4 e7e5 g1f3 b8c6") move=f1b5 ;;
+        "position startpos moves a2a3") move=none ;;
+        position*) move=0000 ;;
+        go*)
+            if [ "$move" != none ]; then
+                echo "info depth 1 score cp 30 nodes 1 pv $move"
+                echo "bestmove $move"
+            fi
+            ;;
+        quit) exit 0 ;;
+    esac
+done